@@ -0,0 +1,112 @@
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    env,
+    io::{self, BufRead},
+    path::Path,
+    process,
+};
+
+use serde::Serialize;
+use tantivy::{Index, Result};
+
+use cantine::{
+    database::DatabaseReader,
+    index::RecipeIndex,
+    model::{Recipe, RecipeId},
+};
+
+/// What was checked and what went wrong, printed as json
+#[derive(Serialize, Default, Debug)]
+struct Summary {
+    /// Recipes read from STDIN
+    checked: usize,
+    /// Recipes from STDIN that are not in the database
+    not_found: usize,
+    /// Recipes from STDIN that differ from the stored ones
+    mismatched: usize,
+    /// Database records without a document in the index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    missing_from_index: Option<usize>,
+    /// Documents in the index without a database record
+    #[serde(skip_serializing_if = "Option::is_none")]
+    missing_from_database: Option<usize>,
+}
+
+impl Summary {
+    fn is_ok(&self) -> bool {
+        self.not_found == 0
+            && self.mismatched == 0
+            && self.missing_from_index.unwrap_or(0) == 0
+            && self.missing_from_database.unwrap_or(0) == 0
+    }
+}
+
+const CHECK_INDEX: &str = "CHECK_INDEX";
+
+/// Verifies that the recipes given as json via STDIN are in the
+/// database. With `CHECK_INDEX=1` it also verifies that the index
+/// and the database know about the exact same recipes
+///
+/// Prints a json summary and exits with a non-zero status if
+/// anything is out of place.
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let base_dir = env::args()
+        .nth(1)
+        .expect("First parameter must be the output directory of load");
+
+    let check_index = matches!(env::var(CHECK_INDEX).as_deref(), Ok("1") | Ok("true"));
+
+    let base_path = Path::new(&base_dir);
+    let database = DatabaseReader::<Recipe>::open(base_path.join("database"))?;
+
+    let mut summary = Summary::default();
+
+    for line in io::stdin().lock().lines() {
+        let recipe: Recipe = serde_json::from_str(line?.as_ref()).expect("valid recipe json");
+        summary.checked += 1;
+
+        match database.find_by_id(recipe.recipe_id) {
+            None => {
+                log::warn!("Recipe {} not found in the database", recipe.recipe_id);
+                summary.not_found += 1;
+            }
+            Some(found) => {
+                if found? != recipe {
+                    log::warn!("Recipe {} differs from the stored one", recipe.recipe_id);
+                    summary.mismatched += 1;
+                }
+            }
+        }
+    }
+
+    if check_index {
+        let index = Index::open_in_dir(base_path.join("tantivy"))?;
+        let recipe_index = RecipeIndex::try_from(&index.schema())?;
+
+        let indexed = recipe_index.indexed_ids(&index.reader()?.searcher())?;
+        let stored = database.ids().copied().collect::<HashSet<RecipeId>>();
+
+        let missing_from_index = stored.difference(&indexed).collect::<Vec<_>>();
+        let missing_from_database = indexed.difference(&stored).collect::<Vec<_>>();
+
+        log::debug!("Not in the index: {:?}", missing_from_index);
+        log::debug!("Not in the database: {:?}", missing_from_database);
+
+        summary.missing_from_index = Some(missing_from_index.len());
+        summary.missing_from_database = Some(missing_from_database.len());
+    }
+
+    println!(
+        "{}",
+        serde_json::to_string(&summary).expect("summary is serializable")
+    );
+
+    if !summary.is_ok() {
+        process::exit(1);
+    }
+
+    Ok(())
+}
@@ -11,7 +11,7 @@ use tantivy::{schema::Term, Index, Result};
 use cantine::{
     database::DatabaseReader,
     index::RecipeIndex,
-    model::{Recipe, RecipeId, Sort},
+    model::{FeaturesSort, Recipe, RecipeId, Sort},
 };
 use tique::topterms::TopTerms;
 
@@ -108,8 +108,13 @@ fn main() -> Result<()> {
                 let src_sim_len = canon_sim_ids.len() as f32;
 
                 let recallfn = |query| -> Result<Res> {
-                    let (_num_matching, similar_ids, _after) =
-                        recipe_index.search(&searcher, &query, 11, Sort::Relevance, None)?;
+                    let (_num_matching, similar_ids, _after) = recipe_index.search(
+                        &searcher,
+                        &query,
+                        11,
+                        Sort::By(FeaturesSort::Relevance),
+                        None,
+                    )?;
 
                     let position = similar_ids.iter().position(|&sim_id| recipe_id == sim_id);
 
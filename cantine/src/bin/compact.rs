@@ -0,0 +1,28 @@
+use std::{env, io::Result, time::Instant};
+
+use cantine::{database::compact, model::Recipe};
+
+/// Rewrites a recipe database without the space taken by deleted records
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let src = env::args()
+        .nth(1)
+        .expect("First parameter must be the database directory");
+
+    let dst = env::args()
+        .nth(2)
+        .expect("Second parameter must be a non-existing output directory");
+
+    let cur = Instant::now();
+    let num_recipes = compact::<Recipe, _, _>(&src, &dst)?;
+
+    log::info!(
+        "Compacted {} recipes into {} in {} seconds",
+        num_recipes,
+        dst,
+        cur.elapsed().as_secs()
+    );
+
+    Ok(())
+}
@@ -1,32 +1,67 @@
 use std::{
+    collections::HashSet,
     env,
     io::{self, BufRead},
     path::Path,
     str::FromStr,
-    sync::{mpsc::channel, Arc, RwLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::channel,
+        Arc, RwLock,
+    },
     thread::spawn,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use crossbeam_channel::unbounded;
 
 use tantivy::{self, directory::MmapDirectory, schema::SchemaBuilder, Index, Result};
 
-use cantine::database::DatabaseWriter;
-use cantine::index::RecipeIndex;
-use cantine::model::Recipe;
+use cantine::database::{DatabaseReader, DatabaseWriter};
+use cantine::index::{RecipeIndex, TextAnalysis};
+use cantine::model::{Recipe, RecipeId};
 
 /// Loads recipes as json into cantine's database and index
 #[derive(Debug)]
 pub struct LoadOptions {
-    /// Size for tantivy's writer buffer in MBs
+    /// Size for tantivy's writer buffer in MBs. It's split evenly
+    /// between the indexing threads, each needing at least 3MB
     buffer_size: usize,
+    /// Number of indexing threads. Tantivy picks one per core (up
+    /// to 8) when not set
+    num_threads: Option<usize>,
     /// How many recipes to ingest before comitting
     commit_every: usize,
     /// Number of worker threads to start
     num_producers: usize,
-    /// Path to a non-existing directory
+    /// Path to a non-existing directory, unless resuming
     output_dir: String,
+    /// How to tokenize the recipe's full text fields
+    text_analysis: TextAnalysis,
+    /// How often to log how far the load has gone
+    progress_interval: Duration,
+    /// Continue a load that stopped midway, skipping the recipes
+    /// that are in the database already
+    resume: bool,
+}
+
+/// What a previous, interrupted, load left behind
+struct Existing {
+    database: DatabaseReader<Recipe>,
+    indexed: HashSet<RecipeId>,
+}
+
+impl Existing {
+    /// Recipes in the database already were fully loaded before
+    fn should_load(&self, recipe: &Recipe) -> bool {
+        !self.database.contains_id(recipe.recipe_id)
+    }
+
+    /// The index may have been committed while the database write
+    /// was still buffered, so some recipes only lack the latter
+    fn should_index(&self, recipe: &Recipe) -> bool {
+        !self.indexed.contains(&recipe.recipe_id)
+    }
 }
 
 fn load(options: LoadOptions) -> Result<()> {
@@ -36,14 +71,25 @@ fn load(options: LoadOptions) -> Result<()> {
     let db_path = base_path.join("database");
     let index_path = base_path.join("tantivy");
 
-    std::fs::create_dir_all(&db_path)?;
-    std::fs::create_dir(&index_path)?;
+    if options.resume {
+        if !db_path.is_dir() || !index_path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Can only resume loading into an existing output directory",
+            )
+            .into());
+        }
+    } else {
+        std::fs::create_dir_all(&db_path)?;
+        std::fs::create_dir(&index_path)?;
+    }
 
     let mut builder = SchemaBuilder::new();
 
-    let fields = RecipeIndex::from(&mut builder);
+    let fields = RecipeIndex::with_analysis(&mut builder, options.text_analysis);
 
     let index = Index::open_or_create(MmapDirectory::open(&index_path)?, builder.build())?;
+    RecipeIndex::register_tokenizers(&index);
 
     // A SpMc channel to paralellize decode and index preparation
     let (line_sender, line_receiver) = unbounded::<String>();
@@ -51,7 +97,48 @@ fn load(options: LoadOptions) -> Result<()> {
     let (recipe_sender, recipe_receiver) = channel();
 
     let buffer_size = options.buffer_size;
-    let writer = Arc::new(RwLock::new(index.writer(buffer_size * 1_000_000)?));
+    let writer = match options.num_threads {
+        Some(num_threads) => index.writer_with_num_threads(num_threads, buffer_size * 1_000_000)?,
+        None => index.writer(buffer_size * 1_000_000)?,
+    };
+    let writer = Arc::new(RwLock::new(writer));
+
+    let existing = if options.resume {
+        let existing = Existing {
+            database: DatabaseReader::open(&db_path)?,
+            indexed: fields.indexed_ids(&index.reader()?.searcher())?,
+        };
+
+        // The database is written to before the index gets committed,
+        // so an interrupted load may leave recipes out of the index
+        let mut num_reindexed = 0;
+        for id in existing.database.ids() {
+            if !existing.indexed.contains(id) {
+                let recipe = existing.database.find_by_id(*id).expect("id exists")?;
+                writer
+                    .read()
+                    .unwrap()
+                    .add_document(fields.make_document(&recipe));
+                num_reindexed += 1;
+            }
+        }
+
+        if num_reindexed > 0 {
+            writer.write()?.commit()?;
+        }
+
+        log::info!(
+            "Resuming with {} recipes in the database. Reindexed {}",
+            existing.database.len(),
+            num_reindexed
+        );
+
+        Some(Arc::new(existing))
+    } else {
+        None
+    };
+
+    let num_skipped = Arc::new(AtomicUsize::new(0));
 
     let num_producers = options.num_producers;
     let mut workers = Vec::with_capacity(num_producers);
@@ -59,6 +146,8 @@ fn load(options: LoadOptions) -> Result<()> {
         let receiver = line_receiver.clone();
         let writer = writer.clone();
         let recipe_sender = recipe_sender.clone();
+        let existing = existing.clone();
+        let num_skipped = num_skipped.clone();
 
         let fields = fields.clone();
         workers.push(spawn(move || {
@@ -66,27 +155,55 @@ fn load(options: LoadOptions) -> Result<()> {
                 let recipe: Recipe =
                     serde_json::from_str(line.as_ref()).expect("valid recipe json");
 
-                writer
-                    .read()
-                    .unwrap()
-                    .add_document(fields.make_document(&recipe));
+                let should_index = match &existing {
+                    Some(existing) if !existing.should_load(&recipe) => {
+                        num_skipped.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                    Some(existing) => existing.should_index(&recipe),
+                    None => true,
+                };
+
+                if should_index {
+                    writer
+                        .read()
+                        .unwrap()
+                        .add_document(fields.make_document(&recipe));
+                }
 
                 recipe_sender.send(recipe).expect("send always works");
             }
         }))
     }
 
+    let commit_every = options.commit_every;
+    let progress_interval = options.progress_interval;
     let disk_writer = spawn(move || -> Result<()> {
         let mut db = DatabaseWriter::new(db_path)?;
 
         let cur = Instant::now();
         let mut num_recipes = 0;
 
+        let mut last_progress = Instant::now();
+        let mut last_num_recipes = 0;
+
         for recipe in recipe_receiver {
             num_recipes += 1;
             db.append(&recipe)?;
 
-            if num_recipes % options.commit_every == 0 {
+            if last_progress.elapsed() >= progress_interval {
+                log::info!(
+                    "DiskWriter: {} documents written, {} skipped ({:.0} docs/sec)",
+                    num_recipes,
+                    num_skipped.load(Ordering::Relaxed),
+                    (num_recipes - last_num_recipes) as f64 / last_progress.elapsed().as_secs_f64()
+                );
+
+                last_progress = Instant::now();
+                last_num_recipes = num_recipes;
+            }
+
+            if num_recipes % commit_every == 0 {
                 writer.write()?.commit()?;
 
                 log::info!(
@@ -100,9 +217,10 @@ fn load(options: LoadOptions) -> Result<()> {
         writer.write()?.commit()?;
 
         log::info!(
-            "DiskWriter: Wrote {} documents in {} seconds",
+            "DiskWriter: Wrote {} documents in {} seconds, skipped {}",
             num_recipes,
-            cur.elapsed().as_secs()
+            cur.elapsed().as_secs(),
+            num_skipped.load(Ordering::Relaxed)
         );
 
         Ok(())
@@ -130,6 +248,10 @@ fn load(options: LoadOptions) -> Result<()> {
 const BUFFER_SIZE: &str = "BUFFER_SIZE";
 const COMMIT_EVERY: &str = "COMMIT_EVERY";
 const NUM_PRODUCERS: &str = "NUM_PRODUCERS";
+const NUM_THREADS: &str = "NUM_THREADS";
+const PROGRESS_SECONDS: &str = "PROGRESS_SECONDS";
+const RESUME: &str = "RESUME";
+const TEXT_ANALYSIS: &str = "TEXT_ANALYSIS";
 
 fn get_usize_from_env_or(key: &str, default: usize) -> usize {
     env::var(key)
@@ -151,11 +273,31 @@ fn main() -> Result<()> {
 
     let num_producers = get_usize_from_env_or(NUM_PRODUCERS, 4);
 
+    let num_threads = env::var(NUM_THREADS).ok().map(|v| {
+        usize::from_str(&v)
+            .ok()
+            .filter(|threads| *threads > 0)
+            .expect("valid non-zero usize")
+    });
+
+    let text_analysis = env::var(TEXT_ANALYSIS)
+        .ok()
+        .map(|v| TextAnalysis::from_str(&v).expect("valid text analysis"))
+        .unwrap_or(TextAnalysis::Default);
+
+    let progress_interval = Duration::from_secs(get_usize_from_env_or(PROGRESS_SECONDS, 30) as u64);
+
+    let resume = matches!(env::var(RESUME).as_deref(), Ok("1") | Ok("true"));
+
     let options = LoadOptions {
         buffer_size,
+        num_threads,
         commit_every,
         num_producers,
         output_dir,
+        text_analysis,
+        progress_interval,
+        resume,
     };
 
     load(options)
@@ -0,0 +1,215 @@
+use std::{
+    io::{self, Result},
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use super::readerwriter::{DatabaseReader, DatabaseRecord, DatabaseWriter};
+
+/// Writes records compressed with zstd
+///
+/// Every record gets compressed on its own, so recipes with long
+/// ingredient lists and instructions take a lot less space on disk.
+/// The price is paid in CPU: each lookup has to decompress the
+/// record before decoding it, so prefer the plain `DatabaseWriter`
+/// when the data is small or lookups are very hot.
+///
+/// Databases written by this can only be read via a
+/// `CompressedDatabaseReader`.
+pub struct CompressedDatabaseWriter<T> {
+    writer: DatabaseWriter<T>,
+    level: i32,
+}
+
+impl<T> CompressedDatabaseWriter<T>
+where
+    T: DatabaseRecord + Serialize,
+{
+    /// Creates a writer that compresses with the given zstd level
+    ///
+    /// Zero means zstd's default level.
+    pub fn new<P: AsRef<Path>>(base_dir: P, level: i32) -> Result<Self> {
+        Ok(Self {
+            writer: DatabaseWriter::new(base_dir)?,
+            level,
+        })
+    }
+
+    pub fn append(&mut self, item: &T) -> Result<()> {
+        let encoded = bincode::serialize(item)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Failure encoding input"))?;
+        let compressed = zstd::stream::encode_all(encoded.as_slice(), self.level)?;
+        self.writer.append_encoded(item, &compressed)
+    }
+}
+
+/// Reads databases written by `CompressedDatabaseWriter`
+pub struct CompressedDatabaseReader<T> {
+    reader: DatabaseReader<T>,
+}
+
+impl<T: DeserializeOwned> CompressedDatabaseReader<T> {
+    pub fn open<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        Ok(Self {
+            reader: DatabaseReader::open(base_dir)?,
+        })
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &u64> {
+        self.reader.ids()
+    }
+
+    pub fn len(&self) -> usize {
+        self.reader.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reader.is_empty()
+    }
+
+    /// Size of the (compressed) data file in bytes
+    pub fn data_size(&self) -> usize {
+        self.reader.data_size()
+    }
+
+    /// Iterates over every record, in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = Result<T>> + '_ {
+        self.reader
+            .live_offsets()
+            .into_iter()
+            .map(move |offset| self.decode_at(offset))
+    }
+
+    pub fn find_by_id(&self, id: u64) -> Option<Result<T>> {
+        self.reader
+            .offset_of(id)
+            .map(|offset| self.decode_at(offset))
+    }
+
+    /// See `DatabaseReader::contains_id`
+    pub fn contains_id(&self, id: u64) -> bool {
+        self.reader.contains_id(id)
+    }
+
+    pub fn find_by_uuid(&self, uuid: &Uuid) -> Option<Result<T>> {
+        self.reader
+            .id_for_uuid(uuid)
+            .and_then(|id| self.find_by_id(*id))
+    }
+
+    pub fn id_for_uuid(&self, uuid: &Uuid) -> Option<&u64> {
+        self.reader.id_for_uuid(uuid)
+    }
+
+    /// See `DatabaseReader::delete_by_id`
+    pub fn delete_by_id(&mut self, id: u64) -> Result<bool> {
+        self.reader.delete_by_id(id)
+    }
+
+    /// See `DatabaseReader::delete_by_uuid`
+    pub fn delete_by_uuid(&mut self, uuid: &Uuid) -> Result<bool> {
+        self.reader.delete_by_uuid(uuid)
+    }
+
+    fn decode_at(&self, offset: usize) -> Result<T> {
+        // Records are laid out back to back, so the decoder must
+        // stop at the end of the first frame
+        let decoder =
+            zstd::stream::read::Decoder::with_buffer(self.reader.data_at(offset))?.single_frame();
+
+        bincode::deserialize_from(decoder)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failure decoding at offset"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile;
+
+    use crate::database::compact;
+
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Text(u64, Uuid, String);
+
+    impl DatabaseRecord for Text {
+        fn get_id(&self) -> u64 {
+            self.0
+        }
+
+        fn get_uuid(&self) -> uuid::Bytes {
+            *self.1.as_bytes()
+        }
+    }
+
+    #[test]
+    fn large_records_compress_and_roundtrip() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = (0..10)
+            .map(|id| {
+                Text(
+                    id,
+                    Uuid::new_v4(),
+                    "mix the flour and the eggs. ".repeat(100),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        {
+            let mut db_writer = CompressedDatabaseWriter::new(basedir.path(), 0)?;
+            for entry in entries.iter() {
+                db_writer.append(entry)?;
+            }
+        }
+
+        let uncompressed_size = entries
+            .iter()
+            .map(|entry| bincode::serialized_size(entry).unwrap())
+            .sum::<u64>();
+        let db_reader = CompressedDatabaseReader::<Text>::open(basedir.path())?;
+
+        assert_eq!(10, db_reader.len());
+        assert!(db_reader.data_size() as u64 * 10 < uncompressed_size);
+
+        for entry in entries.iter() {
+            let found = db_reader.find_by_id(entry.0).transpose()?;
+            assert_eq!(Some(entry), found.as_ref());
+
+            let found = db_reader.find_by_uuid(&entry.1).transpose()?;
+            assert_eq!(
+                bincode::serialize(entry).unwrap(),
+                bincode::serialize(&found.unwrap()).unwrap()
+            );
+        }
+
+        assert_eq!(entries, db_reader.iter().collect::<Result<Vec<_>>>()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_databases_cant_be_compacted() -> Result<()> {
+        let srcdir = tempfile::tempdir()?;
+        let dstdir = tempfile::tempdir()?;
+
+        {
+            let mut db_writer = CompressedDatabaseWriter::new(srcdir.path(), 0)?;
+            db_writer.append(&Text(0, Uuid::new_v4(), "some text".to_string()))?;
+        }
+
+        let compacted_path = dstdir.path().join("compacted");
+        let err = compact::<Text, _, _>(srcdir.path(), &compacted_path)
+            .err()
+            .expect("compressed records are detected");
+
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert!(!compacted_path.exists());
+
+        Ok(())
+    }
+}
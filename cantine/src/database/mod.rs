@@ -1,4 +1,8 @@
+#[cfg(feature = "compression")]
+mod compressed;
 mod readerwriter;
 mod structuredlog;
 
-pub use readerwriter::{DatabaseReader, DatabaseRecord, DatabaseWriter};
+#[cfg(feature = "compression")]
+pub use compressed::{CompressedDatabaseReader, CompressedDatabaseWriter};
+pub use readerwriter::{compact, DatabaseReader, DatabaseRecord, DatabaseWriter, KeyedRecord};
@@ -8,7 +8,10 @@ use std::{
 
 use byteorder::NativeEndian;
 use memmap::Mmap;
-use serde::{de::Deserialize, Serialize};
+use serde::{
+    de::{Deserialize, DeserializeOwned},
+    Serialize,
+};
 use uuid::{self, Uuid};
 use zerocopy::{AsBytes, FromBytes, U64};
 
@@ -19,9 +22,20 @@ pub trait DatabaseRecord {
     fn get_uuid(&self) -> uuid::Bytes;
 }
 
+/// A record that can also be looked up by other keys
+///
+/// See `DatabaseReader::open_keyed`.
+pub trait KeyedRecord: DatabaseRecord {
+    /// Yields every `(name, value)` key pair for the record
+    fn get_keys(&self) -> Vec<(&'static str, String)>;
+}
+
 pub struct DatabaseReader<T> {
     uuid_index: HashMap<Uuid, u64>,
     id_index: HashMap<u64, usize>,
+    key_index: HashMap<&'static str, HashMap<String, usize>>,
+    log: StructuredLog<LogEntry>,
+    endianness: Endianness,
     data: Mmap,
     _marker: PhantomData<T>,
 }
@@ -31,14 +45,28 @@ impl<'a, T: Deserialize<'a>> DatabaseReader<T> {
         let log = StructuredLog::new(base_dir.as_ref().join(OFFSETS_FILE))?;
         let num_items = log.len()?;
 
+        let endianness = detect_endianness(&log)?.unwrap_or(Endianness::Native);
+        let mut skip_header = endianness == Endianness::Little;
+
         let mut id_index = HashMap::with_capacity(num_items);
         let mut uuid_index = HashMap::with_capacity(num_items);
 
         log.for_each_entry(|entry: &LogEntry| {
-            let offset = entry.offset.get() as usize;
-            let id = entry.id.get();
-            id_index.insert(id, offset);
-            uuid_index.insert(Uuid::from_bytes(entry.uuid), id);
+            if skip_header {
+                skip_header = false;
+                return;
+            }
+
+            let id = entry.id(endianness);
+            let uuid = Uuid::from_bytes(entry.uuid);
+
+            if entry.is_tombstone(endianness) {
+                id_index.remove(&id);
+                uuid_index.remove(&uuid);
+            } else {
+                id_index.insert(id, entry.offset(endianness) as usize);
+                uuid_index.insert(uuid, id);
+            }
         })?;
 
         let datafile = OpenOptions::new()
@@ -46,10 +74,28 @@ impl<'a, T: Deserialize<'a>> DatabaseReader<T> {
             .write(true)
             .open(base_dir.as_ref().join(DATA_FILE))?;
 
+        let data = unsafe { Mmap::map(&datafile)? };
+
+        if let Some(max_offset) = id_index.values().max() {
+            if *max_offset >= data.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Offset {} is beyond the end of the data file ({} bytes)",
+                        max_offset,
+                        data.len()
+                    ),
+                ));
+            }
+        }
+
         Ok(Self {
             id_index,
             uuid_index,
-            data: unsafe { Mmap::map(&datafile)? },
+            key_index: HashMap::new(),
+            log,
+            endianness,
+            data,
             _marker: PhantomData,
         })
     }
@@ -58,12 +104,57 @@ impl<'a, T: Deserialize<'a>> DatabaseReader<T> {
         self.id_index.keys()
     }
 
+    /// Number of records in the database, not counting deleted ones
+    pub fn len(&self) -> usize {
+        self.id_index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id_index.is_empty()
+    }
+
+    /// Size of the data file in bytes, including deleted records
+    pub fn data_size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Iterates over every record, in insertion order
+    pub fn iter(&'a self) -> impl Iterator<Item = Result<T>> + 'a {
+        self.live_offsets()
+            .into_iter()
+            .map(move |offset| self.decode_at(offset))
+    }
+
     pub fn find_by_id(&'a self, id: u64) -> Option<Result<T>> {
-        self.id_index.get(&id).map(|offset| {
-            bincode::deserialize(&self.data[*offset..]).map_err(|_| {
-                io::Error::new(io::ErrorKind::InvalidData, "Failure decoding at offset")
-            })
-        })
+        self.offset_of(id).map(|offset| self.decode_at(offset))
+    }
+
+    /// Tells whether there's a record with the given id, without
+    /// decoding it
+    pub fn contains_id(&self, id: u64) -> bool {
+        self.id_index.contains_key(&id)
+    }
+
+    /// Finds the records for every given id, yielding them in the
+    /// same order as the ids (`None` for unknown ones)
+    ///
+    /// Records are decoded in the order they appear in the data file
+    /// rather than the order they were asked for, which plays nicer
+    /// with the page cache when fetching many of them at once
+    pub fn find_many(&'a self, ids: &[u64]) -> Result<Vec<Option<T>>> {
+        let mut offsets = ids
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, id)| self.offset_of(*id).map(|offset| (offset, pos)))
+            .collect::<Vec<_>>();
+        offsets.sort_unstable();
+
+        let mut found = ids.iter().map(|_| None).collect::<Vec<_>>();
+        for (offset, pos) in offsets {
+            found[pos] = Some(self.decode_at(offset)?);
+        }
+
+        Ok(found)
     }
 
     pub fn find_by_uuid(&'a self, uuid: &Uuid) -> Option<Result<T>> {
@@ -75,11 +166,150 @@ impl<'a, T: Deserialize<'a>> DatabaseReader<T> {
     pub fn id_for_uuid(&self, uuid: &Uuid) -> Option<&u64> {
         self.uuid_index.get(uuid)
     }
+
+    /// Finds a record by one of the keys exposed via `KeyedRecord`
+    ///
+    /// Always yields `None` unless the database was opened via
+    /// `open_keyed`.
+    pub fn find_by_key(&'a self, name: &str, value: &str) -> Option<Result<T>> {
+        self.key_index
+            .get(name)
+            .and_then(|keys| keys.get(value))
+            .map(|offset| self.decode_at(*offset))
+    }
+
+    /// Deletes the record with the given id, if it exists
+    ///
+    /// Deleting only appends a tombstone to the offsets log: the
+    /// record data is left in place, so no space is reclaimed until
+    /// the database gets compacted.
+    ///
+    /// Returns whether a record was deleted.
+    pub fn delete_by_id(&mut self, id: u64) -> Result<bool> {
+        if !self.id_index.contains_key(&id) {
+            return Ok(false);
+        }
+
+        let uuid = self
+            .uuid_index
+            .iter()
+            .find(|(_, &uuid_id)| uuid_id == id)
+            .map(|(uuid, _)| *uuid)
+            .unwrap_or_else(Uuid::nil);
+
+        self.delete(id, uuid)?;
+        Ok(true)
+    }
+
+    /// Deletes the record with the given uuid, if it exists
+    ///
+    /// See `delete_by_id`.
+    pub fn delete_by_uuid(&mut self, uuid: &Uuid) -> Result<bool> {
+        if let Some(&id) = self.uuid_index.get(uuid) {
+            self.delete(id, *uuid)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub(crate) fn live_offsets(&self) -> Vec<usize> {
+        // Records are only ever appended, so sorting by offset
+        // yields them in the order they were added
+        let mut offsets = self.id_index.values().copied().collect::<Vec<_>>();
+        offsets.sort_unstable();
+        offsets
+    }
+
+    pub(crate) fn offset_of(&self, id: u64) -> Option<usize> {
+        self.id_index.get(&id).copied()
+    }
+
+    pub(crate) fn data_at(&self, offset: usize) -> &[u8] {
+        &self.data[offset..]
+    }
+
+    fn decode_at(&'a self, offset: usize) -> Result<T> {
+        bincode::deserialize(self.data_at(offset))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failure decoding at offset"))
+    }
+
+    fn delete(&mut self, id: u64, uuid: Uuid) -> Result<()> {
+        self.log
+            .append(&LogEntry::tombstone(id, *uuid.as_bytes(), self.endianness))?;
+        self.uuid_index.remove(&uuid);
+
+        if let Some(offset) = self.id_index.remove(&id) {
+            for keys in self.key_index.values_mut() {
+                keys.retain(|_, key_offset| *key_offset != offset);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: DeserializeOwned> DatabaseReader<T> {
+    /// Like `open`, but also checks that every record can be decoded
+    ///
+    /// This reads the whole data file, so it's a lot slower than
+    /// a plain `open`. In exchange, a corrupted database (say, a
+    /// truncated data file after a crash) is detected right away
+    /// instead of when the broken records are looked up.
+    pub fn open_verified<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        let reader = Self::open(base_dir)?;
+
+        let mut records = reader
+            .id_index
+            .iter()
+            .map(|(id, offset)| (*offset, *id))
+            .collect::<Vec<_>>();
+        records.sort_unstable();
+
+        for (offset, id) in records {
+            if bincode::deserialize::<T>(reader.data_at(offset)).is_err() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Failure decoding record {} at offset {}", id, offset),
+                ));
+            }
+        }
+
+        Ok(reader)
+    }
+}
+
+impl<T: KeyedRecord + DeserializeOwned> DatabaseReader<T> {
+    /// Like `open`, but also indexes every key from `KeyedRecord`
+    ///
+    /// Keys aren't stored anywhere, so every record gets decoded
+    /// to build the index: this is a lot slower than `open`.
+    pub fn open_keyed<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        let mut reader = Self::open(base_dir)?;
+
+        let mut key_index = HashMap::new();
+        for offset in reader.live_offsets() {
+            let record: T = bincode::deserialize(reader.data_at(offset)).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "Failure decoding at offset")
+            })?;
+
+            for (name, value) in record.get_keys() {
+                key_index
+                    .entry(name)
+                    .or_insert_with(HashMap::new)
+                    .insert(value, offset);
+            }
+        }
+
+        reader.key_index = key_index;
+        Ok(reader)
+    }
 }
 
 pub struct DatabaseWriter<T> {
     log: StructuredLog<LogEntry>,
     writer: BufWriter<File>,
+    endianness: Endianness,
     _marker: PhantomData<T>,
 }
 
@@ -88,9 +318,55 @@ where
     T: DatabaseRecord + Serialize,
 {
     pub fn new<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        Self::with_endianness(base_dir, Endianness::Native)
+    }
+
+    /// Like `new`, but the database can be read on any platform
+    ///
+    /// The offsets log gets stored as little-endian regardless of
+    /// the platform's byte order, which costs a byte swap for every
+    /// entry read or written on big-endian machines. Record data is
+    /// encoded by bincode, which is little-endian already.
+    ///
+    /// Existing databases keep the byte order they were created with,
+    /// so this fails when appending to a database created via `new`
+    /// and `new` keeps appending to portable databases as portable.
+    pub fn new_portable<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        Self::with_endianness(base_dir, Endianness::Little)
+    }
+
+    fn with_endianness<P: AsRef<Path>>(base_dir: P, wanted: Endianness) -> Result<Self> {
+        let mut log = StructuredLog::new(base_dir.as_ref().join(OFFSETS_FILE))?;
+
+        let endianness = match detect_endianness(&log)? {
+            Some(Endianness::Native) if wanted == Endianness::Little => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Can't change the byte order of an existing database",
+                ));
+            }
+            Some(existing) => existing,
+            None => {
+                if wanted == Endianness::Little {
+                    log.append(&LogEntry::little_endian_header())?;
+                }
+                wanted
+            }
+        };
+
+        let datafile = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(base_dir.as_ref().join(DATA_FILE))?;
+
+        let mut writer = BufWriter::new(datafile);
+        // Appending mode doesn't move the cursor until the first write
+        writer.seek(SeekFrom::End(0))?;
+
         Ok(Self {
-            writer: BufWriter::new(File::create(base_dir.as_ref().join(DATA_FILE))?),
-            log: StructuredLog::new(base_dir.as_ref().join(OFFSETS_FILE))?,
+            writer,
+            log,
+            endianness,
             _marker: PhantomData,
         })
     }
@@ -98,18 +374,204 @@ where
     pub fn append(&mut self, item: &T) -> Result<()> {
         let encoded = bincode::serialize(item)
             .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Failure encoding input"))?;
-        let offset = self.writer.seek(SeekFrom::Current(0))?;
+        self.append_encoded(item, &encoded)
+    }
+
+    /// Appends every item in a single pass
+    ///
+    /// Nothing is written if any item fails to encode and the error
+    /// mentions the index of the first one that did.
+    pub fn append_batch(&mut self, items: &[T]) -> Result<()> {
+        let start = self.writer.seek(SeekFrom::Current(0))?;
+
+        let mut encoded = Vec::new();
+        let mut entries = Vec::with_capacity(items.len());
+
+        for (idx, item) in items.iter().enumerate() {
+            let offset = start + encoded.len() as u64;
+
+            bincode::serialize_into(&mut encoded, item).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Failure encoding input at index {}", idx),
+                )
+            })?;
+
+            entries.push(LogEntry::new(
+                item.get_id(),
+                item.get_uuid(),
+                offset,
+                self.endianness,
+            ));
+        }
+
         self.writer.write_all(&encoded)?;
+        self.writer.flush()?;
+        self.log.append_all(&entries)
+    }
 
-        let entry = LogEntry::new(item.get_id(), item.get_uuid(), offset);
+    pub(crate) fn append_encoded(&mut self, item: &T, encoded: &[u8]) -> Result<()> {
+        let offset = self.writer.seek(SeekFrom::Current(0))?;
+        self.writer.write_all(encoded)?;
+        // The log is written to right away, so the data must not
+        // linger in the buffer: if the process dies, every logged
+        // offset should still point at a complete record
+        self.writer.flush()?;
+
+        let entry = LogEntry::new(item.get_id(), item.get_uuid(), offset, self.endianness);
         self.log.append(&entry)?;
         Ok(())
     }
 }
 
+/// Copies every live record from the database at `src` into a fresh
+/// one at `dst`, leaving out the space taken by deleted records
+///
+/// The compacted database is checked against the original before
+/// returning and `dst` must not contain a database already. Nothing
+/// is left at `dst` if compaction fails.
+///
+/// Databases written by `CompressedDatabaseWriter` are rejected:
+/// their records would be copied as if they were plain bincode.
+///
+/// Returns the number of records copied.
+pub fn compact<T, P, Q>(src: P, dst: Q) -> Result<usize>
+where
+    T: DatabaseRecord + Serialize + DeserializeOwned,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let dst = dst.as_ref();
+
+    if dst.join(OFFSETS_FILE).exists() || dst.join(DATA_FILE).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "Destination already contains a database",
+        ));
+    }
+
+    let created_dst = !dst.exists();
+    std::fs::create_dir_all(dst)?;
+
+    let result = copy_live_records::<T>(src.as_ref(), dst);
+
+    // Best effort: the error that got us here is the one to report
+    if result.is_err() {
+        if created_dst {
+            std::fs::remove_dir_all(dst).ok();
+        } else {
+            std::fs::remove_file(dst.join(OFFSETS_FILE)).ok();
+            std::fs::remove_file(dst.join(DATA_FILE)).ok();
+        }
+    }
+
+    result
+}
+
+fn copy_live_records<T>(src: &Path, dst: &Path) -> Result<usize>
+where
+    T: DatabaseRecord + Serialize + DeserializeOwned,
+{
+    let source = DatabaseReader::<T>::open(src)?;
+
+    let is_compressed = source
+        .live_offsets()
+        .into_iter()
+        .any(|offset| source.data_at(offset).starts_with(&ZSTD_MAGIC));
+
+    if is_compressed {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Can't compact a database of compressed records",
+        ));
+    }
+
+    let mut num_records = 0;
+    {
+        // Flushes when dropped
+        let mut writer = DatabaseWriter::with_endianness(dst, source.endianness)?;
+        for record in source.iter() {
+            writer.append(&record?)?;
+            num_records += 1;
+        }
+    }
+
+    // There's nothing to check, and an empty data file can't be mapped
+    if num_records == 0 {
+        return Ok(0);
+    }
+
+    let compacted = DatabaseReader::<T>::open(dst)?;
+
+    let encode = |record: T| {
+        bincode::serialize(&record)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failure encoding record"))
+    };
+
+    for (uuid, id) in source.uuid_index.iter() {
+        let identical = match (source.find_by_id(*id), compacted.find_by_id(*id)) {
+            (Some(original), Some(copied)) => {
+                compacted.id_for_uuid(uuid) == Some(id) && encode(original?)? == encode(copied?)?
+            }
+            _ => false,
+        };
+
+        if !identical {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Compacted database differs for id {}", id),
+            ));
+        }
+    }
+
+    Ok(num_records)
+}
+
 const OFFSETS_FILE: &str = "offsets.bin";
 const DATA_FILE: &str = "data.bin";
 
+// Every record written by `CompressedDatabaseWriter` is a zstd
+// frame, which starts with this
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+// Entries with this offset mark their id/uuid as deleted
+const TOMBSTONE_OFFSET: u64 = std::u64::MAX;
+
+// Portable offset logs start with an entry whose uuid is this magic
+// followed by a version byte. Logs without it are in native order
+const HEADER_MAGIC: &[u8; 15] = b"cantine-offsets";
+const LITTLE_ENDIAN_VERSION: u8 = 1;
+
+// Byte order of the numbers in the offsets log
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Endianness {
+    Native,
+    Little,
+}
+
+impl Endianness {
+    // Converting to and from the on-disk order is the same operation
+    fn convert(self, value: u64) -> u64 {
+        match self {
+            Endianness::Native => value,
+            Endianness::Little => value.to_le(),
+        }
+    }
+}
+
+// None when the log is empty and its byte order is yet to be decided
+fn detect_endianness(log: &StructuredLog<LogEntry>) -> Result<Option<Endianness>> {
+    log.with_first_entry(|entry| match entry.header_version() {
+        None => Ok(Endianness::Native),
+        Some(LITTLE_ENDIAN_VERSION) => Ok(Endianness::Little),
+        Some(version) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unknown offsets log version {}", version),
+        )),
+    })?
+    .transpose()
+}
+
 #[derive(FromBytes, AsBytes)]
 #[repr(C)]
 struct LogEntry {
@@ -119,13 +581,45 @@ struct LogEntry {
 }
 
 impl LogEntry {
-    fn new(id: u64, uuid: uuid::Bytes, offset: u64) -> Self {
+    fn new(id: u64, uuid: uuid::Bytes, offset: u64, endianness: Endianness) -> Self {
         Self {
             uuid,
-            id: U64::new(id),
-            offset: U64::new(offset),
+            id: U64::new(endianness.convert(id)),
+            offset: U64::new(endianness.convert(offset)),
+        }
+    }
+
+    fn tombstone(id: u64, uuid: uuid::Bytes, endianness: Endianness) -> Self {
+        Self::new(id, uuid, TOMBSTONE_OFFSET, endianness)
+    }
+
+    fn little_endian_header() -> Self {
+        let mut uuid = uuid::Bytes::default();
+        uuid[..HEADER_MAGIC.len()].copy_from_slice(HEADER_MAGIC);
+        uuid[HEADER_MAGIC.len()] = LITTLE_ENDIAN_VERSION;
+
+        Self::new(0, uuid, 0, Endianness::Little)
+    }
+
+    fn header_version(&self) -> Option<u8> {
+        if self.uuid.starts_with(HEADER_MAGIC) {
+            Some(self.uuid[HEADER_MAGIC.len()])
+        } else {
+            None
         }
     }
+
+    fn id(&self, endianness: Endianness) -> u64 {
+        endianness.convert(self.id.get())
+    }
+
+    fn offset(&self, endianness: Endianness) -> u64 {
+        endianness.convert(self.offset.get())
+    }
+
+    fn is_tombstone(&self, endianness: Endianness) -> bool {
+        self.offset(endianness) == TOMBSTONE_OFFSET
+    }
 }
 
 #[cfg(test)]
@@ -139,6 +633,20 @@ mod tests {
     #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
     struct Named<'a>(u64, Uuid, &'a str);
 
+    // Same encoding as `Named`, but without borrowing
+    #[derive(Serialize, Deserialize)]
+    struct OwnedNamed(u64, Uuid, String);
+
+    impl DatabaseRecord for OwnedNamed {
+        fn get_id(&self) -> u64 {
+            self.0
+        }
+
+        fn get_uuid(&self) -> uuid::Bytes {
+            *self.1.as_bytes()
+        }
+    }
+
     impl<'a> DatabaseRecord for Named<'a> {
         fn get_id(&self) -> u64 {
             self.0
@@ -186,4 +694,581 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn find_many_keeps_the_given_order() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = (0..10)
+            .map(|id| Named(id, Uuid::new_v4(), "named"))
+            .collect::<Vec<_>>();
+
+        DatabaseWriter::new(basedir.path())?.append_batch(&entries)?;
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+
+        let found = db_reader.find_many(&[7, 42, 0, 3, 7])?;
+        assert_eq!(
+            vec![
+                Some(entries[7].clone()),
+                None,
+                Some(entries[0].clone()),
+                Some(entries[3].clone()),
+                Some(entries[7].clone()),
+            ],
+            found
+        );
+
+        assert!(db_reader.find_many(&[])?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_records_are_gone() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = vec![
+            Named(0, Uuid::new_v4(), "a"),
+            Named(1, Uuid::new_v4(), "b"),
+            Named(2, Uuid::new_v4(), "c"),
+        ];
+
+        {
+            let mut db_writer = DatabaseWriter::new(basedir.path())?;
+            for entry in entries.iter() {
+                db_writer.append(entry)?;
+            }
+        }
+
+        let mut db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+
+        assert!(db_reader.delete_by_id(0)?);
+        assert!(db_reader.delete_by_uuid(&entries[2].1)?);
+
+        // Already gone
+        assert!(!db_reader.delete_by_id(0)?);
+        assert!(!db_reader.delete_by_uuid(&entries[2].1)?);
+        assert!(!db_reader.delete_by_id(42)?);
+
+        let check = |db_reader: &DatabaseReader<Named>| {
+            assert!(db_reader.find_by_id(0).is_none());
+            assert!(db_reader.find_by_uuid(&entries[0].1).is_none());
+            assert!(db_reader.find_by_id(2).is_none());
+            assert!(db_reader.id_for_uuid(&entries[2].1).is_none());
+
+            assert_eq!(
+                Some(entries[1].clone()),
+                db_reader.find_by_id(1).transpose().ok().flatten()
+            );
+            assert_eq!(vec![&1], db_reader.ids().collect::<Vec<_>>());
+        };
+
+        check(&db_reader);
+        drop(db_reader);
+
+        // Tombstones survive reopening
+        check(&DatabaseReader::open(basedir.path())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn len_and_data_size() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = (0..10)
+            .map(|id| Named(id, Uuid::new_v4(), "named"))
+            .collect::<Vec<_>>();
+
+        DatabaseWriter::new(basedir.path())?.append_batch(&entries)?;
+
+        let mut db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+
+        let data_size = entries
+            .iter()
+            .map(|entry| bincode::serialized_size(entry).unwrap() as usize)
+            .sum::<usize>();
+
+        assert_eq!(10, db_reader.len());
+        assert!(!db_reader.is_empty());
+        assert_eq!(data_size, db_reader.data_size());
+
+        for id in 0..10 {
+            db_reader.delete_by_id(id)?;
+        }
+
+        // Deleting doesn't reclaim space
+        assert_eq!(0, db_reader.len());
+        assert!(db_reader.is_empty());
+        assert_eq!(data_size, db_reader.data_size());
+
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_records_can_be_readded() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let original = Named(0, Uuid::new_v4(), "original");
+        let readded = Named(0, Uuid::new_v4(), "readded");
+
+        DatabaseWriter::new(basedir.path())?.append(&original)?;
+
+        let mut db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        assert!(db_reader.delete_by_id(0)?);
+        drop(db_reader);
+
+        DatabaseWriter::new(basedir.path())?.append(&readded)?;
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+
+        assert_eq!(
+            Some(readded.clone()),
+            db_reader.find_by_id(0).transpose().ok().flatten()
+        );
+        assert_eq!(
+            Some(readded.clone()),
+            db_reader
+                .find_by_uuid(&readded.1)
+                .transpose()
+                .ok()
+                .flatten()
+        );
+        assert!(db_reader.find_by_uuid(&original.1).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn iter_yields_live_records_in_insertion_order() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = (0..10)
+            .rev()
+            .map(|id| Named(id, Uuid::new_v4(), "named"))
+            .collect::<Vec<_>>();
+
+        {
+            let mut db_writer = DatabaseWriter::new(basedir.path())?;
+            for entry in entries.iter() {
+                db_writer.append(entry)?;
+            }
+        }
+
+        let mut db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+
+        assert_eq!(entries, db_reader.iter().collect::<Result<Vec<_>>>()?);
+
+        db_reader.delete_by_id(5)?;
+        db_reader.delete_by_id(0)?;
+
+        let wanted = entries
+            .iter()
+            .filter(|entry| entry.0 != 5 && entry.0 != 0)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        assert_eq!(wanted, db_reader.iter().collect::<Result<Vec<_>>>()?);
+
+        // Borrows immutably, so lookups still work while iterating
+        for record in db_reader.iter() {
+            let record = record?;
+            assert!(db_reader.find_by_id(record.0).is_some());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_verified_detects_truncated_data() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = (0..5)
+            .map(|id| OwnedNamed(id, Uuid::new_v4(), "named".to_string()))
+            .collect::<Vec<_>>();
+
+        {
+            let mut db_writer = DatabaseWriter::new(basedir.path())?;
+            for entry in entries.iter() {
+                db_writer.append(entry)?;
+            }
+        }
+
+        assert!(DatabaseReader::<OwnedNamed>::open_verified(basedir.path()).is_ok());
+
+        let datafile = OpenOptions::new()
+            .write(true)
+            .open(basedir.path().join(DATA_FILE))?;
+        let data_len = datafile.metadata()?.len();
+
+        // Chop off the end of the last record
+        datafile.set_len(data_len - 2)?;
+
+        assert!(DatabaseReader::<OwnedNamed>::open(basedir.path()).is_ok());
+
+        let err = DatabaseReader::<OwnedNamed>::open_verified(basedir.path())
+            .err()
+            .expect("truncated record is detected");
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(err.to_string().contains("record 4"));
+
+        // Offsets pointing past the end are caught by a plain open
+        datafile.set_len(data_len / 2)?;
+        assert!(DatabaseReader::<OwnedNamed>::open(basedir.path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn writers_that_never_got_dropped_leave_a_readable_database() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = (0..10)
+            .map(|id| OwnedNamed(id, Uuid::new_v4(), format!("named {}", id)))
+            .collect::<Vec<_>>();
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        for entry in entries[..5].iter() {
+            db_writer.append(entry)?;
+        }
+        db_writer.append_batch(&entries[5..7])?;
+        // As if the process got killed: nothing gets flushed on drop
+        std::mem::forget(db_writer);
+
+        let db_reader = DatabaseReader::<OwnedNamed>::open_verified(basedir.path())?;
+        assert_eq!(7, db_reader.len());
+        assert!(db_reader.contains_id(6));
+        assert!(!db_reader.contains_id(7));
+
+        // And picking up from where it stopped works
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        for entry in entries[7..].iter() {
+            db_writer.append(entry)?;
+        }
+        drop(db_writer);
+
+        let db_reader = DatabaseReader::<OwnedNamed>::open_verified(basedir.path())?;
+        let ids = db_reader
+            .iter()
+            .map(|record| record.map(|record| record.0))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!((0..10).collect::<Vec<_>>(), ids);
+
+        Ok(())
+    }
+
+    impl KeyedRecord for OwnedNamed {
+        fn get_keys(&self) -> Vec<(&'static str, String)> {
+            vec![
+                ("name", self.2.clone()),
+                ("name_upper", self.2.to_uppercase()),
+            ]
+        }
+    }
+
+    #[test]
+    fn keyed_lookup() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = vec![
+            OwnedNamed(0, Uuid::new_v4(), "a".to_string()),
+            OwnedNamed(1, Uuid::new_v4(), "b".to_string()),
+            OwnedNamed(2, Uuid::new_v4(), "c".to_string()),
+        ];
+
+        DatabaseWriter::new(basedir.path())?.append_batch(&entries)?;
+
+        let find = |db_reader: &DatabaseReader<OwnedNamed>, name, value| {
+            db_reader
+                .find_by_key(name, value)
+                .transpose()
+                .unwrap()
+                .map(|found| found.0)
+        };
+
+        // Not opted in
+        let db_reader = DatabaseReader::<OwnedNamed>::open(basedir.path())?;
+        assert_eq!(None, find(&db_reader, "name", "a"));
+
+        let mut db_reader = DatabaseReader::<OwnedNamed>::open_keyed(basedir.path())?;
+
+        assert_eq!(Some(0), find(&db_reader, "name", "a"));
+        assert_eq!(Some(1), find(&db_reader, "name_upper", "B"));
+        assert_eq!(Some(2), find(&db_reader, "name", "c"));
+        assert_eq!(None, find(&db_reader, "name", "C"));
+        assert_eq!(None, find(&db_reader, "unknown", "a"));
+
+        db_reader.delete_by_id(1)?;
+        assert_eq!(None, find(&db_reader, "name", "b"));
+        assert_eq!(None, find(&db_reader, "name_upper", "B"));
+        assert_eq!(Some(2), find(&db_reader, "name", "c"));
+
+        Ok(())
+    }
+
+    struct Unencodable(u64);
+
+    impl DatabaseRecord for Unencodable {
+        fn get_id(&self) -> u64 {
+            self.0
+        }
+
+        fn get_uuid(&self) -> uuid::Bytes {
+            *Uuid::nil().as_bytes()
+        }
+    }
+
+    impl Serialize for Unencodable {
+        fn serialize<S: serde::Serializer>(
+            &self,
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            if self.0 == 0 {
+                Err(serde::ser::Error::custom("cannot encode zero"))
+            } else {
+                serializer.serialize_u64(self.0)
+            }
+        }
+    }
+
+    #[test]
+    fn append_batch_works_like_append() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = (0..100)
+            .map(|id| Named(id, Uuid::new_v4(), "named"))
+            .collect::<Vec<_>>();
+
+        {
+            let mut db_writer = DatabaseWriter::new(basedir.path())?;
+            db_writer.append(&entries[0])?;
+            db_writer.append_batch(&entries[1..50])?;
+            db_writer.append(&entries[50])?;
+            db_writer.append_batch(&entries[51..])?;
+            db_writer.append_batch(&[])?;
+        }
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+
+        assert_eq!(entries, db_reader.iter().collect::<Result<Vec<_>>>()?);
+
+        for entry in entries.iter() {
+            assert_eq!(
+                Some(entry),
+                db_reader.find_by_uuid(&entry.1).transpose()?.as_ref()
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn append_batch_reports_first_failure() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        db_writer.append(&Unencodable(42))?;
+
+        let err = db_writer
+            .append_batch(&[
+                Unencodable(1),
+                Unencodable(2),
+                Unencodable(0),
+                Unencodable(0),
+            ])
+            .expect_err("encoding fails");
+
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert!(err.to_string().contains("index 2"));
+
+        drop(db_writer);
+
+        // Nothing from the failed batch was written
+        let db_reader = DatabaseReader::<u64>::open(basedir.path())?;
+        assert_eq!(vec![&42], db_reader.ids().collect::<Vec<_>>());
+
+        Ok(())
+    }
+
+    #[test]
+    fn portable_databases_are_little_endian() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let entries = (0..10)
+            .map(|id| Named(id * 1000, Uuid::new_v4(), "named"))
+            .collect::<Vec<_>>();
+
+        {
+            let mut db_writer = DatabaseWriter::new_portable(basedir.path())?;
+            db_writer.append_batch(&entries[..5])?;
+        }
+
+        {
+            // Keeps writing in the same byte order
+            let mut db_writer = DatabaseWriter::new(basedir.path())?;
+            for entry in entries[5..].iter() {
+                db_writer.append(entry)?;
+            }
+        }
+
+        let mut db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        assert_eq!(entries, db_reader.iter().collect::<Result<Vec<_>>>()?);
+
+        db_reader.delete_by_id(0)?;
+        drop(db_reader);
+
+        let log = std::fs::read(basedir.path().join(OFFSETS_FILE))?;
+        let entry_len = std::mem::size_of::<LogEntry>();
+
+        // Header, one entry per record, then the tombstone
+        assert_eq!(entry_len * 12, log.len());
+        assert_eq!(&HEADER_MAGIC[..], &log[..HEADER_MAGIC.len()]);
+
+        for (entry, raw) in entries.iter().zip(log[entry_len..].chunks(entry_len)) {
+            assert_eq!(entry.1.as_bytes(), &raw[..16]);
+
+            let mut id = [0; 8];
+            id.copy_from_slice(&raw[16..24]);
+            assert_eq!(entry.0, u64::from_le_bytes(id));
+        }
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        assert!(db_reader.find_by_id(0).is_none());
+        assert_eq!(9, db_reader.ids().count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn portable_log_roundtrips_through_swapped_bytes() -> Result<()> {
+        let nativedir = tempfile::tempdir()?;
+        let portabledir = tempfile::tempdir()?;
+
+        let entries = (0..10)
+            .map(|id| Named(id + 0x0102_0304, Uuid::new_v4(), "named"))
+            .collect::<Vec<_>>();
+
+        {
+            let mut db_writer = DatabaseWriter::new(nativedir.path())?;
+            db_writer.append_batch(&entries)?;
+        }
+
+        // Rewrite the native log by hand, swapping every number
+        // to little-endian and adding the portable header
+        let native_log = std::fs::read(nativedir.path().join(OFFSETS_FILE))?;
+        let mut portable_log = LogEntry::little_endian_header().as_bytes().to_vec();
+
+        for raw in native_log.chunks(std::mem::size_of::<LogEntry>()) {
+            portable_log.extend_from_slice(&raw[..16]);
+
+            for number in raw[16..].chunks(8) {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(number);
+                portable_log.extend_from_slice(&u64::from_ne_bytes(bytes).to_le_bytes());
+            }
+        }
+
+        std::fs::write(portabledir.path().join(OFFSETS_FILE), portable_log)?;
+        std::fs::copy(
+            nativedir.path().join(DATA_FILE),
+            portabledir.path().join(DATA_FILE),
+        )?;
+
+        let db_reader = DatabaseReader::<Named>::open(portabledir.path())?;
+        assert_eq!(entries, db_reader.iter().collect::<Result<Vec<_>>>()?);
+
+        // Can't turn an existing native database portable
+        assert!(DatabaseWriter::<Named>::new_portable(nativedir.path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn compaction_keeps_only_live_records() -> Result<()> {
+        let srcdir = tempfile::tempdir()?;
+        let dstdir = tempfile::tempdir()?;
+
+        let entries = (0..10)
+            .map(|id| Named(id, Uuid::new_v4(), "named"))
+            .collect::<Vec<_>>();
+
+        {
+            let mut db_writer = DatabaseWriter::new(srcdir.path())?;
+            for entry in entries.iter() {
+                db_writer.append(entry)?;
+            }
+        }
+
+        let mut db_reader = DatabaseReader::<Named>::open(srcdir.path())?;
+        db_reader.delete_by_id(3)?;
+        db_reader.delete_by_id(7)?;
+        drop(db_reader);
+
+        let compacted_path = dstdir.path().join("compacted");
+        assert_eq!(8, compact::<OwnedNamed, _, _>(&srcdir, &compacted_path)?);
+
+        let source = DatabaseReader::<Named>::open(srcdir.path())?;
+        let compacted = DatabaseReader::<Named>::open(&compacted_path)?;
+
+        assert!(compacted.data.len() < source.data.len());
+        assert_eq!(
+            source.iter().collect::<Result<Vec<_>>>()?,
+            compacted.iter().collect::<Result<Vec<_>>>()?
+        );
+
+        for entry in entries.iter() {
+            assert_eq!(
+                source.id_for_uuid(&entry.1),
+                compacted.id_for_uuid(&entry.1)
+            );
+        }
+
+        // Refuses to overwrite an existing database
+        assert!(compact::<OwnedNamed, _, _>(&srcdir, &compacted_path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn compacting_without_live_records() -> Result<()> {
+        let srcdir = tempfile::tempdir()?;
+        let dstdir = tempfile::tempdir()?;
+
+        {
+            let mut db_writer = DatabaseWriter::new(srcdir.path())?;
+            db_writer.append(&Named(0, Uuid::new_v4(), "named"))?;
+            db_writer.append(&Named(1, Uuid::new_v4(), "named"))?;
+        }
+
+        let mut db_reader = DatabaseReader::<Named>::open(srcdir.path())?;
+        db_reader.delete_by_id(0)?;
+        db_reader.delete_by_id(1)?;
+        drop(db_reader);
+
+        let compacted_path = dstdir.path().join("compacted");
+        assert_eq!(0, compact::<OwnedNamed, _, _>(&srcdir, &compacted_path)?);
+        assert_eq!(0, std::fs::metadata(compacted_path.join(DATA_FILE))?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_compaction_leaves_nothing_behind() -> Result<()> {
+        let srcdir = tempfile::tempdir()?;
+        let dstdir = tempfile::tempdir()?;
+
+        let compacted_path = dstdir.path().join("compacted");
+        let missing_path = srcdir.path().join("missing");
+        assert!(compact::<OwnedNamed, _, _>(&missing_path, &compacted_path).is_err());
+        assert!(!compacted_path.exists());
+
+        // Existing directories are kept, just emptied
+        assert!(compact::<OwnedNamed, _, _>(&missing_path, dstdir.path()).is_err());
+        assert!(dstdir.path().exists());
+        assert!(!dstdir.path().join(OFFSETS_FILE).exists());
+        assert!(!dstdir.path().join(DATA_FILE).exists());
+
+        Ok(())
+    }
 }
@@ -1,6 +1,6 @@
 use std::{
     fs::{File, OpenOptions},
-    io::{self, BufRead, BufReader, Result, Write},
+    io::{self, BufRead, BufReader, Read, Result, Seek, SeekFrom, Write},
     marker::PhantomData,
     mem::size_of,
     path::Path,
@@ -50,12 +50,35 @@ where
         Ok(self.file.metadata()?.len() as usize / size_of::<T>())
     }
 
+    pub fn with_first_entry<F, R>(&self, with_entry: F) -> Result<Option<R>>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        if self.len()? == 0 {
+            return Ok(None);
+        }
+
+        let mut buf = vec![0; size_of::<T>()];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut buf)?;
+
+        if let Some(entry) = LayoutVerified::<_, T>::new(buf.as_slice()) {
+            Ok(Some((with_entry)(&entry)))
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "Log corrupted!"))
+        }
+    }
+
     pub fn for_each_entry<F>(&self, mut each_entry: F) -> std::io::Result<()>
     where
         F: FnMut(&T),
     {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(0))?;
+
         let entry_len = size_of::<T>();
-        let mut log_reader = BufReader::with_capacity((8192 / entry_len) * entry_len, &self.file);
+        let mut log_reader = BufReader::with_capacity((8192 / entry_len) * entry_len, file);
 
         loop {
             let buf = log_reader.fill_buf()?;
@@ -84,6 +107,10 @@ where
     pub fn append(&mut self, item: &T) -> Result<()> {
         self.file.write_all(item.as_bytes())
     }
+
+    pub fn append_all(&mut self, items: &[T]) -> Result<()> {
+        self.file.write_all(items.as_bytes())
+    }
 }
 
 #[cfg(test)]
@@ -113,6 +140,11 @@ mod tests {
 
         assert_eq!(100, log.len()?);
 
+        assert_eq!(
+            Some(0),
+            log.with_first_entry(|e: &U64<NativeEndian>| e.get())?
+        );
+
         let mut wanted: u64 = 0;
         log.for_each_entry(|e: &U64<NativeEndian>| {
             assert_eq!(wanted, e.get());
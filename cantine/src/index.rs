@@ -1,25 +1,43 @@
-use std::{cmp::Ordering, convert::TryFrom};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::HashSet,
+    convert::TryFrom,
+    marker::PhantomData,
+    str::FromStr,
+};
 
 use bincode;
 use serde::{Deserialize, Serialize};
 use tantivy::{
     self,
-    collector::Collector,
+    collector::{Collector, Count, SegmentCollector},
     fastfield::{DynamicFastFieldReader, FastFieldReader},
-    query::Query,
-    schema::{Field, Schema, SchemaBuilder, Value, FAST, INDEXED, STORED, TEXT},
-    DocId, Document, Result, Score, Searcher, SegmentOrdinal, SegmentReader, TantivyError,
+    query::{AllQuery, PhraseQuery, Query, TermQuery},
+    schema::{
+        Field, IndexRecordOption, Schema, SchemaBuilder, TextFieldIndexing, TextOptions, FAST,
+        INDEXED, STORED,
+    },
+    tokenizer::{
+        AsciiFoldingFilter, LowerCaser, NgramTokenizer, RemoveLongFilter, SimpleTokenizer,
+        TextAnalyzer,
+    },
+    DocAddress, DocId, Document, Index, Result, Score, Searcher, SegmentOrdinal, SegmentReader,
+    SnippetGenerator, TantivyError, Term,
 };
 
 use crate::model::{
-    Features, FeaturesAggregationQuery, FeaturesAggregationResult, FeaturesFilterFields, Recipe,
-    RecipeId, Sort,
+    DietFacets, Features, FeaturesAggregationQuery, FeaturesAggregationResult,
+    FeaturesFilterFields, FeaturesSort, Recipe, RecipeId, SearchCursor, Sort,
 };
 
-use cantine_derive::{AggregableCollector, Filterable};
+use cantine_derive::{AggregableCollector, Filterable, FilterableFieldKind, SortTarget};
 
-use tique::conditional_collector::{
-    Ascending, CheckCondition, CollectionResult, ConditionForSegment, Descending, TopCollector,
+use tique::{
+    conditional_collector::{
+        Ascending, CheckCondition, CollectionResult, ConditionForSegment, Descending,
+        FastFieldScore, TopCollector,
+    },
+    topterms::TopTerms,
 };
 
 #[derive(Clone)]
@@ -30,6 +48,12 @@ pub struct RecipeIndex {
     pub ingredients: Field,
     pub instructions: Field,
 
+    /// The recipe name indexed as edge n-grams: every prefix of the
+    /// lowercased name, from `MIN_PREFIX_LEN` up to `MAX_PREFIX_LEN`
+    /// chars, is a term. Requires `register_tokenizers` to be called
+    /// on the index before writing to it
+    pub name_prefixes: Field,
+
     pub features_bincode: Field,
     pub features: FeaturesFilterFields,
 }
@@ -38,14 +62,127 @@ const FIELD_ID: &str = "id";
 const FIELD_NAME: &str = "name";
 const FIELD_INGREDIENTS: &str = "ingredients";
 const FIELD_INSTRUCTIONS: &str = "instructions";
+const FIELD_NAME_PREFIXES: &str = "name_prefixes";
 const FIELD_FEATURES_BINCODE: &str = "features_bincode";
 
+// How many keywords are picked to find similar recipes
+const NUM_SIMILARITY_KEYWORDS: usize = 20;
+
+// How far apart two values of a nutrition feature must be to count
+// as completely different. Used to bring every feature to the same
+// scale when computing distances between recipes
+const CALORIES_SCALE: f64 = 1000.0;
+const NUTRIENT_SCALE: f64 = 100.0;
+
+const PREFIX_TOKENIZER: &str = "cantine_prefix";
+const MIN_PREFIX_LEN: usize = 2;
+const MAX_PREFIX_LEN: usize = 20;
+
+const TEXT_TOKENIZER: &str = "cantine_text";
+// Same as tantivy's default tokenizer
+const MAX_TOKEN_LEN: usize = 40;
+
+const NGRAM_TOKENIZER: &str = "cantine_ngram";
+const MIN_NGRAM_LEN: usize = 3;
+const MAX_NGRAM_LEN: usize = 20;
+
+/// How the full text fields (name, ingredients and instructions)
+/// are tokenized. Picked when the index is created and kept in
+/// its schema
+///
+/// Every analysis folds diacritics into their ascii equivalents,
+/// so "jalapeno" matches "jalapeño". Indices created before that
+/// need to be rebuilt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAnalysis {
+    /// Like tantivy's default: words, lowercased
+    Default,
+    /// Every substring of the lowercased text, from `MIN_NGRAM_LEN`
+    /// up to `MAX_NGRAM_LEN` chars, is a term. Enables partial
+    /// matches at the cost of a much bigger index
+    Ngram,
+}
+
+impl TextAnalysis {
+    fn tokenizer(self) -> &'static str {
+        match self {
+            TextAnalysis::Default => TEXT_TOKENIZER,
+            TextAnalysis::Ngram => NGRAM_TOKENIZER,
+        }
+    }
+}
+
+impl FromStr for TextAnalysis {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input {
+            "default" => Ok(TextAnalysis::Default),
+            "ngram" => Ok(TextAnalysis::Ngram),
+            other => Err(format!("Unknown text analysis: {}", other)),
+        }
+    }
+}
+
 impl RecipeIndex {
+    /// Registers the custom tokenizers the schema relies on
+    pub fn register_tokenizers(index: &Index) {
+        index.tokenizers().register(
+            PREFIX_TOKENIZER,
+            TextAnalyzer::from(NgramTokenizer::new(MIN_PREFIX_LEN, MAX_PREFIX_LEN, true))
+                .filter(LowerCaser),
+        );
+        index.tokenizers().register(
+            TEXT_TOKENIZER,
+            TextAnalyzer::from(SimpleTokenizer)
+                .filter(RemoveLongFilter::limit(MAX_TOKEN_LEN))
+                .filter(LowerCaser)
+                .filter(AsciiFoldingFilter),
+        );
+        index.tokenizers().register(
+            NGRAM_TOKENIZER,
+            TextAnalyzer::from(NgramTokenizer::new(MIN_NGRAM_LEN, MAX_NGRAM_LEN, false))
+                .filter(LowerCaser)
+                .filter(AsciiFoldingFilter),
+        );
+    }
+
+    /// Adds the recipe fields to the builder, tokenizing the full
+    /// text ones as dictated by `analysis`
+    pub fn with_analysis(builder: &mut SchemaBuilder, analysis: TextAnalysis) -> Self {
+        let text_options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(analysis.tokenizer())
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+
+        RecipeIndex {
+            id: builder.add_u64_field(FIELD_ID, STORED | FAST),
+
+            name: builder.add_text_field(FIELD_NAME, text_options.clone()),
+            ingredients: builder.add_text_field(FIELD_INGREDIENTS, text_options.clone()),
+            instructions: builder.add_text_field(FIELD_INSTRUCTIONS, text_options),
+
+            name_prefixes: builder.add_text_field(
+                FIELD_NAME_PREFIXES,
+                TextOptions::default().set_indexing_options(
+                    TextFieldIndexing::default()
+                        .set_tokenizer(PREFIX_TOKENIZER)
+                        .set_index_option(IndexRecordOption::WithFreqs),
+                ),
+            ),
+
+            features_bincode: builder.add_bytes_field(FIELD_FEATURES_BINCODE, FAST),
+            features: Features::create_schema(builder, INDEXED | FAST),
+        }
+    }
+
     pub fn make_document(&self, recipe: &Recipe) -> Document {
         let mut doc = Document::new();
         doc.add_u64(self.id, recipe.recipe_id);
 
         doc.add_text(self.name, recipe.name.as_str());
+        doc.add_text(self.name_prefixes, recipe.name.as_str());
 
         recipe
             .ingredients
@@ -74,66 +211,441 @@ impl RecipeIndex {
         sort: Sort,
         after: Option<After>,
     ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        // Only the total is wanted, so there's no need to track items
+        if limit == 0 {
+            return Ok((searcher.search(query, &Count)?, Vec::new(), None));
+        }
+
+        // Ties are broken by the lowest recipe id, regardless of
+        // the ordering, so that cursors are always unambiguous
+        macro_rules! tie_breaker {
+            (Descending) => {
+                Reverse<RecipeId>
+            };
+            (Ascending) => {
+                RecipeId
+            };
+        }
+
         macro_rules! collect {
-            ($type: ty, $field:ident, $order:ident) => {
-                if let Some(after) = after {
-                    let top_collector =
-                        TopCollector::<$type, $order, _>::new(limit, after.as_paginator(self.id))
-                            .top_fast_field(self.features.$field);
+            ($type: ty, $field:expr, $order:ident) => {{
+                let top_collector = TopCollector::<($type, tie_breaker!($order)), $order, _>::new(
+                    limit,
+                    Paginator::from(after),
+                )
+                .top_fast_field_then($field, self.id);
 
-                    self.render::<$type, _>(&searcher, query, top_collector)
-                } else {
-                    let top_collector = TopCollector::<$type, $order, _>::new(limit, true)
-                        .top_fast_field(self.features.$field);
+                self.render(&searcher, query, top_collector)
+            }};
+
+            ($order:ident) => {{
+                let id_field = self.id;
+                let top_collector = TopCollector::<
+                    (NanLast<Score, $order>, tie_breaker!($order)),
+                    $order,
+                    _,
+                >::new(limit, Paginator::from(after))
+                .with_score_modifier(move |reader: &SegmentReader| {
+                    let id_reader = <tie_breaker!($order)>::fast_field_reader(reader, id_field);
+                    move |doc_id: DocId, score: Score| {
+                        (
+                            NanLast::new(score),
+                            <tie_breaker!($order)>::get(&id_reader, doc_id),
+                        )
+                    }
+                });
 
-                    self.render::<$type, _>(&searcher, query, top_collector)
+                self.render(&searcher, query, top_collector)
+            }};
+        }
+
+        match sort {
+            Sort::By(by) => match (by.target(&self.features), by.is_ascending()) {
+                (SortTarget::Relevance, false) => collect!(Descending),
+                (SortTarget::Relevance, true) => collect!(Ascending),
+                (SortTarget::Field(field, FilterableFieldKind::U64), false) => {
+                    collect!(u64, field, Descending)
+                }
+                (SortTarget::Field(field, FilterableFieldKind::U64), true) => {
+                    collect!(u64, field, Ascending)
+                }
+                // Missing values are read as NaN
+                (SortTarget::Field(field, FilterableFieldKind::F64), false) => {
+                    collect!(NanLast<f64, Descending>, field, Descending)
                 }
+                (SortTarget::Field(field, FilterableFieldKind::F64), true) => {
+                    collect!(NanLast<f64, Ascending>, field, Ascending)
+                }
+                // No feature is signed, so cursors can't carry such values
+                (SortTarget::Field(_field, FilterableFieldKind::I64), _) => Err(
+                    TantivyError::InvalidArgument(format!("Can't sort by {:?}", by)),
+                ),
+            },
+            Sort::Random { random: seed } => {
+                let id_field = self.id;
+                let top_collector = TopCollector::<(u64, Reverse<RecipeId>), Descending, _>::new(
+                    limit,
+                    Paginator::from(after),
+                )
+                .with_custom_scorer(move |reader: &SegmentReader| {
+                    let id_reader = reader
+                        .fast_fields()
+                        .u64(id_field)
+                        .expect("id is a u64 fast field");
+
+                    move |doc_id: DocId| {
+                        let recipe_id = id_reader.get(doc_id);
+                        (shuffle_key(seed, recipe_id), Reverse(recipe_id))
+                    }
+                });
+
+                self.render(searcher, query, top_collector)
+            }
+        }
+    }
+
+    /// Iterates over every recipe matching the query, following
+    /// `search`'s cursors to collect `batch_size` of them at a time
+    pub fn search_all<'a>(
+        &'a self,
+        searcher: &'a Searcher,
+        query: &'a dyn Query,
+        sort: Sort,
+        batch_size: usize,
+    ) -> SearchAll<'a> {
+        SearchAll {
+            recipe_index: self,
+            searcher,
+            query,
+            sort,
+            batch_size,
+            after: None,
+            done: false,
+        }
+    }
+
+    /// Like `search`, but recipes tied on the `primary` sort are
+    /// ordered by the `secondary` one
+    pub fn search_then(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        primary: Sort,
+        secondary: Sort,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        if limit == 0 {
+            return Ok((searcher.search(query, &Count)?, Vec::new(), None));
+        }
+
+        let id_field = self.id;
+        let primary = self.sort_value(primary);
+        let secondary = self.sort_value(secondary);
+
+        let top_collector =
+            TopCollector::<CompoundKey, Descending, _>::new(limit, Paginator::from(after))
+                .with_score_modifier(move |reader: &SegmentReader| {
+                    let id_reader = reader
+                        .fast_fields()
+                        .u64(id_field)
+                        .expect("id is a u64 fast field");
+                    let first = primary.for_segment(reader);
+                    let second = secondary.for_segment(reader);
+
+                    move |doc_id: DocId, score: Score| {
+                        (
+                            NanLast::new(first.get(doc_id, score)),
+                            NanLast::new(second.get(doc_id, score)),
+                            Reverse(id_reader.get(doc_id)),
+                        )
+                    }
+                });
+
+        self.render(searcher, query, top_collector)
+    }
+
+    /// Reads the values of a cursor for the given sorts, the way
+    /// `search` (or `search_then`, with a `secondary` sort) writes
+    /// them. Yields `None` when the cursor can't have come from them
+    pub fn after_from_cursor(
+        &self,
+        cursor: &SearchCursor,
+        sort: Sort,
+        secondary: Option<Sort>,
+        recipe_id: RecipeId,
+    ) -> Option<After> {
+        if let Some(secondary) = secondary {
+            return match cursor {
+                SearchCursor::Compound(primary_bits, secondary_bits, _) => Some(After::Compound(
+                    self.sort_value(sort).value_from_bits(*primary_bits),
+                    self.sort_value(secondary).value_from_bits(*secondary_bits),
+                    recipe_id,
+                )),
+                _ => None,
             };
+        }
+
+        let target = match &sort {
+            Sort::By(by) => Some(by.target(&self.features)),
+            Sort::Random { .. } => None,
+        };
 
-            ($order:ident) => {
-                if let Some(after) = after {
-                    let top_collector =
-                        TopCollector::<_, $order, _>::new(limit, after.as_paginator(self.id));
+        match (cursor, target) {
+            (SearchCursor::Relevance(score, _), Some(SortTarget::Relevance)) => {
+                Some(After::Relevance(*score, recipe_id))
+            }
+            (
+                SearchCursor::U64Field(score, _),
+                Some(SortTarget::Field(_, FilterableFieldKind::U64)),
+            )
+            | (SearchCursor::U64Field(score, _), None) => Some(After::U64Field(*score, recipe_id)),
+            (
+                SearchCursor::F64Field(score, _),
+                Some(SortTarget::Field(_, FilterableFieldKind::F64)),
+            ) => Some(After::F64Field(*score, recipe_id)),
+            _ => None,
+        }
+    }
 
-                    self.render::<Score, _>(&searcher, query, top_collector)
-                } else {
-                    let top_collector = TopCollector::<_, $order, _>::new(limit, true);
+    fn sort_value(&self, sort: Sort) -> SortValue {
+        let (source, ascending) = match sort {
+            Sort::By(by) => {
+                let source = match by.target(&self.features) {
+                    SortTarget::Relevance => SortSource::Relevance,
+                    SortTarget::Field(field, FilterableFieldKind::U64) => SortSource::U64(field),
+                    SortTarget::Field(field, FilterableFieldKind::I64) => SortSource::I64(field),
+                    SortTarget::Field(field, FilterableFieldKind::F64) => SortSource::F64(field),
+                };
+                (source, by.is_ascending())
+            }
+            Sort::Random { random: seed } => (SortSource::Random(seed, self.id), false),
+        };
+
+        SortValue { source, ascending }
+    }
+
+    /// Finds where the recipe with the given id is in the index
+    pub fn doc_address(
+        &self,
+        searcher: &Searcher,
+        recipe_id: RecipeId,
+    ) -> Result<Option<DocAddress>> {
+        for (segment_ord, reader) in searcher.segment_readers().iter().enumerate() {
+            let ids = reader.fast_fields().u64(self.id)?;
 
-                    self.render::<Score, _>(&searcher, query, top_collector)
+            for doc_id in reader.doc_ids_alive() {
+                if ids.get(doc_id) == recipe_id {
+                    return Ok(Some(DocAddress::new(segment_ord as SegmentOrdinal, doc_id)));
                 }
-            };
+            }
         }
 
-        match sort {
-            Sort::Relevance => collect!(Descending),
-            Sort::RelevanceAsc => collect!(Ascending),
-            Sort::NumIngredients => collect!(u64, num_ingredients, Descending),
-            Sort::InstructionsLength => collect!(u64, instructions_length, Descending),
-            Sort::TotalTime => collect!(u64, total_time, Descending),
-            Sort::CookTime => collect!(u64, cook_time, Descending),
-            Sort::PrepTime => collect!(u64, prep_time, Descending),
-            Sort::Calories => collect!(u64, calories, Descending),
-            Sort::FatContent => collect!(f64, fat_content, Descending),
-            Sort::CarbContent => collect!(f64, carb_content, Descending),
-            Sort::ProteinContent => collect!(f64, protein_content, Descending),
-            Sort::NumIngredientsAsc => collect!(u64, num_ingredients, Ascending),
-            Sort::InstructionsLengthAsc => collect!(u64, instructions_length, Ascending),
-            Sort::TotalTimeAsc => collect!(u64, total_time, Ascending),
-            Sort::CookTimeAsc => collect!(u64, cook_time, Ascending),
-            Sort::PrepTimeAsc => collect!(u64, prep_time, Ascending),
-            Sort::CaloriesAsc => collect!(u64, calories, Ascending),
-            Sort::FatContentAsc => collect!(f64, fat_content, Ascending),
-            Sort::CarbContentAsc => collect!(f64, carb_content, Ascending),
-            Sort::ProteinContentAsc => collect!(f64, protein_content, Ascending),
+        Ok(None)
+    }
+
+    /// Collects the id of every recipe in the index
+    pub fn indexed_ids(&self, searcher: &Searcher) -> Result<HashSet<RecipeId>> {
+        let mut found = HashSet::with_capacity(searcher.num_docs() as usize);
+
+        for reader in searcher.segment_readers() {
+            let ids = reader.fast_fields().u64(self.id)?;
+            found.extend(reader.doc_ids_alive().map(|doc_id| ids.get(doc_id)));
+        }
+
+        Ok(found)
+    }
+
+    /// Finds up to `limit` recipes similar to the given one, by
+    /// searching for its most relevant keywords
+    ///
+    /// Yields `None` if the recipe is not in the index.
+    pub fn similar(
+        &self,
+        searcher: &Searcher,
+        topterms: &TopTerms,
+        recipe_id: RecipeId,
+        limit: usize,
+    ) -> Result<Option<Vec<RecipeId>>> {
+        let addr = match self.doc_address(searcher, recipe_id)? {
+            Some(addr) => addr,
+            None => return Ok(None),
+        };
+
+        let keywords = topterms.extract_from_doc(NUM_SIMILARITY_KEYWORDS, addr);
+
+        if keywords.is_empty() || limit == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        // The source recipe is (very likely) the best match, so one
+        // more is fetched to make up for it being skipped
+        let (_total, found_ids, _after) = self.search(
+            searcher,
+            &keywords.into_boosted_query(1.0),
+            limit + 1,
+            Sort::By(FeaturesSort::Relevance),
+            None,
+        )?;
+
+        Ok(Some(
+            found_ids
+                .into_iter()
+                .filter(|id| *id != recipe_id)
+                .take(limit)
+                .collect(),
+        ))
+    }
+
+    /// Finds up to `limit` recipes with nutrition features (calories,
+    /// fat, carbohydrates and protein) closest to the given ones
+    ///
+    /// Features the source doesn't have are ignored. Recipes missing
+    /// a feature the source has are as far as they can be from it in
+    /// that dimension. The source recipe itself, if indexed, is very
+    /// likely among the results, so callers should skip it
+    pub fn similar_by_features(
+        &self,
+        searcher: &Searcher,
+        source_features: &Features,
+        limit: usize,
+    ) -> Result<Vec<RecipeId>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        type FeaturesDistanceKey = (NanLast<f64, Descending>, Reverse<RecipeId>);
+
+        let id_field = self.id;
+        let features_field = self.features_bincode;
+        let source = source_features.clone();
+
+        // Recipes with undecodable features get an infinite distance
+        let only_decoded = |_reader: &SegmentReader| {
+            |_sid: SegmentOrdinal, _doc: DocId, key: FeaturesDistanceKey, _asc: bool| {
+                (key.0).0.is_finite()
+            }
+        };
+
+        let top_collector =
+            TopCollector::<FeaturesDistanceKey, Descending, _>::new(limit, only_decoded)
+                .with_custom_scorer(move |reader: &SegmentReader| {
+                    let id_reader = reader
+                        .fast_fields()
+                        .u64(id_field)
+                        .expect("id is a u64 fast field");
+                    let features_reader = reader
+                        .fast_fields()
+                        .bytes(features_field)
+                        .expect("bytes field is indexed");
+                    let source = source.clone();
+
+                    move |doc_id: DocId| {
+                        let distance = bincode::deserialize(features_reader.get_bytes(doc_id))
+                            .map(|features| features_distance(&source, &features))
+                            .unwrap_or(f64::INFINITY);
+
+                        // Closest first
+                        (NanLast::new(-distance), Reverse(id_reader.get(doc_id)))
+                    }
+                });
+
+        let (_total, recipe_ids, _after) = self.render(searcher, &AllQuery, top_collector)?;
+
+        Ok(recipe_ids)
+    }
+
+    /// Builds a query matching recipes that contain the given ingredient
+    ///
+    /// The input goes through `analyzer`, which should be the one the
+    /// index uses for the ingredients field. Multi-word ingredients
+    /// become phrase queries so that "olive oil" doesn't match every
+    /// recipe that mentions oil
+    pub fn ingredient_query(
+        &self,
+        analyzer: &TextAnalyzer,
+        ingredient: &str,
+    ) -> Option<Box<dyn Query>> {
+        let mut terms = Vec::new();
+        analyzer.token_stream(ingredient).process(&mut |token| {
+            terms.push(Term::from_field_text(self.ingredients, &token.text));
+        });
+
+        match terms.len() {
+            0 => None,
+            1 => Some(Box::new(TermQuery::new(
+                terms.pop().expect("length has been checked"),
+                IndexRecordOption::Basic,
+            ))),
+            _ => Some(Box::new(PhraseQuery::new(terms))),
         }
     }
 
+    /// Finds recipes with names starting with the given input
+    ///
+    /// Shorter names are favored since, with everything else being
+    /// equal, they are a closer match to the input
+    pub fn suggest(&self, searcher: &Searcher, input: &str, limit: usize) -> Result<Vec<RecipeId>> {
+        let prefix: String = input
+            .trim_start()
+            .to_lowercase()
+            .chars()
+            .take(MAX_PREFIX_LEN)
+            .collect();
+
+        if prefix.chars().count() < MIN_PREFIX_LEN {
+            return Ok(Vec::new());
+        }
+
+        let query = TermQuery::new(
+            Term::from_field_text(self.name_prefixes, &prefix),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let (_total, recipe_ids, _after) = self.search(
+            searcher,
+            &query,
+            limit,
+            Sort::By(FeaturesSort::Relevance),
+            None,
+        )?;
+
+        Ok(recipe_ids)
+    }
+
+    /// Creates a Highlighter for the given query
+    pub fn highlighter(&self, searcher: &Searcher, query: &dyn Query) -> Result<Highlighter> {
+        Ok(Highlighter {
+            name: SnippetGenerator::create(searcher, query, self.name)?,
+            ingredients: SnippetGenerator::create(searcher, query, self.ingredients)?,
+            instructions: SnippetGenerator::create(searcher, query, self.instructions)?,
+        })
+    }
+
+    /// Aggregates the features of every recipe matching the query
+    /// and counts how many of them fit each diet, in a single pass
     pub fn aggregate_features(
         &self,
         searcher: &Searcher,
         query: &dyn Query,
         agg_query: FeaturesAggregationQuery,
-    ) -> Result<FeaturesAggregationResult> {
+    ) -> Result<(FeaturesAggregationResult, DietFacets)> {
+        let (agg, diet_facets, _visited) =
+            self.aggregate_features_sampled(searcher, query, agg_query, 1)?;
+        Ok((agg, diet_facets))
+    }
+
+    /// Like `aggregate_features`, but only looks at one of every
+    /// `every` matching recipes. The counts are of the sample, not
+    /// estimates of the full result set, and the sample size is
+    /// yielded alongside them
+    pub fn aggregate_features_sampled(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        agg_query: FeaturesAggregationQuery,
+        every: usize,
+    ) -> Result<(FeaturesAggregationResult, DietFacets, usize)> {
         let features_field = self.features_bincode;
         let collector =
             AggregableCollector::<Features, _>::new(agg_query, move |reader: &SegmentReader| {
@@ -145,40 +657,51 @@ impl RecipeIndex {
                 move |doc| bincode::deserialize(features_reader.get_bytes(doc)).ok()
             });
 
-        searcher.search(query, &collector)
+        let diets = [
+            ("diet_lowcarb", self.features.diet_lowcarb),
+            ("diet_vegetarian", self.features.diet_vegetarian),
+            ("diet_vegan", self.features.diet_vegan),
+            ("diet_keto", self.features.diet_keto),
+            ("diet_paleo", self.features.diet_paleo),
+        ];
+
+        let diet_collector =
+            DietFacetsCollector(diets.iter().map(|(_name, field)| *field).collect());
+
+        let (agg, diet_counts, visited) = searcher.search(
+            query,
+            &SampledCollector::new((collector, diet_collector, Count), every),
+        )?;
+
+        let diet_facets = diets
+            .iter()
+            .zip(diet_counts)
+            .map(|((name, _field), count)| (name.to_string(), count))
+            .collect();
+
+        Ok((agg, diet_facets, visited))
     }
 
-    fn render<T, C>(
+    fn render<K, C>(
         &self,
         searcher: &Searcher,
         query: &dyn Query,
         collector: C,
     ) -> Result<(usize, Vec<RecipeId>, Option<After>)>
     where
-        T: 'static + Sync + Send + Copy + AsAfter,
-        C: Collector<Fruit = CollectionResult<T>>,
+        K: SortKey,
+        C: Collector<Fruit = CollectionResult<K>>,
     {
         let result = searcher.search(query, &collector)?;
-        let mut recipe_ids = Vec::with_capacity(result.items.len());
 
-        let has_next = result.has_next();
-        let last = result
+        let recipe_ids = result
             .items
-            .into_iter()
-            .flat_map(|(score, addr)| {
-                searcher.doc(addr).map(|doc| {
-                    if let Some(&Value::U64(recipe_id)) = doc.get_first(self.id) {
-                        recipe_ids.push(recipe_id);
-                        (score, recipe_id)
-                    } else {
-                        panic!("Found doc with non-U64 id field");
-                    }
-                })
-            })
-            .last();
+            .iter()
+            .map(|(key, _addr)| key.recipe_id())
+            .collect();
 
-        let cursor = if has_next {
-            last.map(|(score, id)| score.as_after(id))
+        let cursor = if result.has_next() {
+            result.items.last().map(|(key, _addr)| key.as_after())
         } else {
             None
         };
@@ -187,18 +710,268 @@ impl RecipeIndex {
     }
 }
 
-impl From<&mut SchemaBuilder> for RecipeIndex {
-    fn from(builder: &mut SchemaBuilder) -> Self {
-        RecipeIndex {
-            id: builder.add_u64_field(FIELD_ID, STORED | FAST),
+/// Scrambles the recipe id with the seed, yielding the same key
+/// for the same input regardless of platform or rust version
+/// (splitmix64's finalizer)
+fn shuffle_key(seed: u64, recipe_id: RecipeId) -> u64 {
+    let mut z = seed ^ recipe_id.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
 
-            name: builder.add_text_field(FIELD_NAME, TEXT),
-            ingredients: builder.add_text_field(FIELD_INGREDIENTS, TEXT),
-            instructions: builder.add_text_field(FIELD_INSTRUCTIONS, TEXT),
+/// Euclidean distance between the nutrition features of two
+/// recipes, with every difference scaled to the [0, 1] range so
+/// that no feature outweighs the others
+fn features_distance(source: &Features, other: &Features) -> f64 {
+    let dimensions = [
+        (
+            source.calories.map(f64::from),
+            other.calories.map(f64::from),
+            CALORIES_SCALE,
+        ),
+        (
+            source.fat_content.map(f64::from),
+            other.fat_content.map(f64::from),
+            NUTRIENT_SCALE,
+        ),
+        (
+            source.carb_content.map(f64::from),
+            other.carb_content.map(f64::from),
+            NUTRIENT_SCALE,
+        ),
+        (
+            source.protein_content.map(f64::from),
+            other.protein_content.map(f64::from),
+            NUTRIENT_SCALE,
+        ),
+    ];
 
-            features_bincode: builder.add_bytes_field(FIELD_FEATURES_BINCODE, FAST),
-            features: Features::create_schema(builder, INDEXED | FAST),
+    dimensions
+        .iter()
+        .filter_map(|(wanted, found, scale)| {
+            let wanted = (*wanted)?;
+            let diff = found
+                .map(|found| ((wanted - found) / scale).abs().min(1.0))
+                .unwrap_or(1.0);
+            Some(diff * diff)
+        })
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Batches of recipe ids, as yielded by `RecipeIndex::search_all`
+pub struct SearchAll<'a> {
+    recipe_index: &'a RecipeIndex,
+    searcher: &'a Searcher,
+    query: &'a dyn Query,
+    sort: Sort,
+    batch_size: usize,
+    after: Option<After>,
+    done: bool,
+}
+
+impl Iterator for SearchAll<'_> {
+    type Item = Result<Vec<RecipeId>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.recipe_index.search(
+            self.searcher,
+            self.query,
+            self.batch_size,
+            self.sort.clone(),
+            self.after.take(),
+        );
+
+        match result {
+            Ok((_total, recipe_ids, after)) => {
+                self.after = after;
+                self.done = self.after.is_none();
+
+                if recipe_ids.is_empty() {
+                    None
+                } else {
+                    Some(Ok(recipe_ids))
+                }
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Generates highlighted excerpts of recipes that match a query
+///
+/// The text fields aren't stored in the index, so the text comes
+/// from the recipe itself.
+pub struct Highlighter {
+    name: SnippetGenerator,
+    ingredients: SnippetGenerator,
+    instructions: SnippetGenerator,
+}
+
+impl Highlighter {
+    /// Yields an html snippet of the first recipe field (name, then
+    /// ingredients, then instructions) that matches the query
+    pub fn highlight(&self, recipe: &Recipe) -> Option<String> {
+        let snippet = |generator: &SnippetGenerator, text: &str| {
+            let snippet = generator.snippet(text);
+            if snippet.highlighted().is_empty() {
+                None
+            } else {
+                Some(snippet.to_html())
+            }
+        };
+
+        snippet(&self.name, &recipe.name)
+            .or_else(|| snippet(&self.ingredients, &recipe.ingredients.join("\n")))
+            .or_else(|| snippet(&self.instructions, &recipe.instructions.join("\n")))
+    }
+}
+
+/// Wraps a collector so that it only sees one of every `every`
+/// documents it would otherwise collect
+struct SampledCollector<C> {
+    inner: C,
+    every: usize,
+}
+
+impl<C> SampledCollector<C> {
+    fn new(inner: C, every: usize) -> Self {
+        Self {
+            inner,
+            every: every.max(1),
+        }
+    }
+}
+
+struct SampledSegmentCollector<C> {
+    inner: C,
+    every: usize,
+    seen: usize,
+}
+
+impl<C: Collector> Collector for SampledCollector<C> {
+    type Fruit = C::Fruit;
+    type Child = SampledSegmentCollector<C::Child>;
+
+    fn for_segment(
+        &self,
+        segment_ord: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(SampledSegmentCollector {
+            inner: self.inner.for_segment(segment_ord, segment_reader)?,
+            every: self.every,
+            seen: 0,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.inner.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> Result<Self::Fruit> {
+        self.inner.merge_fruits(fruits)
+    }
+}
+
+impl<C: SegmentCollector> SegmentCollector for SampledSegmentCollector<C> {
+    type Fruit = C::Fruit;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        if self.seen % self.every == 0 {
+            self.inner.collect(doc, score);
         }
+        self.seen += 1;
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.inner.harvest()
+    }
+}
+
+// Reads the diet fast fields instead of decoding the features, so
+// missing values (which read as NaN) aren't counted either
+struct DietFacetsCollector(Vec<Field>);
+
+struct DietFacetsSegmentCollector {
+    readers: Vec<DynamicFastFieldReader<f64>>,
+    counts: Vec<u64>,
+}
+
+impl Collector for DietFacetsCollector {
+    type Fruit = Vec<u64>;
+    type Child = DietFacetsSegmentCollector;
+
+    fn for_segment(
+        &self,
+        _segment_ord: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let readers = self
+            .0
+            .iter()
+            .map(|field| segment_reader.fast_fields().f64(*field))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(DietFacetsSegmentCollector {
+            counts: vec![0; readers.len()],
+            readers,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<Self::Fruit>) -> Result<Self::Fruit> {
+        let mut iter = fruits.into_iter();
+
+        // Every segment yields as many counts as there are fields, so
+        // the first one can be reused instead of starting from scratch.
+        // Which also means there's nothing to merge in the (very
+        // common) case of a single segment
+        let mut merged = iter.next().unwrap_or_else(|| vec![0; self.0.len()]);
+
+        for counts in iter {
+            for (total, count) in merged.iter_mut().zip(counts) {
+                *total += count;
+            }
+        }
+
+        Ok(merged)
+    }
+}
+
+impl SegmentCollector for DietFacetsSegmentCollector {
+    type Fruit = Vec<u64>;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        for (reader, count) in self.readers.iter().zip(self.counts.iter_mut()) {
+            if reader.get(doc) > 0.0 {
+                *count += 1;
+            }
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.counts
+    }
+}
+
+impl From<&mut SchemaBuilder> for RecipeIndex {
+    fn from(builder: &mut SchemaBuilder) -> Self {
+        RecipeIndex::with_analysis(builder, TextAnalysis::Default)
     }
 }
 
@@ -219,6 +992,8 @@ impl TryFrom<&Schema> for RecipeIndex {
             ingredients: get_field(FIELD_INGREDIENTS)?,
             instructions: get_field(FIELD_INSTRUCTIONS)?,
 
+            name_prefixes: get_field(FIELD_NAME_PREFIXES)?,
+
             features_bincode: get_field(FIELD_FEATURES_BINCODE)?,
             features: FeaturesFilterFields::try_from(schema)?,
         })
@@ -230,138 +1005,356 @@ pub enum After {
     Relevance(Score, RecipeId),
     F64Field(f64, RecipeId),
     U64Field(u64, RecipeId),
+    /// The primary and secondary values of a compound sort, as
+    /// seen by `search_then`
+    Compound(CompoundValue, CompoundValue, RecipeId),
 }
 
-impl From<(Score, RecipeId)> for After {
-    fn from(src: (Score, RecipeId)) -> Self {
-        After::Relevance(src.0, src.1)
-    }
+/// A value of a compound sort, read so that greater is better
+///
+/// Integers are kept as such: as f64s, big ones (like random keys)
+/// would collapse into ties
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum CompoundValue {
+    /// Relevance and f64 fields, negated when ascending
+    Float(f64),
+    /// Integer fields and random keys, mapped to an order-preserving
+    /// u64 and bit-flipped when ascending
+    Int(u64),
 }
 
-impl From<(f64, RecipeId)> for After {
-    fn from(src: (f64, RecipeId)) -> Self {
-        After::F64Field(src.0, src.1)
+impl CompoundValue {
+    /// The bits cursors carry. Which variant they stand for depends
+    /// on the sort, see `RecipeIndex::after_from_cursor`
+    pub fn to_bits(self) -> u64 {
+        match self {
+            CompoundValue::Float(value) => value.to_bits(),
+            CompoundValue::Int(value) => value,
+        }
     }
 }
 
-impl From<(u64, RecipeId)> for After {
-    fn from(src: (u64, RecipeId)) -> Self {
-        After::U64Field(src.0, src.1)
+/// Compound sorts are always collected in descending order, with
+/// ascending values flipped to match. Ties are broken by the lowest
+/// recipe id, as usual
+type CompoundKey = (
+    NanLast<CompoundValue, Descending>,
+    NanLast<CompoundValue, Descending>,
+    Reverse<RecipeId>,
+);
+
+impl SortKey for CompoundKey {
+    fn recipe_id(&self) -> RecipeId {
+        (self.2).0
+    }
+
+    fn as_after(&self) -> After {
+        After::Compound((self.0).0, (self.1).0, (self.2).0)
+    }
+
+    fn from_after(after: After) -> Self {
+        match after {
+            After::Compound(primary, secondary, id) => {
+                (NanLast::new(primary), NanLast::new(secondary), Reverse(id))
+            }
+            rest => panic!("Can't handle {:?}", rest),
+        }
     }
 }
 
-pub trait AsAfter {
-    fn as_after(self, id: RecipeId) -> After;
+#[derive(Clone, Copy)]
+enum SortSource {
+    Relevance,
+    U64(Field),
+    I64(Field),
+    F64(Field),
+    // The seed and the id field
+    Random(u64, Field),
 }
 
-impl AsAfter for u64 {
-    fn as_after(self, id: RecipeId) -> After {
-        (self, id).into()
-    }
+/// Where a value in a compound sort comes from, and its direction
+#[derive(Clone, Copy)]
+struct SortValue {
+    source: SortSource,
+    ascending: bool,
+}
+
+enum SegmentSortSource {
+    Relevance,
+    U64(DynamicFastFieldReader<u64>),
+    I64(DynamicFastFieldReader<i64>),
+    F64(DynamicFastFieldReader<f64>),
+    Random(u64, DynamicFastFieldReader<u64>),
 }
 
-impl AsAfter for f64 {
-    fn as_after(self, id: RecipeId) -> After {
-        (self, id).into()
+struct SegmentSortValue {
+    source: SegmentSortSource,
+    ascending: bool,
+}
+
+impl SortValue {
+    fn value_from_bits(&self, bits: u64) -> CompoundValue {
+        match self.source {
+            SortSource::Relevance | SortSource::F64(_) => {
+                CompoundValue::Float(f64::from_bits(bits))
+            }
+            SortSource::U64(_) | SortSource::I64(_) | SortSource::Random(..) => {
+                CompoundValue::Int(bits)
+            }
+        }
+    }
+
+    fn for_segment(&self, reader: &SegmentReader) -> SegmentSortValue {
+        let source = match self.source {
+            SortSource::Relevance => SegmentSortSource::Relevance,
+            SortSource::U64(field) => SegmentSortSource::U64(
+                reader
+                    .fast_fields()
+                    .u64(field)
+                    .expect("Field is not a fast u64 field"),
+            ),
+            SortSource::I64(field) => SegmentSortSource::I64(
+                reader
+                    .fast_fields()
+                    .i64(field)
+                    .expect("Field is not a fast i64 field"),
+            ),
+            SortSource::F64(field) => SegmentSortSource::F64(
+                reader
+                    .fast_fields()
+                    .f64(field)
+                    .expect("Field is not a fast f64 field"),
+            ),
+            SortSource::Random(seed, id_field) => SegmentSortSource::Random(
+                seed,
+                reader
+                    .fast_fields()
+                    .u64(id_field)
+                    .expect("id is a u64 fast field"),
+            ),
+        };
+
+        SegmentSortValue {
+            source,
+            ascending: self.ascending,
+        }
     }
 }
 
-impl AsAfter for f32 {
-    fn as_after(self, id: RecipeId) -> After {
-        (self, id).into()
+impl SegmentSortValue {
+    fn get(&self, doc_id: DocId, score: Score) -> CompoundValue {
+        let value = match &self.source {
+            SegmentSortSource::Relevance => CompoundValue::Float(f64::from(score)),
+            SegmentSortSource::U64(reader) => CompoundValue::Int(reader.get(doc_id)),
+            // Flipping the sign bit keeps the order: i64::MIN becomes 0
+            SegmentSortSource::I64(reader) => {
+                CompoundValue::Int((reader.get(doc_id) as u64) ^ (1 << 63))
+            }
+            SegmentSortSource::F64(reader) => CompoundValue::Float(reader.get(doc_id)),
+            SegmentSortSource::Random(seed, ids) => {
+                CompoundValue::Int(shuffle_key(*seed, ids.get(doc_id)))
+            }
+        };
+
+        match value {
+            CompoundValue::Float(value) if self.ascending => CompoundValue::Float(-value),
+            CompoundValue::Int(value) if self.ascending => CompoundValue::Int(!value),
+            value => value,
+        }
     }
 }
 
-#[derive(Clone)]
-pub struct PaginationCondition<T> {
-    id_reader: DynamicFastFieldReader<RecipeId>,
-    ref_id: RecipeId,
-    ref_score: T,
+/// The key recipes are sorted by: the actual value being sorted
+/// by and the recipe id, so that there are no ties
+pub trait SortKey: 'static + Copy + Send + Sync + PartialOrd {
+    fn recipe_id(&self) -> RecipeId;
+    fn as_after(&self) -> After;
+    fn from_after(after: After) -> Self;
 }
 
-impl<T> CheckCondition<T> for PaginationCondition<T>
-where
-    T: 'static + PartialOrd + Clone,
-{
-    fn check(&self, _sid: SegmentOrdinal, doc_id: DocId, score: T, ascending: bool) -> bool {
-        let recipe_id = self.id_reader.get(doc_id);
-        match self.ref_score.partial_cmp(&score) {
-            Some(Ordering::Greater) => !ascending,
-            Some(Ordering::Less) => ascending,
-            Some(Ordering::Equal) => self.ref_id < recipe_id,
-            None => false,
+macro_rules! impl_sort_key {
+    ($type: ty, $variant: ident) => {
+        impl SortKey for ($type, RecipeId) {
+            fn recipe_id(&self) -> RecipeId {
+                self.1
+            }
+
+            fn as_after(&self) -> After {
+                After::$variant(self.0, self.1)
+            }
+
+            fn from_after(after: After) -> Self {
+                match after {
+                    After::$variant(score, id) => (score, id),
+                    rest => panic!("Can't handle {:?}", rest),
+                }
+            }
         }
-    }
+
+        impl SortKey for ($type, Reverse<RecipeId>) {
+            fn recipe_id(&self) -> RecipeId {
+                (self.1).0
+            }
+
+            fn as_after(&self) -> After {
+                After::$variant(self.0, (self.1).0)
+            }
+
+            fn from_after(after: After) -> Self {
+                match after {
+                    After::$variant(score, id) => (score, Reverse(id)),
+                    rest => panic!("Can't handle {:?}", rest),
+                }
+            }
+        }
+    };
 }
 
-#[derive(Clone)]
-pub struct Paginator<T>(Field, bool, RecipeId, T);
+impl_sort_key!(u64, U64Field);
 
-impl Paginator<u64> {
-    pub fn new_u64(field: Field, after: After) -> Self {
-        match after {
-            After::U64Field(score, id) => Paginator(field, false, id, score),
-            rest => panic!("Can't handle {:?}", rest),
+macro_rules! impl_nan_last_sort_key {
+    ($type: ty, $variant: ident) => {
+        impl SortKey for (NanLast<$type, Ascending>, RecipeId) {
+            fn recipe_id(&self) -> RecipeId {
+                self.1
+            }
+
+            fn as_after(&self) -> After {
+                After::$variant((self.0).0, self.1)
+            }
+
+            fn from_after(after: After) -> Self {
+                match after {
+                    After::$variant(score, id) => (NanLast::new(score), id),
+                    rest => panic!("Can't handle {:?}", rest),
+                }
+            }
         }
+
+        impl SortKey for (NanLast<$type, Descending>, Reverse<RecipeId>) {
+            fn recipe_id(&self) -> RecipeId {
+                (self.1).0
+            }
+
+            fn as_after(&self) -> After {
+                After::$variant((self.0).0, (self.1).0)
+            }
+
+            fn from_after(after: After) -> Self {
+                match after {
+                    After::$variant(score, id) => (NanLast::new(score), Reverse(id)),
+                    rest => panic!("Can't handle {:?}", rest),
+                }
+            }
+        }
+    };
+}
+
+impl_nan_last_sort_key!(Score, Relevance);
+impl_nan_last_sort_key!(f64, F64Field);
+
+/// Where NaN goes relative to every other value for an ordering:
+/// it's always the worst, so it ends up last
+pub trait NanPlacement: 'static {
+    const NAN: Ordering;
+}
+
+impl NanPlacement for Ascending {
+    const NAN: Ordering = Ordering::Greater;
+}
+
+impl NanPlacement for Descending {
+    const NAN: Ordering = Ordering::Less;
+}
+
+/// A float sort value that places NaN last for the `O` ordering
+///
+/// A plain NaN can't be compared, so it would drop whole sort keys
+/// out of pagination. Here NaN is worse than any other value and
+/// equal to itself, leaving ties to the rest of the key just like
+/// with any other value: keys made out of it are totally ordered
+pub struct NanLast<T, O>(pub T, PhantomData<fn() -> O>);
+
+impl<T, O> NanLast<T, O> {
+    pub fn new(value: T) -> Self {
+        NanLast(value, PhantomData)
     }
 }
 
-impl Paginator<f64> {
-    pub fn new_f64(field: Field, after: After) -> Self {
-        match after {
-            After::F64Field(score, id) => Paginator(field, false, id, score),
-            rest => panic!("Can't handle {:?}", rest),
-        }
+impl<T: Copy, O> Clone for NanLast<T, O> {
+    fn clone(&self) -> Self {
+        *self
     }
 }
 
-impl Paginator<f32> {
-    pub fn new(field: Field, after: After) -> Self {
-        match after {
-            After::Relevance(score, id) => Paginator(field, false, id, score),
-            rest => panic!("Can't handle {:?}", rest),
+impl<T: Copy, O> Copy for NanLast<T, O> {}
+
+impl<T: PartialOrd, O: NanPlacement> PartialOrd for NanLast<T, O> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (is_nan(&self.0), is_nan(&other.0)) {
+            (false, false) => self.0.partial_cmp(&other.0),
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(O::NAN),
+            (false, true) => Some(O::NAN.reverse()),
         }
     }
 }
 
-pub trait AsPaginator<T> {
-    fn as_paginator(self, field: Field) -> Paginator<T>;
+impl<T: PartialOrd, O: NanPlacement> PartialEq for NanLast<T, O> {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
 }
 
-impl AsPaginator<u64> for After {
-    fn as_paginator(self, field: Field) -> Paginator<u64> {
-        Paginator::new_u64(field, self)
+impl<O: NanPlacement> FastFieldScore for NanLast<f64, O> {
+    type Reader = DynamicFastFieldReader<f64>;
+
+    fn fast_field_reader(reader: &SegmentReader, field: Field) -> Self::Reader {
+        f64::fast_field_reader(reader, field)
     }
-}
 
-impl AsPaginator<f64> for After {
-    fn as_paginator(self, field: Field) -> Paginator<f64> {
-        Paginator::new_f64(field, self)
+    fn get(reader: &Self::Reader, doc_id: DocId) -> Self {
+        NanLast::new(reader.get(doc_id))
     }
 }
 
-impl AsPaginator<f32> for After {
-    fn as_paginator(self, field: Field) -> Paginator<f32> {
-        Paginator::new(field, self)
+fn is_nan<T: PartialOrd>(value: &T) -> bool {
+    value.partial_cmp(value).is_none()
+}
+
+/// A condition that only accepts recipes that come after the given
+/// cursor (if any)
+#[derive(Clone)]
+pub struct Paginator<K>(Option<K>);
+
+impl<K: SortKey> From<Option<After>> for Paginator<K> {
+    fn from(after: Option<After>) -> Self {
+        Paginator(after.map(K::from_after))
     }
 }
 
-impl<T> ConditionForSegment<T> for Paginator<T>
-where
-    T: 'static + PartialOrd + Copy,
-{
-    type Type = PaginationCondition<T>;
+impl<K: SortKey> ConditionForSegment<K> for Paginator<K> {
+    type Type = Self;
 
-    fn for_segment(&self, reader: &SegmentReader) -> Self::Type {
-        let id_reader = reader
-            .fast_fields()
-            .u64(self.0)
-            .expect("id field is indexed with the FAST flag");
+    fn for_segment(&self, _reader: &SegmentReader) -> Self::Type {
+        self.clone()
+    }
+}
 
-        PaginationCondition {
-            id_reader,
-            ref_id: self.2,
-            ref_score: self.3,
+impl<K: SortKey> CheckCondition<K> for Paginator<K> {
+    fn check(&self, _sid: SegmentOrdinal, _doc_id: DocId, key: K, ascending: bool) -> bool {
+        if let Some(after) = &self.0 {
+            // Float values are wrapped in `NanLast`, so NaN keys are
+            // compared too: they come after every other value and are
+            // ordered by recipe id amongst themselves
+            match key.partial_cmp(after) {
+                Some(Ordering::Greater) => ascending,
+                Some(Ordering::Less) => !ascending,
+                // The cursor itself
+                _ => false,
+            }
+        } else {
+            true
         }
     }
 }
@@ -1,3 +1,4 @@
 pub mod database;
 pub mod index;
 pub mod model;
+pub mod spelling;
@@ -1,25 +1,45 @@
-use std::{convert::TryFrom, env, io, path::Path, str::FromStr, sync::Arc};
+use std::{
+    collections::hash_map::DefaultHasher,
+    convert::TryFrom,
+    env,
+    hash::{Hash, Hasher},
+    io,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
 
-use serde::Serialize;
-use tique::QueryParser;
+use futures::stream::{self, StreamExt};
+use rand::{seq::IteratorRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use tique::{topterms::TopTerms, QueryParser};
 use uuid::Uuid;
 
 use actix_web::{
-    http::StatusCode, middleware::Logger, web, App, HttpResponse, HttpServer, Result as ActixResult,
+    error::{InternalError, JsonPayloadError},
+    http::{header, StatusCode},
+    middleware::Logger,
+    web::{self, Bytes},
+    App, HttpResponse, HttpServer, Result as ActixResult,
 };
 
 use tantivy::{
-    query::{AllQuery, BooleanQuery, Occur, Query},
-    Index, IndexReader, Result,
+    query::{AllQuery, BooleanQuery, Explanation, Occur, Query},
+    tokenizer::TextAnalyzer,
+    DocId, Index, IndexReader, ReloadPolicy, Result, Searcher, SegmentId, TantivyError,
 };
 
 use cantine::{
     database::DatabaseReader,
-    index::{After, RecipeIndex},
+    index::{After, Highlighter, RecipeIndex},
     model::{
-        FeaturesAggregationQuery, FeaturesAggregationResult, Recipe, RecipeCard, RecipeId,
-        RecipeInfo, SearchCursor, SearchQuery, SearchResult, Sort,
+        DietFacets, FeaturesAggregationQuery, FeaturesAggregationResult, FeaturesFilterQuery,
+        FeaturesSort, Recipe, RecipeCard, RecipeId, RecipeInfo, SearchCursor, SearchQuery,
+        SearchResult, SearchTimings, Sort, Suggestion,
     },
+    spelling::SpellChecker,
 };
 
 type RecipeDatabase = Arc<DatabaseReader<Recipe>>;
@@ -39,25 +59,201 @@ pub async fn recipe(
     }
 }
 
+#[derive(Deserialize)]
+pub struct SimilarParams {
+    limit: Option<u8>,
+}
+
+pub async fn similar(
+    uuid: web::Path<Uuid>,
+    params: web::Query<SimilarParams>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let recipe_id = if let Some(id) = database.id_for_uuid(&uuid) {
+        *id
+    } else {
+        return Ok(HttpResponse::new(StatusCode::NOT_FOUND));
+    };
+
+    let limit = params.limit.unwrap_or(10) as usize;
+    let found = web::block(move || state.similar(recipe_id, limit)).await?;
+
+    let similar_ids = if let Some(ids) = found {
+        ids
+    } else {
+        return Ok(HttpResponse::new(StatusCode::NOT_FOUND));
+    };
+
+    let (recipes, _partial) = fetch_recipes(&database, &similar_ids)?;
+    let items = recipes
+        .into_iter()
+        .map(RecipeCard::from)
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
+/// How many matches a filtered random pick samples from
+const RANDOM_SAMPLE_SIZE: usize = 100;
+
+#[derive(Deserialize)]
+pub struct RandomParams {
+    /// JSON-encoded `FeaturesFilterQuery`
+    filter: Option<String>,
+}
+
+pub async fn random(
+    params: web::Query<RandomParams>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let recipe_id = if let Some(filter) = &params.filter {
+        let filter: FeaturesFilterQuery = match serde_json::from_str(filter) {
+            Ok(filter) => filter,
+            Err(_) => return Ok(HttpResponse::new(StatusCode::BAD_REQUEST)),
+        };
+
+        let found = web::block(move || state.filtered(filter, RANDOM_SAMPLE_SIZE)).await?;
+        found.into_iter().choose(&mut thread_rng())
+    } else {
+        database.ids().copied().choose(&mut thread_rng())
+    };
+
+    let recipe: Option<Recipe> = match recipe_id {
+        Some(recipe_id) => database.find_by_id(recipe_id).transpose()?,
+        None => None,
+    };
+
+    if let Some(recipe) = recipe {
+        Ok(HttpResponse::Ok().json(RecipeCard::from(recipe)))
+    } else {
+        Ok(HttpResponse::new(StatusCode::NOT_FOUND))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SuggestParams {
+    q: String,
+    limit: Option<u8>,
+}
+
+pub async fn suggest(
+    params: web::Query<SuggestParams>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let limit = params.limit.unwrap_or(10) as usize;
+    let recipe_ids = web::block(move || state.suggest(&params.q, limit)).await?;
+
+    let (recipes, _partial) = fetch_recipes(&database, &recipe_ids)?;
+    let items = recipes
+        .into_iter()
+        .map(|recipe| Suggestion {
+            name: recipe.name,
+            uuid: recipe.uuid,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExplainRequest {
+    uuid: Uuid,
+    query: SearchQuery,
+}
+
+pub async fn explain(
+    request: web::Json<ExplainRequest>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let recipe_id = if let Some(id) = database.id_for_uuid(&request.uuid) {
+        *id
+    } else {
+        return Ok(HttpResponse::new(StatusCode::NOT_FOUND));
+    };
+
+    let request = request.into_inner();
+    let found = web::block(move || state.explain(&request.query, recipe_id)).await?;
+
+    if let Some(explanation) = found {
+        Ok(HttpResponse::Ok().json(explanation))
+    } else {
+        Ok(HttpResponse::new(StatusCode::NOT_FOUND))
+    }
+}
+
+#[derive(Serialize)]
+pub struct Health {
+    pub index_docs: u64,
+    pub db_records: usize,
+}
+
+pub async fn health(
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let index_docs = state.num_docs();
+
+    // Any record will do, what matters is that it can be decoded
+    let db_ok = database.ids().next().map_or(true, |id| {
+        database
+            .find_by_id(*id)
+            .map_or(false, |recipe: io::Result<Recipe>| recipe.is_ok())
+    });
+
+    if db_ok {
+        Ok(HttpResponse::Ok().json(Health {
+            index_docs,
+            db_records: database.len(),
+        }))
+    } else {
+        Ok(HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE))
+    }
+}
+
 #[derive(Serialize, Clone)]
 pub struct IndexInfo {
     pub total_recipes: u64,
     pub features: FeaturesAggregationResult,
-    pub sort: Vec<Sort>,
+    pub diet_facets: DietFacets,
+    pub sort: Vec<FeaturesSort>,
 }
 
-pub async fn index_info(info: web::Data<IndexInfo>) -> ActixResult<HttpResponse> {
-    Ok(HttpResponse::Ok().json(info.get_ref()))
+/// The facets of a search next to the index-wide ones they're a
+/// part of
+pub struct BlendedFacets {
+    pub features: FeaturesAggregationResult,
+    pub diet_facets: DietFacets,
+    /// Whether `features` and `diet_facets` only account for a
+    /// sample of the recipes found
+    pub approximate: bool,
+    pub info: Arc<IndexInfo>,
 }
 
-fn cursor_to_after(database: &RecipeDatabase, cursor: &SearchCursor) -> Option<After> {
-    database
-        .id_for_uuid(&Uuid::from_bytes(*cursor.uuid()))
-        .map(|id| match &cursor {
-            SearchCursor::Relevance(score, _) => After::Relevance(*score, *id),
-            SearchCursor::U64Field(score, _) => After::U64Field(*score, *id),
-            SearchCursor::F64Field(score, _) => After::F64Field(*score, *id),
-        })
+pub async fn index_info(state: web::Data<Arc<SearchState>>) -> ActixResult<HttpResponse> {
+    let info = web::block(move || state.index_info()).await?;
+    Ok(HttpResponse::Ok().json(&*info))
+}
+
+// Cursors are only valid for the sorts that created them
+fn cursor_to_after(
+    database: &RecipeDatabase,
+    recipe_index: &RecipeIndex,
+    query: &SearchQuery,
+    cursor: &SearchCursor,
+) -> Option<After> {
+    let id = database.id_for_uuid(&Uuid::from_bytes(*cursor.uuid()))?;
+
+    recipe_index.after_from_cursor(
+        cursor,
+        query.sort.clone().unwrap_or_default(),
+        query.sort_secondary.clone(),
+        *id,
+    )
 }
 
 pub async fn search(
@@ -65,8 +261,12 @@ pub async fn search(
     state: web::Data<Arc<SearchState>>,
     database: web::Data<RecipeDatabase>,
 ) -> ActixResult<HttpResponse> {
+    if query.num_items == Some(0) {
+        return Ok(HttpResponse::new(StatusCode::BAD_REQUEST));
+    }
+
     let after = if let Some(cursor) = &query.after {
-        let checked_after = cursor_to_after(&database, &cursor);
+        let checked_after = cursor_to_after(&database, &state.recipe_index, &query, &cursor);
         if checked_after.is_none() {
             return Ok(HttpResponse::new(StatusCode::BAD_REQUEST));
         }
@@ -75,78 +275,363 @@ pub async fn search(
         None
     };
 
-    let (total_found, recipe_ids, after, agg) =
+    let (total_found, recipe_ids, after, agg, highlighter, did_you_mean, timings, etag) =
         web::block(move || -> Result<ExecuteResult> { state.search(query.0, after) }).await?;
 
-    let num_results = recipe_ids.len();
-    let mut items = Vec::with_capacity(num_results);
-    for recipe_id in recipe_ids {
-        let recipe: Recipe = database
-            .find_by_id(recipe_id)
-            .expect("item in the index always present in the db")?;
-        items.push(RecipeCard::from(recipe));
+    let (recipes, partial) = fetch_recipes(&database, &recipe_ids)?;
+
+    // The cursor points at the last recipe found in the index, so
+    // there's no way to paginate if the database doesn't have it
+    let last_uuid = match (recipes.last(), recipe_ids.last()) {
+        (Some(recipe), Some(last_id)) if recipe.recipe_id == *last_id => Some(recipe.uuid),
+        _ => None,
+    };
+
+    let mut items = Vec::with_capacity(recipes.len());
+    for recipe in recipes {
+        let snippet = highlighter
+            .as_ref()
+            .and_then(|highlighter| highlighter.highlight(&recipe));
+
+        let mut card = RecipeCard::from(recipe);
+        card.snippet = snippet;
+        items.push(card);
     }
 
-    let next = after.map(|after| {
-        let last_uuid = &items[num_results - 1].uuid;
+    let next = after.and_then(|after| {
+        let last_uuid = last_uuid?;
 
-        match after {
+        Some(match after {
             After::Relevance(score, _) => SearchCursor::Relevance(score, *last_uuid.as_bytes()),
             After::U64Field(score, _) => SearchCursor::U64Field(score, *last_uuid.as_bytes()),
             After::F64Field(score, _) => SearchCursor::F64Field(score, *last_uuid.as_bytes()),
+            After::Compound(primary, secondary, _) => SearchCursor::Compound(
+                primary.to_bits(),
+                secondary.to_bits(),
+                *last_uuid.as_bytes(),
+            ),
+        })
+    });
+
+    let (agg, diet_facets, agg_approximate, total_agg, total_diet_facets) = match agg {
+        Some(blended) => (
+            Some(blended.features),
+            Some(blended.diet_facets),
+            blended.approximate,
+            Some(blended.info.features.clone()),
+            Some(blended.info.diet_facets.clone()),
+        ),
+        None => (None, None, false, None, None),
+    };
+
+    Ok(HttpResponse::Ok()
+        .header(header::ETAG, etag)
+        .json(SearchResult {
+            items,
+            total_found,
+            agg,
+            diet_facets,
+            agg_approximate,
+            total_agg,
+            total_diet_facets,
+            did_you_mean,
+            partial,
+            timings,
+            next,
+        }))
+}
+
+/// Streams every recipe matching the query as newline-delimited
+/// JSON `RecipeCard`s, up to the server's `EXPORT_LIMIT`
+pub async fn export(
+    query: web::Json<SearchQuery>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    // Exports always start from the top and compound sorts aren't
+    // supported by `search_all`
+    if query.after.is_some() || query.sort_secondary.is_some() {
+        return Ok(HttpResponse::new(StatusCode::BAD_REQUEST));
+    }
+
+    let recipe_ids = web::block(move || -> Result<Vec<RecipeId>> { state.export(query.0) }).await?;
+
+    let batches: Vec<Vec<RecipeId>> = recipe_ids
+        .chunks(EXPORT_BATCH_SIZE)
+        .map(<[RecipeId]>::to_vec)
+        .collect();
+
+    let lines = stream::iter(batches).map(move |batch| -> io::Result<Bytes> {
+        let (recipes, _partial) = fetch_recipes(&database, &batch)?;
+
+        let mut buf = Vec::new();
+        for recipe in recipes {
+            serde_json::to_writer(&mut buf, &RecipeCard::from(recipe))?;
+            buf.push(b'\n');
         }
+
+        Ok(Bytes::from(buf))
     });
 
-    Ok(HttpResponse::Ok().json(SearchResult {
-        items,
-        total_found,
-        agg,
-        next,
-    }))
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines))
+}
+
+/// Fetches the recipes with the given ids, in the same order
+///
+/// Recipes missing from the database (i.e.: it diverged from the
+/// index) are logged and skipped instead of failing the request.
+/// The flag tells whether any recipe was skipped
+fn fetch_recipes(
+    database: &RecipeDatabase,
+    recipe_ids: &[RecipeId],
+) -> io::Result<(Vec<Recipe>, bool)> {
+    let mut recipes = Vec::with_capacity(recipe_ids.len());
+    let mut partial = false;
+
+    for (recipe_id, recipe) in recipe_ids.iter().zip(database.find_many(recipe_ids)?) {
+        if let Some(recipe) = recipe {
+            recipes.push(recipe);
+        } else {
+            log::warn!("Recipe {} found in the index but not in the db", recipe_id);
+            partial = true;
+        }
+    }
+
+    Ok((recipes, partial))
 }
 
 type ExecuteResult = (
     usize,
     Vec<RecipeId>,
     Option<After>,
-    Option<FeaturesAggregationResult>,
+    Option<BlendedFacets>,
+    Option<Highlighter>,
+    Option<String>,
+    Option<SearchTimings>,
+    String,
 );
 
 pub struct SearchState {
     reader: IndexReader,
     recipe_index: RecipeIndex,
     query_parser: QueryParser,
+    ingredients_analyzer: TextAnalyzer,
+    topterms: TopTerms,
+    spell_checker: SpellChecker,
     agg_threshold: usize,
+    agg_sample_size: Option<usize>,
+    did_you_mean_threshold: usize,
+    max_page_size: usize,
+    export_limit: usize,
+    export_batch_size: usize,
+    info: RwLock<Option<(IndexSignature, Arc<IndexInfo>)>>,
+}
+
+// The segments a searcher sees and how many live docs each has,
+// which changes whenever the reader reloads after a commit
+type IndexSignature = Vec<(SegmentId, DocId)>;
+
+fn index_signature(searcher: &Searcher) -> IndexSignature {
+    searcher
+        .segment_readers()
+        .iter()
+        .map(|reader| (reader.segment_id(), reader.num_docs()))
+        .collect()
 }
 
 impl SearchState {
-    pub fn search(&self, query: SearchQuery, after: Option<After>) -> Result<ExecuteResult> {
-        let limit = query.num_items.unwrap_or(10) as usize;
+    /// How many recipes a page of the given query should have
+    pub fn page_size(&self, query: &SearchQuery) -> usize {
+        query
+            .num_items
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .min(self.max_page_size)
+    }
 
+    /// The ids of every recipe matching the query, in order, but
+    /// no more than `export_limit` of them
+    pub fn export(&self, query: SearchQuery) -> Result<Vec<RecipeId>> {
         let searcher = self.reader.searcher();
         let interpreted_query = self.interpret_query(&query)?;
 
-        let (total_found, recipe_ids, after) = self.recipe_index.search(
+        let mut recipe_ids = Vec::new();
+        for batch in self.recipe_index.search_all(
             &searcher,
             &interpreted_query,
-            limit,
-            query.sort.unwrap_or(Sort::Relevance),
-            after,
-        )?;
+            query.sort.unwrap_or_default(),
+            self.export_batch_size,
+        ) {
+            recipe_ids.extend(batch?);
+
+            if recipe_ids.len() >= self.export_limit {
+                recipe_ids.truncate(self.export_limit);
+                break;
+            }
+        }
 
-        let agg = if total_found <= self.agg_threshold {
-            query
-                .agg
-                .map(|agg_query| {
-                    self.recipe_index
-                        .aggregate_features(&searcher, &interpreted_query, agg_query)
-                })
-                .transpose()?
+        Ok(recipe_ids)
+    }
+
+    pub fn search(&self, query: SearchQuery, after: Option<After>) -> Result<ExecuteResult> {
+        let limit = self.page_size(&query);
+
+        let searcher = self.reader.searcher();
+        let interpreted_query = self.interpret_query(&query)?;
+        let etag = self.etag(&searcher, &query);
+
+        let search_start = Instant::now();
+        let sort = query.sort.unwrap_or_default();
+        let (total_found, recipe_ids, after) = if let Some(secondary) = query.sort_secondary {
+            self.recipe_index.search_then(
+                &searcher,
+                &interpreted_query,
+                limit,
+                sort,
+                secondary,
+                after,
+            )?
+        } else {
+            self.recipe_index
+                .search(&searcher, &interpreted_query, limit, sort, after)?
+        };
+        let search_micros = search_start.elapsed().as_micros() as u64;
+
+        let highlighter = if query.highlight && query.fulltext.is_some() {
+            Some(
+                self.recipe_index
+                    .highlighter(&searcher, &interpreted_query)?,
+            )
         } else {
             None
         };
 
-        Ok((total_found, recipe_ids, after, agg))
+        // Past the threshold, aggregate (roughly) `agg_sample_size`
+        // recipes instead of all of them, if sampling is enabled
+        let sample_every = if query.force_agg || total_found <= self.agg_threshold {
+            Some(1)
+        } else {
+            self.agg_sample_size
+                .map(|size| (total_found + size - 1) / size)
+        };
+
+        let mut agg_timing = None;
+        let agg = match (query.agg, sample_every) {
+            (Some(agg_query), Some(every)) => {
+                let agg_start = Instant::now();
+                let (features, diet_facets, visited) = self
+                    .recipe_index
+                    .aggregate_features_sampled(&searcher, &interpreted_query, agg_query, every)?;
+                if features.skipped > 0 {
+                    log::warn!(
+                        "Aggregation skipped {} unreadable recipes",
+                        features.skipped
+                    );
+                }
+                agg_timing = Some((agg_start.elapsed().as_micros() as u64, visited));
+                Some(self.blend_with_info(features, diet_facets, every > 1)?)
+            }
+            _ => None,
+        };
+
+        let did_you_mean = match &query.fulltext {
+            Some(fulltext) if total_found <= self.did_you_mean_threshold => {
+                self.spell_checker.did_you_mean(&searcher, fulltext)?
+            }
+            _ => None,
+        };
+
+        let timings = if query.debug {
+            Some(SearchTimings {
+                search_micros,
+                total_found,
+                agg_micros: agg_timing.map(|(micros, _visited)| micros),
+                agg_visited: agg_timing.map(|(_micros, visited)| visited),
+            })
+        } else {
+            None
+        };
+
+        Ok((
+            total_found,
+            recipe_ids,
+            after,
+            agg,
+            highlighter,
+            did_you_mean,
+            timings,
+            etag,
+        ))
+    }
+
+    /// The same for every page of a search, as long as the index
+    /// doesn't change: it combines the query's fingerprint (see
+    /// `SearchQuery::fingerprint`) with the index signature
+    fn etag(&self, searcher: &Searcher, query: &SearchQuery) -> String {
+        let mut hasher = DefaultHasher::new();
+        index_signature(searcher).hash(&mut hasher);
+
+        format!(
+            "\"{:016x}-{:016x}\"",
+            query.fingerprint(self.page_size(query)),
+            hasher.finish()
+        )
+    }
+
+    pub fn similar(&self, recipe_id: RecipeId, limit: usize) -> Result<Option<Vec<RecipeId>>> {
+        let searcher = self.reader.searcher();
+        self.recipe_index
+            .similar(&searcher, &self.topterms, recipe_id, limit)
+    }
+
+    /// Explains the score the recipe gets for the given query, if
+    /// it matches it
+    pub fn explain(&self, query: &SearchQuery, recipe_id: RecipeId) -> Result<Option<Explanation>> {
+        let searcher = self.reader.searcher();
+
+        let address = if let Some(address) = self.recipe_index.doc_address(&searcher, recipe_id)? {
+            address
+        } else {
+            return Ok(None);
+        };
+
+        match self.interpret_query(query)?.explain(&searcher, address) {
+            Ok(explanation) => Ok(Some(explanation)),
+            // Queries complain this way when the doc doesn't match
+            Err(TantivyError::InvalidArgument(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn num_docs(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
+    pub fn suggest(&self, input: &str, limit: usize) -> Result<Vec<RecipeId>> {
+        let searcher = self.reader.searcher();
+        self.recipe_index.suggest(&searcher, input, limit)
+    }
+
+    /// Ids of (up to `limit`) recipes matching the given filter
+    pub fn filtered(&self, filter: FeaturesFilterQuery, limit: usize) -> Result<Vec<RecipeId>> {
+        let query = SearchQuery {
+            filter: Some(filter),
+            ..SearchQuery::default()
+        };
+
+        let searcher = self.reader.searcher();
+        let interpreted_query = self.interpret_query(&query)?;
+
+        let (_total, recipe_ids, _after) = self.recipe_index.search(
+            &searcher,
+            &interpreted_query,
+            limit,
+            Sort::default(),
+            None,
+        )?;
+
+        Ok(recipe_ids)
     }
 
     fn interpret_query(&self, query: &SearchQuery) -> Result<Box<dyn Query>> {
@@ -164,6 +649,61 @@ impl SearchState {
             }
         }
 
+        if let Some(filter) = &query.filter_exclude {
+            if let Some(query) = self.recipe_index.features.interpret_exclusion(filter) {
+                subqueries.push((Occur::Must, query));
+            }
+        }
+
+        if let Some(filters) = &query.filter_any {
+            let mut alternatives: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+
+            for filter in filters {
+                let mut clauses = self.recipe_index.features.interpret(filter);
+
+                // A filter without any clause matches everything, so
+                // there's nothing to restrict
+                if clauses.is_empty() {
+                    alternatives.clear();
+                    break;
+                }
+
+                let alternative: Box<dyn Query> = if clauses.len() == 1 {
+                    clauses.pop().expect("length has been checked")
+                } else {
+                    Box::new(BooleanQuery::from(
+                        clauses
+                            .into_iter()
+                            .map(|clause| (Occur::Must, clause))
+                            .collect::<Vec<_>>(),
+                    ))
+                };
+
+                alternatives.push((Occur::Should, alternative));
+            }
+
+            if !alternatives.is_empty() {
+                subqueries.push((Occur::Must, Box::new(BooleanQuery::from(alternatives))));
+            }
+        }
+
+        if let Some(exclude) = &query.exclude {
+            for ingredient in exclude {
+                if let Some(query) = self
+                    .recipe_index
+                    .ingredient_query(&self.ingredients_analyzer, ingredient)
+                {
+                    subqueries.push((Occur::MustNot, query));
+                }
+            }
+        }
+
+        // A BooleanQuery with nothing but prohibited clauses
+        // matches nothing, so exclusions apply to every recipe
+        if !subqueries.is_empty() && subqueries.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+            subqueries.push((Occur::Must, Box::new(AllQuery)));
+        }
+
         match subqueries.len() {
             0 => Ok(Box::new(AllQuery)),
             1 => Ok(subqueries.pop().expect("length has been checked").1),
@@ -171,19 +711,63 @@ impl SearchState {
         }
     }
 
-    pub fn index_info(&self) -> Result<IndexInfo> {
+    /// Global stats about the index. They're computed once and then
+    /// only again when the reader reloads to pick up new commits
+    pub fn index_info(&self) -> Result<Arc<IndexInfo>> {
         let searcher = self.reader.searcher();
-        let features = self.recipe_index.aggregate_features(
-            &searcher,
+        let signature = index_signature(&searcher);
+
+        if let Some((cached_signature, info)) = &*self.info.read().expect("lock not poisoned") {
+            if *cached_signature == signature {
+                return Ok(info.clone());
+            }
+        }
+
+        let info = Arc::new(self.compute_index_info(&searcher)?);
+        *self.info.write().expect("lock not poisoned") = Some((signature, info.clone()));
+
+        Ok(info)
+    }
+
+    /// Puts the facets of a search next to the cached index-wide
+    /// ones, so there's always something to compare them to, even
+    /// when the search found nothing
+    pub fn blend_with_info(
+        &self,
+        features: FeaturesAggregationResult,
+        diet_facets: DietFacets,
+        approximate: bool,
+    ) -> Result<BlendedFacets> {
+        Ok(BlendedFacets {
+            features,
+            diet_facets,
+            approximate,
+            info: self.index_info()?,
+        })
+    }
+
+    /// Makes new commits visible to searches started after this.
+    /// Searches that already got a searcher are unaffected
+    pub fn reload(&self) -> Result<()> {
+        self.reader.reload()?;
+        // Refreshes the cached info if anything changed
+        self.index_info()?;
+        Ok(())
+    }
+
+    fn compute_index_info(&self, searcher: &Searcher) -> Result<IndexInfo> {
+        let (features, diet_facets) = self.recipe_index.aggregate_features(
+            searcher,
             &AllQuery,
             FeaturesAggregationQuery::full_range(),
         )?;
 
-        let sort = Sort::VALUES.to_vec();
+        let sort = FeaturesSort::VALUES.to_vec();
 
         Ok(IndexInfo {
             total_recipes: searcher.num_docs(),
             features,
+            diet_facets,
             sort,
         })
     }
@@ -191,6 +775,50 @@ impl SearchState {
 
 const BASE_DIR: &str = "BASE_DIR";
 const AGG_THRESHOLD: &str = "AGG_THRESHOLD";
+const AGG_SAMPLE_SIZE: &str = "AGG_SAMPLE_SIZE";
+const DID_YOU_MEAN_THRESHOLD: &str = "DID_YOU_MEAN_THRESHOLD";
+const MAX_PAGE_SIZE: &str = "MAX_PAGE_SIZE";
+const RELOAD_SECONDS: &str = "RELOAD_SECONDS";
+const JSON_LIMIT: &str = "JSON_LIMIT";
+const EXPORT_LIMIT: &str = "EXPORT_LIMIT";
+
+const DEFAULT_PAGE_SIZE: usize = 10;
+const DEFAULT_MAX_PAGE_SIZE: usize = 100;
+// In bytes
+const DEFAULT_JSON_LIMIT: usize = 4096;
+const DEFAULT_EXPORT_LIMIT: usize = 10_000;
+// Exported recipes are read from the index and from the
+// database this many at a time
+const EXPORT_BATCH_SIZE: usize = 500;
+
+// Searches finding this many recipes (or fewer) get spelling
+// suggestions unless DID_YOU_MEAN_THRESHOLD says otherwise
+const DEFAULT_DID_YOU_MEAN_THRESHOLD: usize = 5;
+// Terms in fewer documents than this are considered for correction
+const SPELLING_MIN_DOC_FREQ: u64 = 5;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PayloadTooLarge {
+    error: String,
+    limit: usize,
+}
+
+/// Accepts JSON bodies of up to `limit` bytes. Larger ones are
+/// rejected with a 413 that says what the limit is
+fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(move |err, _req| match err {
+            JsonPayloadError::Overflow => {
+                let response = HttpResponse::PayloadTooLarge().json(PayloadTooLarge {
+                    error: format!("Request body is larger than {} bytes", limit),
+                    limit,
+                });
+                InternalError::from_response(err, response).into()
+            }
+            other => other.into(),
+        })
+}
 
 fn get_env(key: &str) -> Result<String> {
     env::var(key).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, key).into())
@@ -204,11 +832,55 @@ async fn main() -> Result<()> {
     let threshold = get_env(AGG_THRESHOLD)
         .ok()
         .map(|v| usize::from_str(&v).expect("valid usize"));
+    let agg_sample_size = get_env(AGG_SAMPLE_SIZE)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .filter(|size| *size > 0);
+    let max_page_size = get_env(MAX_PAGE_SIZE)
+        .ok()
+        .map_or(DEFAULT_MAX_PAGE_SIZE, |v| {
+            usize::from_str(&v)
+                .ok()
+                .filter(|max| *max > 0)
+                .expect("valid non-zero usize")
+        });
+    let did_you_mean_threshold = get_env(DID_YOU_MEAN_THRESHOLD)
+        .ok()
+        .map_or(DEFAULT_DID_YOU_MEAN_THRESHOLD, |v| {
+            usize::from_str(&v).expect("valid usize")
+        });
+
+    let reload_interval = get_env(RELOAD_SECONDS).ok().map(|v| {
+        u64::from_str(&v)
+            .ok()
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+            .expect("valid non-zero u64")
+    });
+
+    let json_limit = get_env(JSON_LIMIT).ok().map_or(DEFAULT_JSON_LIMIT, |v| {
+        usize::from_str(&v)
+            .ok()
+            .filter(|limit| *limit > 0)
+            .expect("valid non-zero usize")
+    });
+
+    let export_limit = get_env(EXPORT_LIMIT)
+        .ok()
+        .map_or(DEFAULT_EXPORT_LIMIT, |v| {
+            usize::from_str(&v).expect("valid usize")
+        });
 
     log::info!(
-        "Starting with base_dir={} agg_threshold={:?}",
+        "Starting with base_dir={} agg_threshold={:?} agg_sample_size={:?} did_you_mean_threshold={} max_page_size={} reload_interval={:?} json_limit={} export_limit={}",
         base_dir,
-        threshold
+        threshold,
+        agg_sample_size,
+        did_you_mean_threshold,
+        max_page_size,
+        reload_interval,
+        json_limit,
+        export_limit
     );
 
     let base_path = Path::new(&base_dir);
@@ -216,6 +888,7 @@ async fn main() -> Result<()> {
     let db_path = base_path.join("database");
 
     let index = Index::open_in_dir(&index_path)?;
+    RecipeIndex::register_tokenizers(&index);
     let recipe_index = RecipeIndex::try_from(&index.schema())?;
     let mut query_parser = QueryParser::new(
         &index,
@@ -232,28 +905,83 @@ async fn main() -> Result<()> {
     // And make name matches slightly more important than ingredient
     query_parser.set_boost(recipe_index.name, Some(1.15));
 
-    let reader = index.reader()?;
+    let ingredients_analyzer = index.tokenizer_for_field(recipe_index.ingredients)?;
+
+    let topterms = TopTerms::new(
+        &index,
+        vec![
+            recipe_index.name,
+            recipe_index.ingredients,
+            recipe_index.instructions,
+        ],
+    )?;
+
+    let spell_checker = SpellChecker::new(
+        &index,
+        vec![
+            recipe_index.name,
+            recipe_index.ingredients,
+            recipe_index.instructions,
+        ],
+        SPELLING_MIN_DOC_FREQ,
+    )?;
+
+    // With an interval set, reloading is left to a background thread
+    let reader = if reload_interval.is_some() {
+        index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?
+    } else {
+        index.reader()?
+    };
+
     let search_state = Arc::new(SearchState {
         reader,
         recipe_index,
         query_parser,
+        ingredients_analyzer,
+        topterms,
+        spell_checker,
         agg_threshold: threshold.unwrap_or(std::usize::MAX),
+        agg_sample_size,
+        did_you_mean_threshold,
+        max_page_size,
+        export_limit,
+        export_batch_size: EXPORT_BATCH_SIZE,
+        info: RwLock::new(None),
     });
 
     let database: RecipeDatabase = Arc::new(DatabaseReader::open(&db_path)?);
 
-    let info = search_state.index_info()?;
+    // Computed upfront so that the first request doesn't pay for it
+    search_state.index_info()?;
+
+    if let Some(interval) = reload_interval {
+        let state = search_state.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if let Err(err) = state.reload() {
+                log::error!("Failed to reload the index: {}", err);
+            }
+        });
+    }
 
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .app_data(web::Data::new(search_state.clone()))
             .app_data(web::Data::new(database.clone()))
-            .app_data(web::Data::new(info.clone()))
-            .data(web::JsonConfig::default().limit(4096))
+            .app_data(json_config(json_limit))
             .service(web::resource("/recipe/{uuid}").route(web::get().to(recipe)))
+            .service(web::resource("/recipe/{uuid}/similar").route(web::get().to(similar)))
+            .service(web::resource("/random").route(web::get().to(random)))
+            .service(web::resource("/suggest").route(web::get().to(suggest)))
             .service(web::resource("/search").route(web::post().to(search)))
+            .service(web::resource("/export").route(web::post().to(export)))
+            .service(web::resource("/explain").route(web::post().to(explain)))
             .service(web::resource("/info").route(web::get().to(index_info)))
+            .service(web::resource("/health").route(web::get().to(health)))
     })
     .bind("127.0.0.1:8080")?
     .run()
@@ -261,3 +989,206 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+
+    use actix_web::test;
+    use cantine::database::DatabaseWriter;
+    use tantivy::schema::SchemaBuilder;
+
+    fn sample_recipes(num_recipes: usize) -> Vec<Recipe> {
+        include_str!("../tests/sample_recipes.jsonlines")
+            .lines()
+            .take(num_recipes)
+            .map(|line| serde_json::from_str(line).expect("valid recipe json"))
+            .collect()
+    }
+
+    fn search_state(recipes: &[Recipe]) -> Result<SearchState> {
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+        RecipeIndex::register_tokenizers(&index);
+
+        let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+        for recipe in recipes {
+            writer.add_document(recipe_index.make_document(recipe));
+        }
+        writer.commit()?;
+
+        let fields = vec![
+            recipe_index.name,
+            recipe_index.ingredients,
+            recipe_index.instructions,
+        ];
+
+        Ok(SearchState {
+            reader: index.reader()?,
+            query_parser: QueryParser::new(&index, fields.clone())?,
+            ingredients_analyzer: index.tokenizer_for_field(recipe_index.ingredients)?,
+            topterms: TopTerms::new(&index, fields.clone())?,
+            spell_checker: SpellChecker::new(&index, fields, SPELLING_MIN_DOC_FREQ)?,
+            recipe_index,
+            agg_threshold: std::usize::MAX,
+            agg_sample_size: None,
+            did_you_mean_threshold: DEFAULT_DID_YOU_MEAN_THRESHOLD,
+            max_page_size: DEFAULT_MAX_PAGE_SIZE,
+            export_limit: DEFAULT_EXPORT_LIMIT,
+            // Small enough to need a few cursors
+            export_batch_size: 7,
+            info: RwLock::new(None),
+        })
+    }
+
+    #[actix_rt::test]
+    async fn oversized_bodies_are_explained() {
+        let mut app = test::init_service(App::new().app_data(json_config(512)).route(
+            "/search",
+            web::post().to(|_: web::Json<SearchQuery>| async { HttpResponse::Ok().finish() }),
+        ))
+        .await;
+
+        let small = SearchQuery {
+            fulltext: Some("bacon".to_string()),
+            ..SearchQuery::default()
+        };
+        let req = test::TestRequest::post()
+            .uri("/search")
+            .set_json(&small)
+            .to_request();
+        assert_eq!(
+            StatusCode::OK,
+            test::call_service(&mut app, req).await.status()
+        );
+
+        let large = SearchQuery {
+            fulltext: Some("bacon ".repeat(100)),
+            ..SearchQuery::default()
+        };
+        let req = test::TestRequest::post()
+            .uri("/search")
+            .set_json(&large)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, resp.status());
+
+        let body: PayloadTooLarge = test::read_body_json(resp).await;
+        assert_eq!(512, body.limit);
+    }
+
+    #[test]
+    fn export_includes_recipes_without_the_sort_value() -> Result<()> {
+        let mut recipes = sample_recipes(30);
+        for (i, recipe) in recipes.iter_mut().enumerate() {
+            recipe.features.fat_content = if i % 4 == 0 { Some(i as f32) } else { None };
+        }
+
+        let state = search_state(&recipes)?;
+
+        for sort in vec![FeaturesSort::FatContent, FeaturesSort::FatContentAsc] {
+            let exported = state.export(SearchQuery {
+                sort: Some(Sort::By(sort)),
+                ..SearchQuery::default()
+            })?;
+
+            assert_eq!(recipes.len(), exported.len(), "{:?}", sort);
+            assert_eq!(recipes.len(), exported.iter().collect::<HashSet<_>>().len());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn etag_changes_with_the_index() -> Result<()> {
+        let recipes = sample_recipes(10);
+        let state = search_state(&recipes[..5])?;
+
+        let query = SearchQuery::default();
+        let before = state.etag(&state.reader.searcher(), &query);
+        assert_eq!(before, state.etag(&state.reader.searcher(), &query));
+
+        let index = state.reader.searcher().index().clone();
+        let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+        for recipe in recipes[5..].iter() {
+            writer.add_document(state.recipe_index.make_document(recipe));
+        }
+        writer.commit()?;
+        state.reader.reload()?;
+
+        assert_ne!(before, state.etag(&state.reader.searcher(), &query));
+
+        Ok(())
+    }
+
+    #[test]
+    fn aggregations_come_with_the_index_wide_ones() -> Result<()> {
+        let recipes = sample_recipes(10);
+        let state = search_state(&recipes)?;
+
+        let (total_found, _ids, _after, agg, _, _, _, _) = state.search(
+            SearchQuery {
+                fulltext: Some("xylophone".to_string()),
+                agg: Some(FeaturesAggregationQuery::full_range()),
+                ..SearchQuery::default()
+            },
+            None,
+        )?;
+        assert_eq!(0, total_found);
+
+        let blended = agg.expect("aggregates when nothing is found");
+        assert!(blended.diet_facets.values().all(|count| *count == 0));
+        assert_eq!(0, blended.features.num_ingredients[0].count);
+
+        assert!(Arc::ptr_eq(&state.index_info()?, &blended.info));
+        assert_eq!(recipes.len() as u64, blended.info.total_recipes);
+        assert_eq!(
+            recipes.len() as u64,
+            blended.info.features.num_ingredients[0].count
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_recipes_are_skipped() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let recipes = include_str!("../tests/sample_recipes.jsonlines")
+            .lines()
+            .take(5)
+            .map(|line| serde_json::from_str(line).expect("valid recipe json"))
+            .collect::<Vec<Recipe>>();
+
+        DatabaseWriter::new(basedir.path())?.append_batch(&recipes)?;
+
+        let mut db_reader = DatabaseReader::open(basedir.path())?;
+        // Still in the index, say, but gone from the db
+        assert!(db_reader.delete_by_id(recipes[2].recipe_id)?);
+        let database: RecipeDatabase = Arc::new(db_reader);
+
+        let ids = recipes
+            .iter()
+            .map(|recipe| recipe.recipe_id)
+            .collect::<Vec<_>>();
+
+        let (found, partial) = fetch_recipes(&database, &ids)?;
+        assert!(partial);
+        assert_eq!(
+            vec![ids[0], ids[1], ids[3], ids[4]],
+            found
+                .iter()
+                .map(|recipe| recipe.recipe_id)
+                .collect::<Vec<_>>()
+        );
+
+        let (found, partial) = fetch_recipes(&database, &ids[..2])?;
+        assert!(!partial);
+        assert_eq!(2, found.len());
+
+        Ok(())
+    }
+}
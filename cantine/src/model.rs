@@ -1,4 +1,4 @@
-use std::convert::TryInto;
+use std::{collections::HashMap, convert::TryInto};
 
 use base64::{self, URL_SAFE_NO_PAD};
 use serde::{
@@ -8,8 +8,8 @@ use serde::{
 use tantivy::Score;
 use uuid::{self, Uuid};
 
-use crate::database::DatabaseRecord;
-use cantine_derive::{Aggregable, Filterable};
+use crate::database::{DatabaseRecord, KeyedRecord};
+use cantine_derive::{Aggregable, Filterable, Sortable};
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct Recipe {
@@ -39,6 +39,12 @@ impl DatabaseRecord for Recipe {
     }
 }
 
+impl KeyedRecord for Recipe {
+    fn get_keys(&self) -> Vec<(&'static str, String)> {
+        vec![("crawl_url", self.crawl_url.clone())]
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct RecipeCard {
     pub name: String,
@@ -53,6 +59,17 @@ pub struct RecipeCard {
     pub total_time: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub calories: Option<u32>,
+
+    /// An html excerpt of the recipe with the fulltext matches
+    /// highlighted. See `SearchQuery::highlight`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Suggestion {
+    pub name: String,
+    pub uuid: Uuid,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -103,11 +120,14 @@ impl From<Recipe> for RecipeCard {
             instructions_length: src.features.instructions_length,
             total_time: src.features.total_time,
             calories: src.features.calories,
+            snippet: None,
         }
     }
 }
 
-#[derive(Filterable, Aggregable, Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
+#[derive(
+    Filterable, Aggregable, Sortable, Serialize, Deserialize, Debug, Default, PartialEq, Clone,
+)]
 pub struct Features {
     pub num_ingredients: u8,
     pub instructions_length: u32,
@@ -121,82 +141,181 @@ pub struct Features {
     pub carb_content: Option<f32>,
     pub protein_content: Option<f32>,
 
+    #[sortable(skip)]
     pub diet_lowcarb: Option<f32>,
+    #[sortable(skip)]
     pub diet_vegetarian: Option<f32>,
+    #[sortable(skip)]
     pub diet_vegan: Option<f32>,
+    #[sortable(skip)]
     pub diet_keto: Option<f32>,
+    #[sortable(skip)]
     pub diet_paleo: Option<f32>,
+
+    // Must stay the last field: see `decode_trailing`
+    #[serde(default, deserialize_with = "decode_trailing")]
+    pub popularity: Option<f32>,
+}
+
+// Recipes loaded before `popularity` existed are encoded (both in
+// the database and in the index) without it, ending right where it
+// would start. bincode can't tell that apart from a broken value,
+// so for it any failure decoding the trailing field means missing
+fn decode_trailing<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    if deserializer.is_human_readable() {
+        Option::deserialize(deserializer)
+    } else {
+        Ok(Option::deserialize(deserializer).unwrap_or(None))
+    }
 }
 
 pub type FeaturesFilterQuery = <Features as Filterable>::Query;
 pub type FeaturesFilterFields = <Features as Filterable>::Schema;
 
+/// Sorting by relevance or by any feature, except for the diet ones
+pub type FeaturesSort = <Features as Sortable>::Sort;
+
 pub type FeaturesAggregationQuery = <Features as Aggregable>::Query;
 pub type FeaturesAggregationResult = <Features as Aggregable>::Agg;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(rename_all = "snake_case")]
+/// How many recipes have a positive value for each `diet_*` feature
+pub type DietFacets = HashMap<String, u64>;
+
+/// How to order search results. On the wire it's either one
+/// of the `FeaturesSort` names or `{"random": <seed>}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
 pub enum Sort {
-    Relevance,
-    RelevanceAsc,
-
-    Calories,
-    CaloriesAsc,
-    CarbContent,
-    CarbContentAsc,
-    CookTime,
-    CookTimeAsc,
-    FatContent,
-    FatContentAsc,
-    InstructionsLength,
-    InstructionsLengthAsc,
-    NumIngredients,
-    NumIngredientsAsc,
-    PrepTime,
-    PrepTimeAsc,
-    ProteinContent,
-    ProteinContentAsc,
-    TotalTime,
-    TotalTimeAsc,
+    By(FeaturesSort),
+    /// Shuffles the recipes in an order determined by the given seed,
+    /// so paginating with the same seed never repeats a recipe.
+    /// Changing the seed reshuffles everything
+    Random {
+        random: u64,
+    },
 }
 
-impl Sort {
-    pub const VALUES: [Self; 20] = [
-        Sort::Relevance,
-        Sort::RelevanceAsc,
-        Sort::Calories,
-        Sort::CaloriesAsc,
-        Sort::CarbContent,
-        Sort::CarbContentAsc,
-        Sort::CookTime,
-        Sort::CookTimeAsc,
-        Sort::FatContent,
-        Sort::FatContentAsc,
-        Sort::InstructionsLength,
-        Sort::InstructionsLengthAsc,
-        Sort::NumIngredients,
-        Sort::NumIngredientsAsc,
-        Sort::PrepTime,
-        Sort::PrepTimeAsc,
-        Sort::ProteinContent,
-        Sort::ProteinContentAsc,
-        Sort::TotalTime,
-        Sort::TotalTimeAsc,
-    ];
+impl Default for Sort {
+    fn default() -> Self {
+        Sort::By(FeaturesSort::Relevance)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct SearchQuery {
     pub fulltext: Option<String>,
-    pub num_items: Option<u8>,
+    /// How many recipes to return. Defaults to 10 and can't be
+    /// zero. Values over the server's `MAX_PAGE_SIZE` are clamped
+    pub num_items: Option<usize>,
     pub filter: Option<FeaturesFilterQuery>,
+    /// Leave out recipes with features in any of these ranges.
+    /// Recipes without a value for a feature are kept
+    pub filter_exclude: Option<FeaturesFilterQuery>,
+    /// Only match recipes that pass at least one of these filters
+    pub filter_any: Option<Vec<FeaturesFilterQuery>>,
+    /// Leave out recipes that have any of these ingredients
+    pub exclude: Option<Vec<String>>,
     pub agg: Option<FeaturesAggregationQuery>,
+    /// Aggregate even if too many recipes are found. See
+    /// the `AGG_THRESHOLD` setting
+    #[serde(default)]
+    pub force_agg: bool,
     pub after: Option<SearchCursor>,
 
     pub sort: Option<Sort>,
+    /// Orders recipes that are tied on `sort`
+    pub sort_secondary: Option<Sort>,
     #[serde(default)]
     pub ascending: bool,
+
+    /// Add a highlighted snippet to every item when searching
+    /// with `fulltext`. Off by default since it's costly
+    #[serde(default)]
+    pub highlight: bool,
+
+    /// Report how long the search took. See `SearchResult::timings`
+    #[serde(default)]
+    pub debug: bool,
+}
+
+impl SearchQuery {
+    /// A hash of everything that affects the search output, except
+    /// for the `after` cursor, so that every page of the same search
+    /// gets the same fingerprint. `page_size` is the effective number
+    /// of items, after defaults and limits are applied
+    ///
+    /// It doesn't depend on how the query was written: field order
+    /// doesn't matter and `fulltext` has its whitespace normalized.
+    /// Its case is kept, since the query parser's operators and field
+    /// names are case-sensitive. The value is stable across builds,
+    /// so it's fit to be used as a cache key
+    pub fn fingerprint(&self, page_size: usize) -> u64 {
+        let fulltext = self
+            .fulltext
+            .as_ref()
+            .map(|fulltext| fulltext.split_whitespace().collect::<Vec<_>>().join(" "));
+
+        let exclude = self.exclude.as_ref().map(|exclude| {
+            let mut exclude: Vec<_> = exclude
+                .iter()
+                .map(|ingredient| ingredient.trim().to_lowercase())
+                .collect();
+            exclude.sort_unstable();
+            exclude.dedup();
+            exclude
+        });
+
+        // Serializing a struct always follows its declaration order
+        let canonical = CanonicalQuery {
+            fulltext,
+            page_size,
+            filter: &self.filter,
+            filter_exclude: &self.filter_exclude,
+            filter_any: &self.filter_any,
+            exclude,
+            agg: &self.agg,
+            force_agg: self.force_agg,
+            sort: self.sort.clone().unwrap_or_default(),
+            sort_secondary: &self.sort_secondary,
+            ascending: self.ascending,
+            highlight: self.highlight,
+            debug: self.debug,
+        };
+
+        fnv1a(&serde_json::to_vec(&canonical).expect("serializing to a vec never fails"))
+    }
+}
+
+#[derive(Serialize)]
+struct CanonicalQuery<'a> {
+    fulltext: Option<String>,
+    page_size: usize,
+    filter: &'a Option<FeaturesFilterQuery>,
+    filter_exclude: &'a Option<FeaturesFilterQuery>,
+    filter_any: &'a Option<Vec<FeaturesFilterQuery>>,
+    exclude: Option<Vec<String>>,
+    agg: &'a Option<FeaturesAggregationQuery>,
+    force_agg: bool,
+    sort: Sort,
+    sort_secondary: &'a Option<Sort>,
+    ascending: bool,
+    highlight: bool,
+    debug: bool,
+}
+
+// std's hashers make no promises about stability across releases
+fn fnv1a(input: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    input.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
 }
 
 #[derive(Serialize, Debug, Default)]
@@ -207,26 +326,88 @@ pub struct SearchResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub agg: Option<FeaturesAggregationResult>,
 
+    /// Computed alongside `agg`, so it's only present when it is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diet_facets: Option<DietFacets>,
+
+    /// Set when too many recipes were found, so `agg` and
+    /// `diet_facets` only account for a sample of them
+    #[serde(skip_serializing_if = "is_false")]
+    pub agg_approximate: bool,
+
+    /// The index-wide aggregation and diet facets from `/info`, to
+    /// put `agg` and `diet_facets` in context. Present along with
+    /// them, even when nothing was found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_agg: Option<FeaturesAggregationResult>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_diet_facets: Option<DietFacets>,
+
+    /// A corrected version of `SearchQuery::fulltext`, suggested
+    /// when too few recipes are found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub did_you_mean: Option<String>,
+
+    /// Set when some of the found recipes couldn't be loaded, so
+    /// `items` may be shorter than expected and `next` missing
+    #[serde(skip_serializing_if = "is_false")]
+    pub partial: bool,
+
+    /// Only present when `SearchQuery::debug` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<SearchTimings>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub next: Option<SearchCursor>,
 }
 
+/// Where the time went when executing a search
+#[derive(Serialize, Debug, Default)]
+pub struct SearchTimings {
+    /// Time spent finding (and counting) the matching recipes
+    pub search_micros: u64,
+    pub total_found: usize,
+
+    /// Time spent aggregating, if aggregations were computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agg_micros: Option<u64>,
+    /// How many recipes were actually aggregated, which is less
+    /// than `total_found` when the aggregation is sampled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agg_visited: Option<usize>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
 #[derive(Debug, PartialEq)]
 pub enum SearchCursor {
     F64Field(f64, uuid::Bytes),
     U64Field(u64, uuid::Bytes),
     Relevance(Score, uuid::Bytes),
+    /// The bits of both values of a compound sort: how to read them
+    /// depends on the sorts, see `RecipeIndex::after_from_cursor`
+    Compound(u64, u64, uuid::Bytes),
 }
 
 impl SearchCursor {
-    /// tag + score_as_bits + uuid
-    pub const SIZE: usize = 1 + 8 + 16;
+    /// tag + score_as_bits + secondary_score_as_bits + uuid
+    ///
+    /// Cursors with a single score have the uuid right after it
+    /// and ignore the trailing bytes, so the encoding is the same
+    /// as when the secondary score didn't exist
+    pub const SIZE: usize = 1 + 8 + 8 + 16;
+
+    const SINGLE_SCORE_SIZE: usize = 1 + 8 + 16;
 
     pub fn uuid(&self) -> &uuid::Bytes {
         match self {
             Self::Relevance(_, uuid) => uuid,
             Self::U64Field(_, uuid) => uuid,
             Self::F64Field(_, uuid) => uuid,
+            Self::Compound(_, _, uuid) => uuid,
         }
     }
 
@@ -234,13 +415,21 @@ impl SearchCursor {
         // tag 0 + 0-padding for f32
         if src[0..5] == [0, 0, 0, 0, 0] {
             let score = f32::from_be_bytes(src[5..9].try_into().unwrap());
-            Ok(Self::Relevance(score, src[9..].try_into().unwrap()))
+            Ok(Self::Relevance(score, src[9..25].try_into().unwrap()))
         } else if src[0] == 1 {
             let score = u64::from_be_bytes(src[1..9].try_into().unwrap());
-            Ok(Self::U64Field(score, src[9..].try_into().unwrap()))
+            Ok(Self::U64Field(score, src[9..25].try_into().unwrap()))
         } else if src[0] == 2 {
             let score = f64::from_be_bytes(src[1..9].try_into().unwrap());
-            Ok(Self::F64Field(score, src[9..].try_into().unwrap()))
+            Ok(Self::F64Field(score, src[9..25].try_into().unwrap()))
+        } else if src[0] == 3 {
+            let primary = u64::from_be_bytes(src[1..9].try_into().unwrap());
+            let secondary = u64::from_be_bytes(src[9..17].try_into().unwrap());
+            Ok(Self::Compound(
+                primary,
+                secondary,
+                src[17..].try_into().unwrap(),
+            ))
         } else {
             Err("Invalid payload")
         }
@@ -252,23 +441,34 @@ impl SearchCursor {
                 // tag 0 + 0-padding
                 buf[0..5].copy_from_slice(&[0, 0, 0, 0, 0]);
                 buf[5..9].copy_from_slice(&score.to_be_bytes());
-                buf[9..].copy_from_slice(&uuid[..]);
+                buf[9..25].copy_from_slice(&uuid[..]);
+                buf[25..].copy_from_slice(&[0; 8]);
             }
             Self::U64Field(score, uuid) => {
                 buf[0] = 1;
                 buf[1..9].copy_from_slice(&score.to_be_bytes());
-                buf[9..].copy_from_slice(&uuid[..]);
+                buf[9..25].copy_from_slice(&uuid[..]);
+                buf[25..].copy_from_slice(&[0; 8]);
             }
             Self::F64Field(score, uuid) => {
                 buf[0] = 2;
                 buf[1..9].copy_from_slice(&score.to_be_bytes());
-                buf[9..].copy_from_slice(&uuid[..]);
+                buf[9..25].copy_from_slice(&uuid[..]);
+                buf[25..].copy_from_slice(&[0; 8]);
+            }
+            Self::Compound(primary, secondary, uuid) => {
+                buf[0] = 3;
+                buf[1..9].copy_from_slice(&primary.to_be_bytes());
+                buf[9..17].copy_from_slice(&secondary.to_be_bytes());
+                buf[17..].copy_from_slice(&uuid[..]);
             }
         }
     }
 }
 
-const ENCODED_SEARCH_CURSOR_LEN: usize = 34;
+const ENCODED_SEARCH_CURSOR_LEN: usize = 44;
+// Cursors generated before compound sorts existed
+const LEGACY_ENCODED_SEARCH_CURSOR_LEN: usize = 34;
 
 impl Serialize for SearchCursor {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -297,14 +497,21 @@ impl<'de> Visitor<'de> for SearchCursorVisitor {
     }
 
     fn visit_bytes<E: Error>(self, input: &[u8]) -> Result<Self::Value, E> {
-        if input.len() != ENCODED_SEARCH_CURSOR_LEN {
-            return Err(Error::invalid_length(ENCODED_SEARCH_CURSOR_LEN, &self));
-        }
+        let decoded_len = match input.len() {
+            ENCODED_SEARCH_CURSOR_LEN => SearchCursor::SIZE,
+            LEGACY_ENCODED_SEARCH_CURSOR_LEN => SearchCursor::SINGLE_SCORE_SIZE,
+            _ => return Err(Error::invalid_length(ENCODED_SEARCH_CURSOR_LEN, &self)),
+        };
 
         let mut decode_buf = [0u8; SearchCursor::SIZE];
-        base64::decode_config_slice(input, URL_SAFE_NO_PAD, &mut decode_buf[..])
+        base64::decode_config_slice(input, URL_SAFE_NO_PAD, &mut decode_buf[..decoded_len])
             .map_err(|_| Error::custom("base64_decode failed"))?;
 
+        // Legacy cursors can't be compound
+        if decoded_len != SearchCursor::SIZE && decode_buf[0] == 3 {
+            return Err(Error::custom("invalid payload"));
+        }
+
         SearchCursor::from_bytes(&decode_buf).map_err(|_| Error::custom("invalid payload"))
     }
 }
@@ -325,6 +532,56 @@ mod tests {
     use quickcheck::{quickcheck, TestResult};
     use serde_json;
 
+    #[test]
+    fn records_without_popularity_still_decode() {
+        let features = Features {
+            num_ingredients: 3,
+            calories: Some(420),
+            diet_paleo: Some(0.5),
+            ..Features::default()
+        };
+
+        let mut legacy = bincode::serialize(&features).unwrap();
+        // A `None` is a single byte
+        assert_eq!(Some(0), legacy.pop());
+
+        assert_eq!(features, bincode::deserialize(&legacy).unwrap());
+
+        // In the database, the next record comes right after
+        let recipe = Recipe {
+            uuid: Uuid::new_v4(),
+            recipe_id: 1,
+            name: "legacy".to_string(),
+            crawl_url: "https://example.com/legacy".to_string(),
+            ingredients: vec!["bacon".to_string()],
+            instructions: Vec::new(),
+            images: Vec::new(),
+            similar_recipe_ids: Vec::new(),
+            features,
+        };
+
+        let mut data = bincode::serialize(&recipe).unwrap();
+        data.pop();
+        data.extend(bincode::serialize(&recipe).unwrap());
+
+        assert_eq!(recipe, bincode::deserialize(&data).unwrap());
+
+        let with_popularity = Features {
+            popularity: Some(0.7),
+            ..recipe.features
+        };
+        assert_eq!(
+            with_popularity,
+            bincode::deserialize(&bincode::serialize(&with_popularity).unwrap()).unwrap()
+        );
+
+        // Human readable formats are not as lenient
+        assert!(serde_json::from_str::<Features>(
+            r#"{"num_ingredients": 1, "instructions_length": 1, "popularity": "high"}"#
+        )
+        .is_err());
+    }
+
     #[test]
     fn search_cursor_json_round_trip() {
         let roundtrip = |cursor| {
@@ -344,9 +601,45 @@ mod tests {
                 i as f64 * 1.0f64,
                 *Uuid::new_v4().as_bytes(),
             ));
+            roundtrip(SearchCursor::Compound(
+                (i as f64 * -1.0f64).to_bits(),
+                u64::MAX - i,
+                *Uuid::new_v4().as_bytes(),
+            ));
         }
     }
 
+    #[test]
+    fn sort_wire_format() {
+        let check = |sort: Sort, json: &str| {
+            assert_eq!(json, serde_json::to_string(&sort).unwrap());
+            assert_eq!(sort, serde_json::from_str::<Sort>(json).unwrap());
+        };
+
+        check(Sort::default(), "\"relevance\"");
+        check(Sort::By(FeaturesSort::CaloriesAsc), "\"calories_asc\"");
+        check(Sort::Random { random: 42 }, "{\"random\":42}");
+
+        // Diet features aren't sortable
+        assert!(serde_json::from_str::<Sort>("\"diet_vegan\"").is_err());
+    }
+
+    #[test]
+    fn legacy_search_cursors_still_decode() {
+        let uuid = *Uuid::new_v4().as_bytes();
+        let cursor = SearchCursor::U64Field(42, uuid);
+
+        let mut buf = [0u8; SearchCursor::SIZE];
+        cursor.write_bytes(&mut buf);
+
+        let legacy =
+            base64::encode_config(&buf[..SearchCursor::SINGLE_SCORE_SIZE], URL_SAFE_NO_PAD);
+        assert_eq!(LEGACY_ENCODED_SEARCH_CURSOR_LEN, legacy.len());
+
+        let decoded: SearchCursor = serde_json::from_str(&format!("\"{}\"", legacy)).unwrap();
+        assert_eq!(cursor, decoded);
+    }
+
     fn search_cursor_from_bytes(mut input: Vec<u8>) -> TestResult {
         if input.len() != SearchCursor::SIZE {
             TestResult::discard()
@@ -364,6 +657,11 @@ mod tests {
             SearchCursor::from_bytes(input.as_slice().try_into().unwrap())
                 .expect("SearchCursor::F64Field");
 
+            // Tag=3 too
+            input[0] = 3;
+            SearchCursor::from_bytes(input.as_slice().try_into().unwrap())
+                .expect("SearchCursor::Compound");
+
             // Tag=0 requires padding
             input[0..5].copy_from_slice(&[0, 0, 0, 0, 0]);
             SearchCursor::from_bytes(input.as_slice().try_into().unwrap())
@@ -375,7 +673,9 @@ mod tests {
 
     #[allow(unused_must_use)]
     fn search_cursor_from_base64(input: Vec<u8>) -> TestResult {
-        if input.len() != ENCODED_SEARCH_CURSOR_LEN {
+        if input.len() != ENCODED_SEARCH_CURSOR_LEN
+            && input.len() != LEGACY_ENCODED_SEARCH_CURSOR_LEN
+        {
             TestResult::discard()
         } else {
             let visitor = SearchCursorVisitor;
@@ -389,4 +689,63 @@ mod tests {
         quickcheck(search_cursor_from_bytes as fn(Vec<u8>) -> TestResult);
         quickcheck(search_cursor_from_base64 as fn(Vec<u8>) -> TestResult);
     }
+
+    #[test]
+    fn fingerprint_ignores_field_order_and_cursor() {
+        let parse = |input: &str| serde_json::from_str::<SearchQuery>(input).unwrap();
+
+        let query = parse(
+            r#"{"fulltext": "Bacon  egg", "sort": "num_ingredients", "filter": {"calories": [0, 100]}}"#,
+        );
+        let reordered = parse(
+            r#"{"filter": {"calories": [0, 100]}, "sort": "num_ingredients", "fulltext": " Bacon egg "}"#,
+        );
+
+        assert_eq!(query.fingerprint(10), reordered.fingerprint(10));
+
+        let mut paginated = parse(
+            r#"{"fulltext": "Bacon egg", "sort": "num_ingredients", "filter": {"calories": [0, 100]}}"#,
+        );
+        paginated.after = Some(SearchCursor::U64Field(3, [1; 16]));
+        assert_eq!(query.fingerprint(10), paginated.fingerprint(10));
+
+        // Reference values: fingerprints must not change between builds
+        assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(b"a"));
+        assert_eq!(0x8594_4171_f739_67e8, fnv1a(b"foobar"));
+
+        assert_ne!(query.fingerprint(10), query.fingerprint(20));
+        assert_ne!(
+            query.fingerprint(10),
+            parse(r#"{"fulltext": "bacon egg", "filter": {"calories": [0, 100]}}"#).fingerprint(10)
+        );
+        assert_ne!(
+            query.fingerprint(10),
+            parse(r#"{"fulltext": "bacon", "sort": "num_ingredients", "filter": {"calories": [0, 100]}}"#)
+                .fingerprint(10)
+        );
+    }
+
+    #[test]
+    fn fingerprint_keeps_the_case_of_fulltext() {
+        let fingerprint = |fulltext: &str| {
+            SearchQuery {
+                fulltext: Some(fulltext.to_string()),
+                ..SearchQuery::default()
+            }
+            .fingerprint(10)
+        };
+
+        // Only uppercase operators are operators
+        assert_ne!(
+            fingerprint("chicken AND rice"),
+            fingerprint("chicken and rice")
+        );
+        // And field names are case-sensitive
+        assert_ne!(fingerprint("name:rice"), fingerprint("NAME:rice"));
+
+        assert_eq!(
+            fingerprint("chicken AND rice"),
+            fingerprint(" chicken  AND\trice")
+        );
+    }
 }
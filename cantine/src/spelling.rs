@@ -0,0 +1,193 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use levenshtein_automata::{Distance, LevenshteinAutomatonBuilder, DFA, SINK_STATE};
+use tantivy::{
+    schema::Field, tokenizer::TextAnalyzer, Index, Result, Searcher, TantivyError, Term,
+};
+use tantivy_fst::Automaton;
+use tique::{queryparser::RawQuery, QueryParser};
+
+// Terms farther than this from a known term are left alone
+const MAX_DISTANCE: u8 = 2;
+
+/// Suggests corrections for rare query terms by looking for
+/// similar, more frequent, terms in the index
+pub struct SpellChecker {
+    fields: Vec<Field>,
+    analyzer: TextAnalyzer,
+    parser: QueryParser,
+    builder: LevenshteinAutomatonBuilder,
+    min_doc_freq: u64,
+}
+
+impl SpellChecker {
+    /// Creates a SpellChecker that looks at the term dictionaries
+    /// of the given fields. Terms that appear in fewer than
+    /// `min_doc_freq` documents are considered for correction
+    ///
+    /// The input is parsed like `QueryParser` would, recognizing the
+    /// given fields' names, and its terms are tokenized with the
+    /// first field's tokenizer, so the fields should all share the
+    /// same one
+    pub fn new(index: &Index, fields: Vec<Field>, min_doc_freq: u64) -> Result<Self> {
+        let analyzer = match fields.first() {
+            Some(field) => index.tokenizer_for_field(*field)?,
+            None => {
+                return Err(TantivyError::InvalidArgument(
+                    "SpellChecker requires at least one field".to_string(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            parser: QueryParser::new(index, fields.clone())?,
+            fields,
+            analyzer,
+            builder: LevenshteinAutomatonBuilder::new(MAX_DISTANCE, true),
+            min_doc_freq,
+        })
+    }
+
+    /// Rewrites the input replacing its rare terms with the closest
+    /// more frequent ones. Yields `None` when there's nothing to fix
+    ///
+    /// Only plain terms are considered: phrases, prefixes, fuzzy
+    /// terms and ranges are kept as typed, as is all the query syntax
+    /// around the terms. Terms the analyzer breaks into many tokens
+    /// (e.g.: every word, with ngram analysis) are kept too, since
+    /// there's no single term to replace them with
+    pub fn did_you_mean(&self, searcher: &Searcher, input: &str) -> Result<Option<String>> {
+        let mut terms = Vec::new();
+        collect_terms(&self.parser.parse_to_raw(input), &mut terms);
+
+        let mut corrections = Vec::new();
+        for term in terms {
+            let token = match self.single_token(term) {
+                Some(token) => token,
+                None => continue,
+            };
+
+            let doc_freq = self.doc_freq(searcher, &token)?;
+
+            if doc_freq < self.min_doc_freq {
+                if let Some(closest) = self.closest(searcher, &token, doc_freq)? {
+                    corrections.push((offset_in(input, term), term.len(), closest));
+                }
+            }
+        }
+
+        if corrections.is_empty() {
+            return Ok(None);
+        }
+
+        let mut suggestion = String::with_capacity(input.len());
+        let mut last = 0;
+        for (start, len, closest) in corrections {
+            suggestion.push_str(&input[last..start]);
+            suggestion.push_str(&closest);
+            last = start + len;
+        }
+        suggestion.push_str(&input[last..]);
+
+        Ok(Some(suggestion))
+    }
+
+    fn single_token(&self, term: &str) -> Option<String> {
+        let mut tokens = Vec::with_capacity(1);
+        self.analyzer.token_stream(term).process(&mut |token| {
+            tokens.push(token.text.clone());
+        });
+
+        if tokens.len() == 1 {
+            tokens.pop()
+        } else {
+            None
+        }
+    }
+
+    fn doc_freq(&self, searcher: &Searcher, text: &str) -> Result<u64> {
+        let mut total = 0;
+        for field in &self.fields {
+            total += searcher.doc_freq(&Term::from_field_text(*field, text))?;
+        }
+        Ok(total)
+    }
+
+    // Picks the closest term that's more frequent than the input,
+    // using the frequency to break ties
+    fn closest(&self, searcher: &Searcher, text: &str, doc_freq: u64) -> Result<Option<String>> {
+        let dfa = self.builder.build_dfa(text);
+        let mut candidates: HashMap<Vec<u8>, (u8, u64)> = HashMap::new();
+
+        for segment_reader in searcher.segment_readers() {
+            for field in &self.fields {
+                let inverted_index = segment_reader.inverted_index(*field)?;
+                let mut stream = inverted_index
+                    .terms()
+                    .search(DfaWrapper(&dfa))
+                    .into_stream()?;
+
+                while stream.advance() {
+                    let distance = match dfa.eval(stream.key()) {
+                        Distance::Exact(distance) => distance,
+                        Distance::AtLeast(_) => continue,
+                    };
+
+                    let entry = candidates
+                        .entry(stream.key().to_vec())
+                        .or_insert((distance, 0));
+                    entry.1 += u64::from(stream.value().doc_freq);
+                }
+            }
+        }
+
+        Ok(candidates
+            .into_iter()
+            .filter(|(_key, (_distance, freq))| *freq > doc_freq)
+            .min_by_key(|(key, (distance, freq))| (*distance, Reverse(*freq), key.clone()))
+            .and_then(|(key, _)| String::from_utf8(key).ok()))
+    }
+}
+
+// Every term of the query that's spelled out as-is, in the order
+// they appear in the input
+fn collect_terms<'a>(items: &[RawQuery<'a>], terms: &mut Vec<&'a str>) {
+    for item in items {
+        if let Some(group) = &item.group {
+            collect_terms(group, terms);
+        } else if !item.is_phrase && !item.is_prefix && item.range.is_none() && item.fuzzy.is_none()
+        {
+            terms.push(item.input);
+        }
+    }
+}
+
+// Raw queries borrow from the input, so their position in it can be
+// recovered from the pointers alone
+fn offset_in(input: &str, part: &str) -> usize {
+    let offset = part.as_ptr() as usize - input.as_ptr() as usize;
+    debug_assert!(offset + part.len() <= input.len());
+    offset
+}
+
+struct DfaWrapper<'a>(&'a DFA);
+
+impl Automaton for DfaWrapper<'_> {
+    type State = u32;
+
+    fn start(&self) -> u32 {
+        self.0.initial_state()
+    }
+
+    fn is_match(&self, state: &u32) -> bool {
+        matches!(self.0.distance(*state), Distance::Exact(_))
+    }
+
+    fn can_match(&self, state: &u32) -> bool {
+        *state != SINK_STATE
+    }
+
+    fn accept(&self, state: &u32, byte: u8) -> u32 {
+        self.0.transition(*state, byte)
+    }
+}
@@ -2,17 +2,21 @@ use once_cell::sync::Lazy;
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use tantivy::{
-    query::{AllQuery, RangeQuery},
-    schema::SchemaBuilder,
-    Index, Result,
+    collector::Count,
+    query::{AllQuery, BooleanQuery, Occur, Query, RangeQuery, TermQuery},
+    schema::{IndexRecordOption, SchemaBuilder},
+    Index, Result, Term,
 };
 
 use cantine::{
-    index::RecipeIndex,
-    model::{Recipe, RecipeId, Sort},
+    index::{After, CompoundValue, RecipeIndex, TextAnalysis},
+    model::{
+        Features, FeaturesAggregationQuery, FeaturesSort, Recipe, RecipeId, SearchCursor, Sort,
+    },
+    spelling::SpellChecker,
 };
 
-use tique::QueryParser;
+use tique::{topterms::TopTerms, QueryParser};
 
 struct GlobalData {
     index: Index,
@@ -24,6 +28,7 @@ static GLOBAL: Lazy<GlobalData> = Lazy::new(|| {
     let mut builder = SchemaBuilder::new();
     let cantine = RecipeIndex::from(&mut builder);
     let index = Index::create_in_ram(builder.build());
+    RecipeIndex::register_tokenizers(&index);
 
     let mut writer = index.writer_with_num_threads(1, 50_000_000).unwrap();
 
@@ -64,10 +69,13 @@ fn pagination_works() -> Result<()> {
     let mut seen = HashSet::with_capacity(INDEX_SIZE);
 
     loop {
-        let (_total, found_ids, next) =
-            GLOBAL
-                .cantine
-                .search(&searcher, &AllQuery, 10, Sort::Relevance, after)?;
+        let (_total, found_ids, next) = GLOBAL.cantine.search(
+            &searcher,
+            &AllQuery,
+            10,
+            Sort::By(FeaturesSort::Relevance),
+            after,
+        )?;
 
         for id in found_ids {
             seen.insert(id);
@@ -85,15 +93,38 @@ fn pagination_works() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn zero_limit_only_counts() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let (total, found_ids, next) = GLOBAL.cantine.search(
+        &searcher,
+        &AllQuery,
+        0,
+        Sort::By(FeaturesSort::Relevance),
+        None,
+    )?;
+
+    assert_eq!(INDEX_SIZE, total);
+    assert!(found_ids.is_empty());
+    assert!(next.is_none());
+
+    Ok(())
+}
+
 #[test]
 fn num_ingredients_sort() -> Result<()> {
     let reader = GLOBAL.index.reader()?;
     let searcher = reader.searcher();
 
-    let (_total, found_ids, _next) =
-        GLOBAL
-            .cantine
-            .search(&searcher, &AllQuery, INDEX_SIZE, Sort::NumIngredients, None)?;
+    let (_total, found_ids, _next) = GLOBAL.cantine.search(
+        &searcher,
+        &AllQuery,
+        INDEX_SIZE,
+        Sort::By(FeaturesSort::NumIngredients),
+        None,
+    )?;
 
     let mut last = None;
     for id in found_ids {
@@ -109,7 +140,7 @@ fn num_ingredients_sort() -> Result<()> {
         &searcher,
         &AllQuery,
         INDEX_SIZE,
-        Sort::NumIngredientsAsc,
+        Sort::By(FeaturesSort::NumIngredientsAsc),
         None,
     )?;
 
@@ -171,7 +202,7 @@ macro_rules! stress_sort_pagination {
 
 stress_sort_pagination!(
     sort_total_time,
-    Sort::TotalTime,
+    Sort::By(FeaturesSort::TotalTime),
     total_time,
     u64,
     new_u64,
@@ -179,7 +210,7 @@ stress_sort_pagination!(
 );
 stress_sort_pagination!(
     sort_total_time_asc,
-    Sort::TotalTimeAsc,
+    Sort::By(FeaturesSort::TotalTimeAsc),
     total_time,
     u64,
     new_u64,
@@ -188,7 +219,7 @@ stress_sort_pagination!(
 
 stress_sort_pagination!(
     sort_cook_time,
-    Sort::CookTime,
+    Sort::By(FeaturesSort::CookTime),
     cook_time,
     u64,
     new_u64,
@@ -196,7 +227,7 @@ stress_sort_pagination!(
 );
 stress_sort_pagination!(
     sort_cook_time_asc,
-    Sort::CookTimeAsc,
+    Sort::By(FeaturesSort::CookTimeAsc),
     cook_time,
     u64,
     new_u64,
@@ -205,7 +236,7 @@ stress_sort_pagination!(
 
 stress_sort_pagination!(
     sort_prep_time,
-    Sort::PrepTime,
+    Sort::By(FeaturesSort::PrepTime),
     prep_time,
     u64,
     new_u64,
@@ -213,7 +244,7 @@ stress_sort_pagination!(
 );
 stress_sort_pagination!(
     sort_prep_time_asc,
-    Sort::PrepTimeAsc,
+    Sort::By(FeaturesSort::PrepTimeAsc),
     prep_time,
     u64,
     new_u64,
@@ -222,7 +253,7 @@ stress_sort_pagination!(
 
 stress_sort_pagination!(
     sort_calories,
-    Sort::Calories,
+    Sort::By(FeaturesSort::Calories),
     calories,
     u64,
     new_u64,
@@ -230,7 +261,7 @@ stress_sort_pagination!(
 );
 stress_sort_pagination!(
     sort_calories_asc,
-    Sort::CaloriesAsc,
+    Sort::By(FeaturesSort::CaloriesAsc),
     calories,
     u64,
     new_u64,
@@ -239,7 +270,7 @@ stress_sort_pagination!(
 
 stress_sort_pagination!(
     sort_fat_content,
-    Sort::FatContent,
+    Sort::By(FeaturesSort::FatContent),
     fat_content,
     f64,
     new_f64,
@@ -247,7 +278,7 @@ stress_sort_pagination!(
 );
 stress_sort_pagination!(
     sort_fat_content_asc,
-    Sort::FatContentAsc,
+    Sort::By(FeaturesSort::FatContentAsc),
     fat_content,
     f64,
     new_f64,
@@ -256,7 +287,7 @@ stress_sort_pagination!(
 
 stress_sort_pagination!(
     sort_carb_content,
-    Sort::CarbContent,
+    Sort::By(FeaturesSort::CarbContent),
     carb_content,
     f64,
     new_f64,
@@ -264,7 +295,7 @@ stress_sort_pagination!(
 );
 stress_sort_pagination!(
     sort_carb_content_asc,
-    Sort::CarbContentAsc,
+    Sort::By(FeaturesSort::CarbContentAsc),
     carb_content,
     f64,
     new_f64,
@@ -273,7 +304,7 @@ stress_sort_pagination!(
 
 stress_sort_pagination!(
     sort_protein_content,
-    Sort::ProteinContent,
+    Sort::By(FeaturesSort::ProteinContent),
     protein_content,
     f64,
     new_f64,
@@ -281,7 +312,7 @@ stress_sort_pagination!(
 );
 stress_sort_pagination!(
     sort_protein_content_asc,
-    Sort::ProteinContentAsc,
+    Sort::By(FeaturesSort::ProteinContentAsc),
     protein_content,
     f64,
     new_f64,
@@ -304,15 +335,21 @@ fn ascending_sort_works_for_relevance() -> Result<()> {
 
     let query = parser.parse("+potato +cheese").unwrap();
 
-    let (_total, found_ids, _next) =
-        GLOBAL
-            .cantine
-            .search(&searcher, &query, INDEX_SIZE, Sort::Relevance, None)?;
+    let (_total, found_ids, _next) = GLOBAL.cantine.search(
+        &searcher,
+        &query,
+        INDEX_SIZE,
+        Sort::By(FeaturesSort::Relevance),
+        None,
+    )?;
 
-    let (total, mut found_ids_asc, _next) =
-        GLOBAL
-            .cantine
-            .search(&searcher, &query, INDEX_SIZE, Sort::RelevanceAsc, None)?;
+    let (total, mut found_ids_asc, _next) = GLOBAL.cantine.search(
+        &searcher,
+        &query,
+        INDEX_SIZE,
+        Sort::By(FeaturesSort::RelevanceAsc),
+        None,
+    )?;
 
     assert!(total > 5);
     // NOTE Flaky test: the only reason the reverse check works
@@ -325,3 +362,936 @@ fn ascending_sort_works_for_relevance() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn pagination_survives_ties_across_segments() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::from(&mut builder);
+    let index = Index::create_in_ram(builder.build());
+    RecipeIndex::register_tokenizers(&index);
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+
+    let num_recipes = 50;
+    let mut popularity = HashMap::with_capacity(num_recipes);
+    let mut features = HashMap::with_capacity(num_recipes);
+    for (i, line) in include_str!("sample_recipes.jsonlines")
+        .lines()
+        .take(num_recipes)
+        .enumerate()
+    {
+        let mut recipe: Recipe = serde_json::from_str(line).expect("valid recipe json");
+
+        // Ids that don't follow the insertion order
+        recipe.recipe_id = (i as u64 * 7919) % 1000 + 1;
+        // And plenty of tied values
+        recipe.features.num_ingredients = (i % 3) as u8;
+        recipe.features.fat_content = Some((i % 2) as f32);
+        recipe.features.popularity = Some((i % 5) as f32);
+        popularity.insert(recipe.recipe_id, (i % 5) as f32);
+        features.insert(recipe.recipe_id, recipe.features.clone());
+
+        writer.add_document(cantine.make_document(&recipe));
+
+        // Spread the recipes across multiple segments
+        if i % 10 == 9 {
+            writer.commit()?;
+        }
+    }
+
+    writer.commit()?;
+
+    let reader = index.reader()?;
+    let searcher = reader.searcher();
+
+    let sorts = vec![
+        Sort::By(FeaturesSort::Relevance),
+        Sort::By(FeaturesSort::RelevanceAsc),
+        Sort::By(FeaturesSort::NumIngredients),
+        Sort::By(FeaturesSort::NumIngredientsAsc),
+        Sort::By(FeaturesSort::FatContent),
+        Sort::By(FeaturesSort::FatContentAsc),
+        Sort::By(FeaturesSort::Popularity),
+        Sort::By(FeaturesSort::PopularityAsc),
+        Sort::Random { random: 42 },
+    ];
+
+    for sort in sorts {
+        let (total, everything, next) =
+            cantine.search(&searcher, &AllQuery, num_recipes, sort.clone(), None)?;
+
+        assert_eq!(num_recipes, total);
+        assert_eq!(num_recipes, everything.len());
+        assert!(next.is_none());
+
+        for page_size in 1..=7 {
+            let mut after = None;
+            let mut paginated = Vec::with_capacity(num_recipes);
+
+            loop {
+                let (_total, found_ids, next) =
+                    cantine.search(&searcher, &AllQuery, page_size, sort.clone(), after)?;
+
+                paginated.extend(found_ids);
+
+                if let Some(new_after) = next {
+                    after = Some(new_after);
+                } else {
+                    break;
+                }
+            }
+
+            assert_eq!(
+                everything, paginated,
+                "{:?} with page_size={}",
+                sort, page_size
+            );
+        }
+    }
+
+    let (_total, most_popular, _next) = cantine.search(
+        &searcher,
+        &AllQuery,
+        num_recipes,
+        Sort::By(FeaturesSort::Popularity),
+        None,
+    )?;
+    assert!(most_popular
+        .windows(2)
+        .all(|pair| popularity[&pair[0]] >= popularity[&pair[1]]));
+
+    // Most ingredients first, then least fat
+    let (total, everything, next) = cantine.search_then(
+        &searcher,
+        &AllQuery,
+        num_recipes,
+        Sort::By(FeaturesSort::NumIngredients),
+        Sort::By(FeaturesSort::FatContentAsc),
+        None,
+    )?;
+
+    assert_eq!(num_recipes, total);
+    assert!(next.is_none());
+    assert!(everything.windows(2).all(|pair| {
+        let (a, b) = (&features[&pair[0]], &features[&pair[1]]);
+        a.num_ingredients > b.num_ingredients
+            || (a.num_ingredients == b.num_ingredients && a.fat_content <= b.fat_content)
+    }));
+
+    for page_size in 1..=7 {
+        let mut after = None;
+        let mut paginated = Vec::with_capacity(num_recipes);
+
+        loop {
+            let (_total, found_ids, next) = cantine.search_then(
+                &searcher,
+                &AllQuery,
+                page_size,
+                Sort::By(FeaturesSort::NumIngredients),
+                Sort::By(FeaturesSort::FatContentAsc),
+                after,
+            )?;
+
+            paginated.extend(found_ids);
+
+            if let Some(new_after) = next {
+                after = Some(new_after);
+            } else {
+                break;
+            }
+        }
+
+        assert_eq!(
+            everything, paginated,
+            "compound with page_size={}",
+            page_size
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn random_keys_stay_exact_in_compound_sorts() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    // Big keys that a f64 can't tell apart
+    assert_eq!((u64::MAX - 1) as f64, u64::MAX as f64);
+    assert!(CompoundValue::Int(u64::MAX - 1) < CompoundValue::Int(u64::MAX));
+
+    let random = Sort::Random { random: 42 };
+    let (_total, shuffled, _next) =
+        GLOBAL
+            .cantine
+            .search(&searcher, &AllQuery, INDEX_SIZE, random.clone(), None)?;
+
+    // Every recipe ties on relevance, so the random keys decide
+    let mut after = None;
+    let mut paginated = Vec::with_capacity(INDEX_SIZE);
+    loop {
+        let (_total, found_ids, next) = GLOBAL.cantine.search_then(
+            &searcher,
+            &AllQuery,
+            7,
+            Sort::default(),
+            random.clone(),
+            after,
+        )?;
+        paginated.extend(found_ids);
+
+        // Through the cursor, like the server does
+        after = match next {
+            Some(After::Compound(primary, secondary, id)) => GLOBAL.cantine.after_from_cursor(
+                &SearchCursor::Compound(primary.to_bits(), secondary.to_bits(), [0; 16]),
+                Sort::default(),
+                Some(random.clone()),
+                id,
+            ),
+            Some(other) => panic!("Not a compound cursor: {:?}", other),
+            None => break,
+        };
+        assert!(after.is_some());
+    }
+
+    assert_eq!(shuffled, paginated);
+
+    Ok(())
+}
+
+#[test]
+fn cursors_must_match_the_sort() {
+    let uuid = [0; 16];
+    let check = |cursor: SearchCursor, sort: Sort, secondary: Option<Sort>| {
+        GLOBAL
+            .cantine
+            .after_from_cursor(&cursor, sort, secondary, 1)
+    };
+
+    let random = Sort::Random { random: 1 };
+
+    assert!(check(SearchCursor::Relevance(1.0, uuid), Sort::default(), None).is_some());
+    assert!(check(SearchCursor::U64Field(1, uuid), random.clone(), None).is_some());
+    assert!(check(
+        SearchCursor::U64Field(1, uuid),
+        Sort::By(FeaturesSort::NumIngredients),
+        None
+    )
+    .is_some());
+    assert!(check(
+        SearchCursor::F64Field(1.0, uuid),
+        Sort::By(FeaturesSort::FatContentAsc),
+        None
+    )
+    .is_some());
+
+    assert!(check(SearchCursor::U64Field(1, uuid), Sort::default(), None).is_none());
+    assert!(check(SearchCursor::Relevance(1.0, uuid), random.clone(), None).is_none());
+    assert!(check(
+        SearchCursor::F64Field(1.0, uuid),
+        Sort::By(FeaturesSort::NumIngredients),
+        None
+    )
+    .is_none());
+    assert!(check(
+        SearchCursor::U64Field(1, uuid),
+        Sort::By(FeaturesSort::FatContent),
+        None
+    )
+    .is_none());
+
+    // Compound cursors go with compound sorts only
+    assert!(check(SearchCursor::Compound(1, 1, uuid), Sort::default(), None).is_none());
+    assert!(check(
+        SearchCursor::U64Field(1, uuid),
+        Sort::default(),
+        Some(random.clone())
+    )
+    .is_none());
+
+    match check(
+        SearchCursor::Compound(0.5f64.to_bits(), u64::MAX, uuid),
+        Sort::default(),
+        Some(random),
+    ) {
+        Some(After::Compound(CompoundValue::Float(primary), CompoundValue::Int(secondary), 1)) => {
+            assert_eq!(0.5, primary);
+            assert_eq!(u64::MAX, secondary);
+        }
+        other => panic!("Unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn pagination_goes_past_missing_values() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::from(&mut builder);
+    let index = Index::create_in_ram(builder.build());
+    RecipeIndex::register_tokenizers(&index);
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+
+    let num_recipes = 30;
+    let mut fat_content = HashMap::with_capacity(num_recipes);
+    for (i, line) in include_str!("sample_recipes.jsonlines")
+        .lines()
+        .take(num_recipes)
+        .enumerate()
+    {
+        let mut recipe: Recipe = serde_json::from_str(line).expect("valid recipe json");
+
+        recipe.recipe_id = (i as u64 * 7919) % 1000 + 1;
+        // Missing values are stored as NaN
+        recipe.features.fat_content = if i % 3 == 0 {
+            Some((i % 4) as f32)
+        } else {
+            None
+        };
+        fat_content.insert(recipe.recipe_id, recipe.features.fat_content);
+
+        writer.add_document(cantine.make_document(&recipe));
+
+        if i % 10 == 9 {
+            writer.commit()?;
+        }
+    }
+
+    writer.commit()?;
+
+    let reader = index.reader()?;
+    let searcher = reader.searcher();
+
+    for sort in vec![
+        Sort::By(FeaturesSort::FatContent),
+        Sort::By(FeaturesSort::FatContentAsc),
+    ] {
+        let (_total, everything, _next) =
+            cantine.search(&searcher, &AllQuery, num_recipes, sort.clone(), None)?;
+
+        assert_eq!(num_recipes, everything.len());
+
+        // Recipes without a value come last, lowest id first
+        let missing = everything
+            .iter()
+            .skip_while(|id| fat_content[*id].is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(20, missing.len(), "{:?}", sort);
+        assert!(missing.iter().all(|id| fat_content[*id].is_none()));
+        assert!(missing.windows(2).all(|pair| pair[0] < pair[1]));
+
+        for page_size in 1..=7 {
+            let mut after = None;
+            let mut paginated = Vec::with_capacity(num_recipes);
+
+            loop {
+                let (_total, found_ids, next) =
+                    cantine.search(&searcher, &AllQuery, page_size, sort.clone(), after)?;
+
+                paginated.extend(found_ids);
+
+                if let Some(new_after) = next {
+                    after = Some(new_after);
+                } else {
+                    break;
+                }
+            }
+
+            assert_eq!(
+                everything, paginated,
+                "{:?} with page_size={}",
+                sort, page_size
+            );
+        }
+    }
+
+    let (_total, everything, _next) = cantine.search_then(
+        &searcher,
+        &AllQuery,
+        num_recipes,
+        Sort::By(FeaturesSort::FatContentAsc),
+        Sort::By(FeaturesSort::Relevance),
+        None,
+    )?;
+    assert_eq!(num_recipes, everything.len());
+
+    for page_size in 1..=7 {
+        let mut after = None;
+        let mut paginated = Vec::with_capacity(num_recipes);
+
+        loop {
+            let (_total, found_ids, next) = cantine.search_then(
+                &searcher,
+                &AllQuery,
+                page_size,
+                Sort::By(FeaturesSort::FatContentAsc),
+                Sort::By(FeaturesSort::Relevance),
+                after,
+            )?;
+
+            paginated.extend(found_ids);
+
+            if let Some(new_after) = next {
+                after = Some(new_after);
+            } else {
+                break;
+            }
+        }
+
+        assert_eq!(
+            everything, paginated,
+            "compound with page_size={}",
+            page_size
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn popularity_sort_pages_through_unpopular_recipes() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::from(&mut builder);
+    let index = Index::create_in_ram(builder.build());
+    RecipeIndex::register_tokenizers(&index);
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+
+    let num_recipes = 40;
+    let mut with_popularity = HashSet::new();
+    for (i, line) in include_str!("sample_recipes.jsonlines")
+        .lines()
+        .take(num_recipes)
+        .enumerate()
+    {
+        let mut recipe: Recipe = serde_json::from_str(line).expect("valid recipe json");
+
+        // Like in the sample data, most recipes have no popularity
+        if i % 5 == 0 {
+            recipe.features.popularity = Some(i as f32);
+            with_popularity.insert(recipe.recipe_id);
+        } else {
+            recipe.features.popularity = None;
+        }
+
+        writer.add_document(cantine.make_document(&recipe));
+
+        if i % 10 == 9 {
+            writer.commit()?;
+        }
+    }
+
+    writer.commit()?;
+
+    let reader = index.reader()?;
+    let searcher = reader.searcher();
+
+    for sort in vec![
+        Sort::By(FeaturesSort::Popularity),
+        Sort::By(FeaturesSort::PopularityAsc),
+    ] {
+        let mut after = None;
+        let mut paginated = Vec::with_capacity(num_recipes);
+
+        loop {
+            let (_total, found_ids, next) =
+                cantine.search(&searcher, &AllQuery, 3, sort.clone(), after)?;
+
+            paginated.extend(found_ids);
+
+            if let Some(new_after) = next {
+                after = Some(new_after);
+            } else {
+                break;
+            }
+        }
+
+        assert_eq!(num_recipes, paginated.len(), "{:?}", sort);
+        assert_eq!(
+            num_recipes,
+            paginated.iter().collect::<HashSet<_>>().len(),
+            "{:?}",
+            sort
+        );
+
+        // The ones with a popularity come first either way
+        assert!(
+            paginated[..with_popularity.len()]
+                .iter()
+                .all(|id| with_popularity.contains(id)),
+            "{:?}",
+            sort
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn doc_address_finds_recipes() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    for recipe_id in GLOBAL.db.keys() {
+        let addr = GLOBAL
+            .cantine
+            .doc_address(&searcher, *recipe_id)?
+            .expect("every recipe is indexed");
+
+        let doc = searcher.doc(addr)?;
+        assert_eq!(
+            Some(*recipe_id),
+            doc.get_first(GLOBAL.cantine.id).and_then(|v| v.u64_value())
+        );
+    }
+
+    assert!(GLOBAL.cantine.doc_address(&searcher, 0)?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn similar_excludes_source_recipe() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let topterms = TopTerms::new(
+        &GLOBAL.index,
+        vec![
+            GLOBAL.cantine.name,
+            GLOBAL.cantine.ingredients,
+            GLOBAL.cantine.instructions,
+        ],
+    )?;
+
+    for recipe_id in GLOBAL.db.keys().take(10) {
+        let similar = GLOBAL
+            .cantine
+            .similar(&searcher, &topterms, *recipe_id, 5)?
+            .expect("recipe is indexed");
+
+        assert_eq!(5, similar.len());
+        assert!(!similar.contains(recipe_id));
+    }
+
+    assert!(GLOBAL
+        .cantine
+        .similar(&searcher, &topterms, 0, 5)?
+        .is_none());
+
+    Ok(())
+}
+
+#[test]
+fn highlighter_marks_matches() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let parser = QueryParser::new(
+        &GLOBAL.index,
+        vec![
+            GLOBAL.cantine.name,
+            GLOBAL.cantine.ingredients,
+            GLOBAL.cantine.instructions,
+        ],
+    )?;
+
+    let query = parser.parse("lemon").unwrap();
+    let highlighter = GLOBAL.cantine.highlighter(&searcher, &query)?;
+
+    let (_total, found_ids, _next) = GLOBAL.cantine.search(
+        &searcher,
+        &query,
+        10,
+        Sort::By(FeaturesSort::Relevance),
+        None,
+    )?;
+
+    assert!(!found_ids.is_empty());
+
+    for id in found_ids {
+        let snippet = highlighter
+            .highlight(&GLOBAL.db[&id])
+            .expect("matching recipes get a snippet");
+        assert!(snippet.to_lowercase().contains("<b>lemon"));
+    }
+
+    // Nothing to highlight when the query doesn't match
+    let query = parser.parse("xylophone").unwrap();
+    let highlighter = GLOBAL.cantine.highlighter(&searcher, &query)?;
+    assert!(GLOBAL
+        .db
+        .values()
+        .all(|recipe| highlighter.highlight(recipe).is_none()));
+
+    Ok(())
+}
+
+#[test]
+fn diet_facets_match_database() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let (_agg, diet_facets) = GLOBAL.cantine.aggregate_features(
+        &searcher,
+        &AllQuery,
+        FeaturesAggregationQuery::default(),
+    )?;
+
+    let count = |get: fn(&Recipe) -> Option<f32>| {
+        GLOBAL
+            .db
+            .values()
+            .filter(|recipe| get(recipe).map_or(false, |v| v > 0.0))
+            .count() as u64
+    };
+
+    assert_eq!(5, diet_facets.len());
+    assert_eq!(
+        count(|r| r.features.diet_lowcarb),
+        diet_facets["diet_lowcarb"]
+    );
+    assert_eq!(
+        count(|r| r.features.diet_vegetarian),
+        diet_facets["diet_vegetarian"]
+    );
+    assert_eq!(count(|r| r.features.diet_vegan), diet_facets["diet_vegan"]);
+    assert_eq!(count(|r| r.features.diet_keto), diet_facets["diet_keto"]);
+    assert_eq!(count(|r| r.features.diet_paleo), diet_facets["diet_paleo"]);
+
+    Ok(())
+}
+
+#[test]
+fn excluded_ingredients_disappear() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let analyzer = GLOBAL
+        .index
+        .tokenizer_for_field(GLOBAL.cantine.ingredients)?;
+    let garlic = GLOBAL
+        .cantine
+        .ingredient_query(&analyzer, "Garlic")
+        .expect("garlic is a valid query");
+
+    let has_garlic = searcher.search(garlic.as_ref(), &Count)?;
+    assert!(has_garlic > 0);
+
+    let query = BooleanQuery::from(vec![
+        (Occur::Must, Box::new(AllQuery) as Box<dyn Query>),
+        (Occur::MustNot, garlic),
+    ]);
+
+    let (total, found_ids, _next) = GLOBAL.cantine.search(
+        &searcher,
+        &query,
+        INDEX_SIZE,
+        Sort::By(FeaturesSort::Relevance),
+        None,
+    )?;
+
+    assert_eq!(INDEX_SIZE - has_garlic, total);
+    for id in found_ids {
+        assert!(GLOBAL.db[&id]
+            .ingredients
+            .iter()
+            .all(|ingredient| ingredient
+                .to_lowercase()
+                .split(|c: char| !c.is_alphanumeric())
+                .all(|word| word != "garlic")));
+    }
+
+    // Nothing to exclude when the input has no tokens
+    assert!(GLOBAL.cantine.ingredient_query(&analyzer, " ").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn suggest_finds_names_by_prefix() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let suggested = GLOBAL.cantine.suggest(&searcher, "Choc", 5)?;
+    assert!(!suggested.is_empty());
+    assert!(suggested.len() <= 5);
+
+    for id in suggested {
+        assert!(GLOBAL.db[&id].name.to_lowercase().starts_with("choc"));
+    }
+
+    // Too short to be useful
+    assert!(GLOBAL.cantine.suggest(&searcher, "c", 5)?.is_empty());
+    assert!(GLOBAL
+        .cantine
+        .suggest(&searcher, "xylophone", 5)?
+        .is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn spell_checker_suggests_frequent_terms() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let checker = SpellChecker::new(
+        &GLOBAL.index,
+        vec![
+            GLOBAL.cantine.name,
+            GLOBAL.cantine.ingredients,
+            GLOBAL.cantine.instructions,
+        ],
+        5,
+    )?;
+
+    assert_eq!(
+        Some("garlic butter".to_string()),
+        checker.did_you_mean(&searcher, "Garlci butter")?
+    );
+    assert_eq!(
+        Some("chocolate".to_string()),
+        checker.did_you_mean(&searcher, "chocolat")?
+    );
+
+    // Nothing to fix
+    assert_eq!(None, checker.did_you_mean(&searcher, "lemon butter")?);
+
+    // The query syntax is kept around corrected terms
+    assert_eq!(
+        Some("-ingredients:garlic +bacon".to_string()),
+        checker.did_you_mean(&searcher, "-ingredients:garlci +bacon")?
+    );
+    assert_eq!(
+        Some("(garlic OR bacon) AND \"garlci bread\"^2".to_string()),
+        checker.did_you_mean(&searcher, "(garlci OR bacon) AND \"garlci bread\"^2")?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn spell_checker_leaves_ngrams_alone() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::with_analysis(&mut builder, TextAnalysis::Ngram);
+    let index = Index::create_in_ram(builder.build());
+    RecipeIndex::register_tokenizers(&index);
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+    for recipe in GLOBAL.db.values() {
+        writer.add_document(cantine.make_document(recipe));
+    }
+    writer.commit()?;
+
+    let checker = SpellChecker::new(
+        &index,
+        vec![cantine.name, cantine.ingredients, cantine.instructions],
+        5,
+    )?;
+
+    // Every word is many terms, none of which is a correction
+    assert_eq!(
+        None,
+        checker.did_you_mean(&index.reader()?.searcher(), "-ingredients:garlci +bacon")?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sampled_aggregation_sees_a_fraction() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let (full, _diets) = GLOBAL.cantine.aggregate_features(
+        &searcher,
+        &AllQuery,
+        FeaturesAggregationQuery::full_range(),
+    )?;
+    assert_eq!(INDEX_SIZE as u64, full.num_ingredients[0].count);
+
+    let (sampled, _diets, visited) = GLOBAL.cantine.aggregate_features_sampled(
+        &searcher,
+        &AllQuery,
+        FeaturesAggregationQuery::full_range(),
+        3,
+    )?;
+
+    // One of every 3 docs, starting from the first, in every segment
+    let expected: u64 = searcher
+        .segment_readers()
+        .iter()
+        .map(|reader| (u64::from(reader.num_docs()) + 2) / 3)
+        .sum();
+    assert_eq!(expected, sampled.num_ingredients[0].count);
+    assert_eq!(expected, visited as u64);
+
+    Ok(())
+}
+
+#[test]
+fn similar_by_features_ranks_by_closeness() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    // Only calories are set, so they're all that matters
+    let source = Features {
+        calories: Some(350),
+        ..Features::default()
+    };
+
+    let found = GLOBAL
+        .cantine
+        .similar_by_features(&searcher, &source, INDEX_SIZE)?;
+    assert_eq!(INDEX_SIZE, found.len());
+
+    let distance = |id: &RecipeId| {
+        GLOBAL.db[id]
+            .features
+            .calories
+            .map(|calories| (i64::from(calories) - 350).abs().min(1000))
+            .unwrap_or(1000)
+    };
+
+    let mut last = 0;
+    for id in &found {
+        let current = distance(id);
+        assert!(current >= last);
+        last = current;
+    }
+
+    assert!(GLOBAL
+        .cantine
+        .similar_by_features(&searcher, &source, 0)?
+        .is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn random_sort_is_stable_per_seed() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let search = |seed, after| {
+        GLOBAL.cantine.search(
+            &searcher,
+            &AllQuery,
+            10,
+            Sort::Random { random: seed },
+            after,
+        )
+    };
+
+    let (total, first_page, after) = search(42, None)?;
+    assert_eq!(INDEX_SIZE, total);
+    assert_eq!(first_page, search(42, None)?.1);
+
+    let (_total, second_page, _after) = search(42, after)?;
+    assert_eq!(10, second_page.len());
+    assert!(second_page.iter().all(|id| !first_page.contains(id)));
+
+    // A new seed, a new order
+    assert_ne!(first_page, search(7, None)?.1);
+
+    Ok(())
+}
+
+#[test]
+fn ngram_analysis_matches_partial_words() -> Result<()> {
+    let choco_count = |analysis| -> Result<usize> {
+        let mut builder = SchemaBuilder::new();
+        let cantine = RecipeIndex::with_analysis(&mut builder, analysis);
+        let index = Index::create_in_ram(builder.build());
+        RecipeIndex::register_tokenizers(&index);
+
+        let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+        for line in include_str!("sample_recipes.jsonlines").lines() {
+            let recipe: Recipe = serde_json::from_str(line).expect("valid recipe json");
+            writer.add_document(cantine.make_document(&recipe));
+        }
+        writer.commit()?;
+
+        let query = TermQuery::new(
+            Term::from_field_text(cantine.name, "choco"),
+            IndexRecordOption::Basic,
+        );
+
+        index.reader()?.searcher().search(&query, &Count)
+    };
+
+    let with_chocolate = GLOBAL
+        .db
+        .values()
+        .filter(|recipe| recipe.name.to_lowercase().contains("choco"))
+        .count();
+    assert!(with_chocolate > 0);
+
+    assert_eq!(0, choco_count(TextAnalysis::Default)?);
+    assert_eq!(with_chocolate, choco_count(TextAnalysis::Ngram)?);
+
+    Ok(())
+}
+
+#[test]
+fn accents_are_folded() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let analyzer = GLOBAL
+        .index
+        .tokenizer_for_field(GLOBAL.cantine.ingredients)?;
+
+    let with_jalapeno: Vec<_> = GLOBAL
+        .db
+        .values()
+        .filter(|recipe| {
+            recipe
+                .ingredients
+                .iter()
+                .any(|ingredient| ingredient.to_lowercase().contains("jalapeño"))
+        })
+        .map(|recipe| recipe.recipe_id)
+        .collect();
+    assert!(!with_jalapeno.is_empty());
+
+    let query = GLOBAL
+        .cantine
+        .ingredient_query(&analyzer, "jalapeno")
+        .expect("jalapeno is a valid query");
+
+    let (_total, found_ids, _next) = GLOBAL.cantine.search(
+        &searcher,
+        query.as_ref(),
+        INDEX_SIZE,
+        Sort::By(FeaturesSort::Relevance),
+        None,
+    )?;
+
+    assert!(with_jalapeno.iter().all(|id| found_ids.contains(id)));
+
+    Ok(())
+}
+
+#[test]
+fn search_all_visits_every_match() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let sort = Sort::By(FeaturesSort::NumIngredients);
+    let (_total, expected, _next) =
+        GLOBAL
+            .cantine
+            .search(&searcher, &AllQuery, INDEX_SIZE, sort.clone(), None)?;
+
+    let batches = GLOBAL
+        .cantine
+        .search_all(&searcher, &AllQuery, sort, 7)
+        .collect::<Result<Vec<_>>>()?;
+
+    assert_eq!((INDEX_SIZE + 6) / 7, batches.len());
+    assert!(batches.iter().all(|batch| batch.len() <= 7));
+    assert_eq!(expected, batches.concat());
+
+    Ok(())
+}
@@ -2,29 +2,65 @@ use proc_macro::TokenStream;
 use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
 use quote::{format_ident, quote, quote_spanned};
 use syn::{
-    parse_macro_input, spanned::Spanned, Data, DeriveInput, Field, Fields, GenericArgument,
-    PathArguments, Type, Visibility,
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Field, Fields,
+    GenericArgument, Lit, Meta, NestedMeta, PathArguments, Type, Visibility,
 };
 
-#[proc_macro_derive(Filterable)]
+mod types;
+
+use types::{get_field_type, FieldType, SUPPORTED_TYPES};
+
+/// Fields marked with `#[filterable(enum)]` are treated as categorical:
+/// their type must be `Copy + PartialEq + Into<u64>` and they are
+/// filtered by matching any of the given values instead of by range.
+///
+/// `Vec<T>` fields are multi-valued: every element is indexed and a
+/// filter matches when any of them falls within the range.
+///
+/// Only primitives up to 64 bits are supported, since that's what
+/// tantivy stores: 128-bit integers are rejected at compile time
+/// instead of being silently truncated.
+///
+/// The schema field names are prefixed with `Filterable_field_` by
+/// default, which can be changed with `#[filterable(prefix = "...")]`
+/// on the struct.
+///
+/// Serde's `rename` on fields and `rename_all` on the struct carry
+/// over to the generated query, so it uses the same names on the
+/// wire. The same goes for the `Aggregable` query and result.
+#[proc_macro_derive(Filterable, attributes(filterable))]
 pub fn derive_filter_and_agg(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
-    TokenStream::from(
-        parse_public_fields(&input).map_or_else(render_error, |fields| {
-            make_filter_query(&input.ident, &fields)
-        }),
-    )
+    let result = parse_field_prefix(&input).and_then(|prefix| {
+        let serde_attrs = serde_container_attrs(&input);
+        parse_public_fields(&input)
+            .map(|fields| make_filter_query(&input.ident, &prefix, &fields, &serde_attrs))
+    });
+
+    TokenStream::from(result.unwrap_or_else(render_error))
 }
 
-#[proc_macro_derive(Aggregable)]
+/// Aggregates numeric fields (floats included) over the ranges in
+/// its query. A NaN value doesn't fall within any range, so it's
+/// skipped: it isn't counted in any range nor as missing.
+///
+/// Categorical and multi-valued fields are left out.
+#[proc_macro_derive(Aggregable, attributes(filterable))]
 pub fn derive_agg(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
     TokenStream::from(
         parse_public_fields(&input).map_or_else(render_error, |fields| {
-            let agg_query = make_agg_query(&input.ident, &fields);
-            let agg_result = make_agg_result(&input.ident, &fields);
+            // Ranges make no sense for categorical values and
+            // multi-valued fields would count items more than once
+            let fields: Vec<_> = fields
+                .into_iter()
+                .filter(|f| !f.is_enum && !f.is_multi)
+                .collect();
+            let serde_attrs = serde_container_attrs(&input);
+            let agg_query = make_agg_query(&input.ident, &fields, &serde_attrs);
+            let agg_result = make_agg_result(&input.ident, &fields, &serde_attrs);
             quote! {
                 #agg_query
                 #agg_result
@@ -33,6 +69,35 @@ pub fn derive_agg(input: TokenStream) -> TokenStream {
     )
 }
 
+/// Generates the orderings items can be sorted by: relevance plus
+/// every numeric field, highest first or, with the `_asc` suffix,
+/// lowest first. Categorical and multi-valued fields are left out,
+/// as are the ones marked with `#[sortable(skip)]`.
+///
+/// Sorting relies on the fast fields of the `Filterable` schema, so
+/// that must be derived as well.
+#[proc_macro_derive(Sortable, attributes(sortable))]
+pub fn derive_sortable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let result = get_public_fields(&input).and_then(|fields| {
+        let mut infos = Vec::with_capacity(fields.len());
+
+        for field in fields.into_iter() {
+            if !is_sort_skipped(field)? {
+                infos.push(FieldInfo::new(field)?);
+            }
+        }
+
+        Ok(infos
+            .into_iter()
+            .filter(|f| !f.is_enum && !f.is_multi)
+            .collect::<Vec<_>>())
+    });
+
+    TokenStream::from(result.map_or_else(render_error, |fields| make_sort(&input.ident, &fields)))
+}
+
 fn parse_public_fields(input: &DeriveInput) -> Result<Vec<FieldInfo<'_>>, Error> {
     let fields = get_public_fields(input)?;
 
@@ -69,9 +134,13 @@ struct FieldInfo<'a> {
     ident: &'a Ident,
     ty: &'a Type,
     is_optional: bool,
+    is_multi: bool,
 
     schema: FieldType,
     is_largest: bool,
+    is_enum: bool,
+
+    rename: Option<String>,
 }
 
 impl<'a> FieldInfo<'a> {
@@ -79,36 +148,117 @@ impl<'a> FieldInfo<'a> {
         let span = field.span();
         let ident = field.ident.as_ref().ok_or(Error::BadField(span))?;
 
-        let optional_type = extract_type_if_option(&field.ty);
+        let optional_type = extract_type_if(&field.ty, "Option");
         let is_optional = optional_type.is_some();
         let ty = optional_type.unwrap_or(&field.ty);
 
-        let (schema, is_largest) = get_field_type(&ty).ok_or(Error::BadField(span))?;
+        let is_enum = is_enum_field(field)?;
+
+        // Multi-valued fields are stored as a plain `Vec<T>`: an empty
+        // one already means "no value", so `Option<Vec<T>>` is rejected
+        let multi_type = extract_type_if(ty, "Vec").filter(|_| !is_enum);
+        let is_multi = multi_type.is_some();
+        if is_multi && is_optional {
+            return Err(Error::BadField(span));
+        }
+        let ty = multi_type.unwrap_or(ty);
+
+        let (schema, is_largest) = if is_enum {
+            (FieldType::Unsigned, false)
+        } else {
+            get_field_type(ty).ok_or_else(|| Error::UnsupportedType(ty.span(), ident.clone()))?
+        };
+
         Ok(Self {
             span,
             ident,
             ty,
             is_optional,
+            is_multi,
             schema,
             is_largest,
+            is_enum,
+            rename: serde_rename(&field.attrs, "rename"),
         })
     }
 
     fn span(&self) -> Span {
         self.span
     }
+
+    // Forwards the field's serde rename, if any. Fields derived from
+    // this one (like `_missing` counts) get the rename with a suffix
+    fn serde_rename(&self, suffix: &str) -> TokenStream2 {
+        match &self.rename {
+            Some(rename) => {
+                let rename = format!("{}{}", rename, suffix);
+                quote!(#[serde(rename = #rename)])
+            }
+            None => quote!(),
+        }
+    }
 }
 
-fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
+fn make_filter_query(
+    feat: &Ident,
+    prefix: &str,
+    fields: &[FieldInfo],
+    serde_attrs: &TokenStream2,
+) -> TokenStream2 {
     let name = format_ident!("FilterableFilterQuery{}", &feat);
 
     let query_fields = fields.iter().map(|field| {
         let name = &field.ident;
         let ty = &field.ty;
+        let rename = field.serde_rename("");
+
+        if field.is_enum {
+            quote_spanned! { field.span() =>
+                #[serde(skip_serializing_if = "Option::is_none")]
+                #rename
+                pub #name: Option<Vec<#ty>>
+            }
+        } else {
+            quote_spanned! { field.span() =>
+                #[serde(skip_serializing_if = "Option::is_none")]
+                #rename
+                pub #name: Option<cantine_derive::FilterRange<#ty>>
+            }
+        }
+    });
+
+    let builder_name = format_ident!("FilterableFilterQueryBuilder{}", &feat);
+    let builder_doc = format!(
+        "Builder for [`{query}`]\n\n\
+         ```ignore\n\
+         let query = {query}::builder()\n    .{field}(value)\n    .build();\n\
+         ```",
+        query = name,
+        field = fields[0].ident,
+    );
+
+    let builder_methods = fields.iter().map(|field| {
+        let name = &field.ident;
+        let ty = &field.ty;
 
-        quote_spanned! { field.span() =>
-            #[serde(skip_serializing_if = "Option::is_none")]
-            pub #name: Option<std::ops::Range<#ty>>
+        if field.is_enum {
+            let doc = format!("Matches items with any of the given `{}` values", name);
+            quote_spanned! { field.span()=>
+                #[doc = #doc]
+                pub fn #name(mut self, values: Vec<#ty>) -> Self {
+                    self.query.#name = Some(values);
+                    self
+                }
+            }
+        } else {
+            let doc = format!("Matches items with `{}` within the given range", name);
+            quote_spanned! { field.span()=>
+                #[doc = #doc]
+                pub fn #name<R: Into<cantine_derive::FilterRange<#ty>>>(mut self, range: R) -> Self {
+                    self.query.#name = Some(range.into());
+                    self
+                }
+            }
         }
     });
 
@@ -122,8 +272,7 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
 
     let from_decls = fields.iter().map(|field| {
         let name = field.ident;
-        let field_name = format_ident!("Filterable_field_{}", name);
-        let quoted = format!("{}", &field_name);
+        let quoted = format!("{}{}", prefix, name);
 
         let method = match field.schema {
             FieldType::Unsigned => quote!(add_u64_field),
@@ -138,50 +287,69 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
 
     let try_from_decls = fields.iter().map(|field| {
         let name = field.ident;
-        let field_name = format_ident!("Filterable_field_{}", &name);
-        let err_msg = format!("Missing field for {} ({})", name, field_name);
-        let quoted = format!("{}", field_name);
+        let quoted = format!("{}{}", prefix, name);
+        let err_msg = format!("Missing field for {} ({})", name, quoted);
         quote_spanned! { field.span()=>
             #name: schema.get_field(#quoted).ok_or_else(
                 || tantivy::TantivyError::SchemaError(#err_msg.to_string()))?
         }
     });
 
+    let field_infos = fields.iter().map(|field| {
+        let quoted = format!("{}", field.ident);
+        let is_optional = field.is_optional;
+
+        let kind = field.schema.kind();
+
+        quote_spanned! { field.span()=>
+            cantine_derive::FilterableFieldInfo {
+                name: #quoted,
+                kind: cantine_derive::FilterableFieldKind::#kind,
+                is_optional: #is_optional,
+            }
+        }
+    });
+
     let interpret_code = fields.iter().map(|field| {
         let name = field.ident;
 
-        let (from_code, query_code) = match field.schema {
-            FieldType::Unsigned => (
-                quote!(u64::from),
-                quote!(tantivy::query::RangeQuery::new_u64),
-            ),
-            FieldType::Signed => (
-                quote!(i64::from),
-                quote!(tantivy::query::RangeQuery::new_i64),
-            ),
-            FieldType::Float => (
-                quote!(f64::from),
-                quote!(tantivy::query::RangeQuery::new_f64),
-            ),
+        if field.is_enum {
+            return quote_spanned! { field.span()=>
+                if let Some(ref values) = query.#name {
+                    let terms = values
+                        .iter()
+                        .map(|value| {
+                            let value: u64 = (*value).into();
+                            tantivy::Term::from_field_u64(self.#name, value)
+                        })
+                        .collect();
+                    let query = tantivy::query::BooleanQuery::new_multiterms_query(terms);
+                    result.push(Box::new(query));
+                }
+            };
+        }
+
+        let query_code = match field.schema {
+            FieldType::Unsigned => quote!(tantivy::query::RangeQuery::new_u64_bounds),
+            FieldType::Signed => quote!(tantivy::query::RangeQuery::new_i64_bounds),
+            FieldType::Float => quote!(tantivy::query::RangeQuery::new_f64_bounds),
         };
 
-        let range_code = if field.is_largest {
-            quote! {
-                let range = rr.clone();
-            }
-        } else {
-            quote! {
-                let range = std::ops::Range {
-                    start: #from_code(rr.start),
-                    end: #from_code(rr.end),
-                };
-            }
+        let start = field.schema.widen(field.is_largest, quote!(rr.start));
+        let end = field.schema.widen(field.is_largest, quote!(rr.end));
+        let range_code = quote! {
+            let (start, end) = (#start, #end);
         };
 
         quote_spanned! { field.span()=>
             if let Some(ref rr) = query.#name {
                 #range_code
-                let query = #query_code(self.#name, range);
+                let upper = if rr.inclusive {
+                    std::ops::Bound::Included(end)
+                } else {
+                    std::ops::Bound::Excluded(end)
+                };
+                let query = #query_code(self.#name, std::ops::Bound::Included(start), upper);
                 result.push(Box::new(query));
             }
         }
@@ -190,21 +358,14 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
     let add_to_doc_code = fields.iter().map(|field| {
         let name = field.ident;
 
-        let convert_code = if field.is_largest {
+        let convert_code = if field.is_enum {
             quote_spanned! { field.span()=>
-                let value = value;
+                let value: u64 = value.into();
             }
         } else {
-            match field.schema {
-                FieldType::Unsigned => quote_spanned! { field.span()=>
-                    let value = u64::from(value);
-                },
-                FieldType::Signed => quote_spanned! { field.span()=>
-                    let value = i64::from(value);
-                },
-                FieldType::Float => quote_spanned! { field.span()=>
-                    let value = f64::from(value);
-                },
+            let widened = field.schema.widen(field.is_largest, quote!(value));
+            quote_spanned! { field.span()=>
+                let value = #widened;
             }
         };
 
@@ -221,6 +382,13 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
                     #add_code
                 }
             }
+        } else if field.is_multi {
+            quote_spanned! { field.span()=>
+                for &value in feat.#name.iter() {
+                    #convert_code
+                    #add_code
+                }
+            }
         } else {
             quote_spanned! { field.span()=>
                 let value = feat.#name;
@@ -231,12 +399,33 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
     });
 
     quote! {
-        #[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone)]
+        #[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone, PartialEq)]
         #[serde(deny_unknown_fields)]
+        #serde_attrs
         pub struct #name {
             #(#query_fields),*
         }
 
+        impl #name {
+            pub fn builder() -> #builder_name {
+                #builder_name::default()
+            }
+        }
+
+        #[doc = #builder_doc]
+        #[derive(Default, Debug, Clone)]
+        pub struct #builder_name {
+            query: #name,
+        }
+
+        impl #builder_name {
+            #(#builder_methods)*
+
+            pub fn build(self) -> #name {
+                self.query
+            }
+        }
+
         #[derive(Clone, Debug, PartialEq)]
         pub struct #index_name {
             #(#index_fields),*
@@ -283,12 +472,27 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
         }
 
         impl #index_name {
+            /// Every filterable field, in declaration order
+            pub fn fields() -> &'static [cantine_derive::FilterableFieldInfo] {
+                const FIELDS: &[cantine_derive::FilterableFieldInfo] = &[
+                    #(#field_infos),*
+                ];
+                FIELDS
+            }
+
             pub fn interpret(&self, query: &#name) -> Vec<Box<dyn tantivy::query::Query>> {
                 let mut result : Vec<Box<dyn tantivy::query::Query>> = Vec::new();
                 #(#interpret_code);*
                 result
             }
 
+            pub fn interpret_exclusion(
+                &self,
+                query: &#name
+            ) -> Option<Box<dyn tantivy::query::Query>> {
+                cantine_derive::FilterableSchema::<#feat, #name>::interpret_exclusion(self, query)
+            }
+
             pub fn add_to_doc(&self, doc: &mut tantivy::Document, feat: &#feat) {
                 #(#add_to_doc_code);*
             }
@@ -318,14 +522,115 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
     }
 }
 
-fn make_agg_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
+fn make_sort(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
+    let name = format_ident!("SortableSort{}", &feat);
+
+    let variants: Vec<_> = fields
+        .iter()
+        .map(|field| {
+            let desc = format_ident!("{}", to_camel_case(&field.ident.to_string()));
+            let asc = format_ident!("{}Asc", desc);
+            (field, desc, asc)
+        })
+        .collect();
+
+    let variant_decls = variants.iter().map(|(field, desc, asc)| {
+        let desc_doc = format!("By `{}`, highest first", field.ident);
+        let asc_doc = format!("By `{}`, lowest first", field.ident);
+        quote_spanned! { field.span()=>
+            #[doc = #desc_doc]
+            #desc,
+            #[doc = #asc_doc]
+            #asc
+        }
+    });
+
+    let all_values = variants
+        .iter()
+        .map(|(_field, desc, asc)| quote!(Self::#desc, Self::#asc));
+
+    let ascending = variants
+        .iter()
+        .map(|(_field, _desc, asc)| quote!(Self::#asc));
+
+    let targets = variants.iter().map(|(field, desc, asc)| {
+        let ident = field.ident;
+        let kind = field.schema.kind();
+
+        quote_spanned! { field.span()=>
+            Self::#desc | Self::#asc => cantine_derive::SortTarget::Field(
+                fields.#ident,
+                cantine_derive::FilterableFieldKind::#kind,
+            )
+        }
+    });
+
+    quote! {
+        #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+        #[serde(rename_all = "snake_case")]
+        pub enum #name {
+            /// Most relevant first
+            Relevance,
+            /// Least relevant first
+            RelevanceAsc,
+            #(#variant_decls),*
+        }
+
+        impl cantine_derive::Sortable for #feat {
+            type Sort = #name;
+        }
+
+        impl #name {
+            /// Every sort, in declaration order
+            pub const VALUES: &'static [Self] = &[
+                Self::Relevance,
+                Self::RelevanceAsc,
+                #(#all_values),*
+            ];
+
+            /// Whether items get sorted from lowest to highest
+            pub fn is_ascending(self) -> bool {
+                matches!(self, Self::RelevanceAsc #(| #ascending)*)
+            }
+
+            /// What items get sorted by, with fields taken from
+            /// the given `Filterable` schema
+            pub fn target(
+                self,
+                fields: &<#feat as cantine_derive::Filterable>::Schema
+            ) -> cantine_derive::SortTarget {
+                match self {
+                    Self::Relevance | Self::RelevanceAsc => cantine_derive::SortTarget::Relevance,
+                    #(#targets),*
+                }
+            }
+        }
+    }
+}
+
+fn to_camel_case(snake: &str) -> String {
+    snake
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn make_agg_query(feat: &Ident, fields: &[FieldInfo], serde_attrs: &TokenStream2) -> TokenStream2 {
     let name = format_ident!("AggregableAggregationQuery{}", &feat);
 
     let query_fields = fields.iter().map(|field| {
         let name = &field.ident;
         let ty = &field.ty;
+        let rename = field.serde_rename("");
         quote_spanned! { field.span()=>
             #[serde(default = "Vec::new")]
+            #rename
             pub #name: Vec<std::ops::Range<#ty>>
         }
     });
@@ -338,9 +643,22 @@ fn make_agg_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
         }
     });
 
+    let full_range_bucketed = fields.iter().map(|field| {
+        let name = &field.ident;
+        let ty = &field.ty;
+        quote_spanned! { field.span()=>
+            #name: <#ty as cantine_derive::Bucketable>::histogram(
+                std::#ty::MIN,
+                std::#ty::MAX,
+                num_buckets,
+            )
+        }
+    });
+
     quote! {
         #[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone, PartialEq)]
         #[serde(deny_unknown_fields)]
+        #serde_attrs
         pub struct #name {
             #(#query_fields),*
         }
@@ -351,35 +669,103 @@ fn make_agg_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
                     #(#full_range),*
                 }
             }
+
+            /// Like `full_range`, but splitting every field's domain
+            /// into `num_buckets` equal-width ranges
+            pub fn full_range_bucketed(num_buckets: usize) -> Self {
+                Self {
+                    #(#full_range_bucketed),*
+                }
+            }
+
+            /// Equal-width ranges covering `[min, max)`, ready to be
+            /// used as the aggregation for any field of type `T`
+            pub fn histogram<T: cantine_derive::Bucketable>(
+                min: T,
+                max: T,
+                num_buckets: usize,
+            ) -> Vec<std::ops::Range<T>> {
+                T::histogram(min, max, num_buckets)
+            }
         }
     }
 }
 
-fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
+fn make_agg_result(
+    feature: &Ident,
+    fields: &[FieldInfo],
+    serde_attrs: &TokenStream2,
+) -> TokenStream2 {
     let name = format_ident!("AggregableAggregationResult{}", &feature);
 
     let agg_fields = fields.iter().map(|field| {
         let name = &field.ident;
         let ty = &field.ty;
+        let rename = field.serde_rename("");
 
-        quote_spanned! { field.span()=>
-            #[serde(skip_serializing_if = "Vec::is_empty")]
-            pub #name: Vec<cantine_derive::RangeStats<#ty>>
+        if field.is_optional {
+            let missing = format_ident!("{}_missing", name);
+            let rename_missing = field.serde_rename("_missing");
+            let doc = format!("Number of collected items without a `{}`", name);
+            quote_spanned! { field.span()=>
+                #[serde(skip_serializing_if = "Vec::is_empty")]
+                #rename
+                pub #name: Vec<cantine_derive::RangeStats<#ty>>,
+                #[doc = #doc]
+                #rename_missing
+                pub #missing: u64
+            }
+        } else {
+            quote_spanned! { field.span()=>
+                #[serde(skip_serializing_if = "Vec::is_empty")]
+                #rename
+                pub #name: Vec<cantine_derive::RangeStats<#ty>>
+            }
         }
     });
 
     let merge_code = fields.iter().map(|field| {
         let name = &field.ident;
+        let merge_missing = if field.is_optional {
+            let missing = format_ident!("{}_missing", name);
+            quote!(self.#missing += other.#missing;)
+        } else {
+            quote!()
+        };
+
         quote_spanned! { field.span()=>
             for (idx, stats) in self.#name.iter_mut().enumerate() {
                 stats.merge(&other.#name[idx]);
             }
+            #merge_missing
+        }
+    });
+
+    let check_size_code = fields.iter().map(|field| {
+        let name = &field.ident;
+        let err_msg = format!("Cannot merge aggregations for {}", name);
+        quote_spanned! { field.span()=>
+            if self.#name.len() != other.#name.len() {
+                return Err(tantivy::TantivyError::SystemError(format!(
+                    "{}: expected {} ranges, got {}",
+                    #err_msg,
+                    self.#name.len(),
+                    other.#name.len()
+                )));
+            }
         }
     });
 
     let agg_query = format_ident!("AggregableAggregationQuery{}", &feature);
     let convert_code = fields.iter().map(|field| {
         let name = &field.ident;
+        let init_missing = if field.is_optional {
+            let missing = format_ident!("{}_missing", name);
+            quote!(, #missing: 0)
+        } else {
+            quote!()
+        };
+
         quote_spanned! { field.span()=>
             #name:
                 if src.#name.is_empty() {
@@ -387,12 +773,14 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
                 } else {
                     src.#name.iter().map(From::from).collect()
                 }
+            #init_missing
         }
     });
 
     let collect_code = fields.iter().map(|field| {
         let name = &field.ident;
         if field.is_optional {
+            let missing = format_ident!("{}_missing", name);
             quote_spanned! { field.span()=>
                 if let Some(feat) = feature.#name {
                     for (idx, range) in query.#name.iter().enumerate() {
@@ -400,6 +788,8 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
                             self.#name[idx].collect(feat);
                         }
                     }
+                } else {
+                    self.#missing += 1;
                 }
             }
         } else {
@@ -415,8 +805,11 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
 
     quote! {
         #[derive(serde::Serialize, Default, Debug, Clone)]
+        #serde_attrs
         pub struct #name {
-            #(#agg_fields),*
+            #(#agg_fields,)*
+            /// Number of items that couldn't be read, so weren't collected
+            pub skipped: u64
         }
 
         impl cantine_derive::Aggregable for #feature {
@@ -425,8 +818,8 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
         }
 
         impl cantine_derive::Aggregator<#agg_query, #feature> for #name {
-            fn merge_same_size(&mut self, other: &Self) {
-                <#name>::merge_same_size(self, other);
+            fn merge_same_size(&mut self, other: &Self) -> tantivy::Result<()> {
+                <#name>::merge_same_size(self, other)
             }
 
             fn collect(&mut self, query: &#agg_query, feature: &#feature) {
@@ -436,11 +829,20 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
             fn from_query(query: &#agg_query) -> Self {
                 <#name>::from(query)
             }
+
+            fn skip(&mut self) {
+                self.skipped += 1;
+            }
         }
 
         impl #name {
-            fn merge_same_size(&mut self, other: &Self) {
+            fn merge_same_size(&mut self, other: &Self) -> tantivy::Result<()> {
+                // Validate everything first so that a failure
+                // doesn't leave `self` partially merged
+                #(#check_size_code)*
                 #(#merge_code);*
+                self.skipped += other.skipped;
+                Ok(())
             }
 
             fn collect(&mut self, query: &#agg_query, feature: &#feature) {
@@ -452,7 +854,8 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
         impl From<&#agg_query> for #name {
             fn from(src: &#agg_query) -> Self {
                 Self {
-                    #(#convert_code),*
+                    #(#convert_code,)*
+                    skipped: 0
                 }
             }
         }
@@ -466,9 +869,9 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
     }
 }
 
-fn extract_type_if_option(ty: &Type) -> Option<&Type> {
+fn extract_type_if<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
     match ty {
-        Type::Path(tp) if tp.path.segments.first()?.ident == "Option" => {
+        Type::Path(tp) if tp.path.segments.first()?.ident == wrapper => {
             match tp.path.segments.first()?.arguments {
                 PathArguments::AngleBracketed(ref params) => match params.args.first()? {
                     GenericArgument::Type(ty) => Some(ty),
@@ -481,33 +884,119 @@ fn extract_type_if_option(ty: &Type) -> Option<&Type> {
     }
 }
 
-enum FieldType {
-    Unsigned,
-    Signed,
-    Float,
+fn is_enum_field(field: &Field) -> Result<bool, Error> {
+    let mut is_enum = false;
+
+    for attr in field.attrs.iter() {
+        if !attr.path.is_ident("filterable") {
+            continue;
+        }
+
+        match attr.parse_meta() {
+            Ok(Meta::List(list)) => {
+                for nested in list.nested.iter() {
+                    match nested {
+                        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("enum") => {
+                            is_enum = true;
+                        }
+                        _ => return Err(Error::UnknownAttribute(nested.span())),
+                    }
+                }
+            }
+            _ => return Err(Error::UnknownAttribute(attr.span())),
+        }
+    }
+
+    Ok(is_enum)
 }
 
-fn get_field_type(ty: &Type) -> Option<(FieldType, bool)> {
-    match ty {
-        Type::Path(tp) if tp.path.segments.len() == 1 => {
-            match tp.path.segments.first()?.ident.to_string().as_str() {
-                "u64" => Some((FieldType::Unsigned, true)),
-                "u8" | "u16" | "u32" => Some((FieldType::Unsigned, false)),
+fn is_sort_skipped(field: &Field) -> Result<bool, Error> {
+    let mut skipped = false;
+
+    for attr in field.attrs.iter() {
+        if !attr.path.is_ident("sortable") {
+            continue;
+        }
+
+        match attr.parse_meta() {
+            Ok(Meta::List(list)) => {
+                for nested in list.nested.iter() {
+                    match nested {
+                        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => {
+                            skipped = true;
+                        }
+                        _ => return Err(Error::UnknownAttribute(nested.span())),
+                    }
+                }
+            }
+            _ => return Err(Error::UnknownAttribute(attr.span())),
+        }
+    }
 
-                "i64" => Some((FieldType::Signed, true)),
-                "i8" | "i16" | "i32" => Some((FieldType::Signed, false)),
+    Ok(skipped)
+}
 
-                "f64" => Some((FieldType::Float, true)),
-                "f32" => Some((FieldType::Float, false)),
+// Reads `#[serde(key = "value")]` from the attributes. Anything
+// else is serde's business, so it's ignored instead of rejected
+fn serde_rename(attrs: &[Attribute], key: &str) -> Option<String> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("serde"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|nested| match nested {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(key) => match nv.lit {
+                Lit::Str(value) => Some(value.value()),
                 _ => None,
+            },
+            _ => None,
+        })
+        .last()
+}
+
+fn serde_container_attrs(input: &DeriveInput) -> TokenStream2 {
+    match serde_rename(&input.attrs, "rename_all") {
+        Some(rename_all) => quote!(#[serde(rename_all = #rename_all)]),
+        None => quote!(),
+    }
+}
+
+fn parse_field_prefix(input: &DeriveInput) -> Result<String, Error> {
+    let mut prefix = String::from("Filterable_field_");
+
+    for attr in input.attrs.iter() {
+        if !attr.path.is_ident("filterable") {
+            continue;
+        }
+
+        match attr.parse_meta() {
+            Ok(Meta::List(list)) => {
+                for nested in list.nested.iter() {
+                    match nested {
+                        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("prefix") => {
+                            match nv.lit {
+                                Lit::Str(ref value) => prefix = value.value(),
+                                _ => return Err(Error::UnknownAttribute(nv.lit.span())),
+                            }
+                        }
+                        _ => return Err(Error::UnknownAttribute(nested.span())),
+                    }
+                }
             }
+            _ => return Err(Error::UnknownAttribute(attr.span())),
         }
-        _ => None,
     }
+
+    Ok(prefix)
 }
 
 enum Error {
     BadField(Span),
+    UnsupportedType(Span, Ident),
+    UnknownAttribute(Span),
     BadInput,
 }
 
@@ -518,6 +1007,21 @@ fn render_error(err: Error) -> TokenStream2 {
                 compile_error!("Unsupported field");
             }
         }
+        Error::UnsupportedType(span, ident) => {
+            let msg = format!(
+                "Unsupported type for field `{}`: expected one of {}, \
+                 optionally wrapped in an `Option` or a `Vec`",
+                ident, SUPPORTED_TYPES
+            );
+            quote_spanned! { span =>
+                compile_error!(#msg);
+            }
+        }
+        Error::UnknownAttribute(span) => {
+            quote_spanned! { span =>
+                compile_error!("Unsupported filterable attribute");
+            }
+        }
         Error::BadInput => panic!("Only structs with public named fields are supported"),
     }
 }
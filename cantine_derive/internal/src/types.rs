@@ -0,0 +1,110 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Type;
+
+/// How a numeric field is stored in the index
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FieldType {
+    Unsigned,
+    Signed,
+    Float,
+}
+
+impl FieldType {
+    /// The `FilterableFieldKind` variant for this type
+    pub(crate) fn kind(self) -> TokenStream {
+        match self {
+            FieldType::Unsigned => quote!(U64),
+            FieldType::Signed => quote!(I64),
+            FieldType::Float => quote!(F64),
+        }
+    }
+
+    /// Code that turns `value` into the type tantivy stores. Fields
+    /// that already are of the largest type are left untouched
+    pub(crate) fn widen(self, is_largest: bool, value: TokenStream) -> TokenStream {
+        if is_largest {
+            return value;
+        }
+
+        match self {
+            FieldType::Unsigned => quote!(u64::from(#value)),
+            FieldType::Signed => quote!(i64::from(#value)),
+            FieldType::Float => quote!(f64::from(#value)),
+        }
+    }
+}
+
+/// What `get_field_type` accepts, for error messages
+pub(crate) const SUPPORTED_TYPES: &str = "u8, u16, u32, u64, i8, i16, i32, i64, f32, f64";
+
+/// Classifies a primitive type, also telling wether it's the
+/// largest one of its kind
+pub(crate) fn get_field_type(ty: &Type) -> Option<(FieldType, bool)> {
+    match ty {
+        Type::Path(tp) if tp.path.segments.len() == 1 => {
+            match tp.path.segments.first()?.ident.to_string().as_str() {
+                "u64" => Some((FieldType::Unsigned, true)),
+                "u8" | "u16" | "u32" => Some((FieldType::Unsigned, false)),
+
+                "i64" => Some((FieldType::Signed, true)),
+                "i8" | "i16" | "i32" => Some((FieldType::Signed, false)),
+
+                "f64" => Some((FieldType::Float, true)),
+                "f32" => Some((FieldType::Float, false)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(ty: &str) -> Option<(FieldType, bool)> {
+        get_field_type(&syn::parse_str(ty).unwrap())
+    }
+
+    #[test]
+    fn classifies_every_supported_primitive() {
+        for (ty, wanted) in &[
+            ("u8", (FieldType::Unsigned, false)),
+            ("u16", (FieldType::Unsigned, false)),
+            ("u32", (FieldType::Unsigned, false)),
+            ("u64", (FieldType::Unsigned, true)),
+            ("i8", (FieldType::Signed, false)),
+            ("i16", (FieldType::Signed, false)),
+            ("i32", (FieldType::Signed, false)),
+            ("i64", (FieldType::Signed, true)),
+            ("f32", (FieldType::Float, false)),
+            ("f64", (FieldType::Float, true)),
+        ] {
+            assert_eq!(Some(*wanted), classify(ty), "{}", ty);
+        }
+    }
+
+    #[test]
+    fn rejects_everything_else() {
+        for ty in &[
+            "u128", "i128", "usize", "bool", "String", "std::u64", "Vec<u64>", "&u8",
+        ] {
+            assert_eq!(None, classify(ty), "{}", ty);
+        }
+    }
+
+    #[test]
+    fn only_smaller_types_are_widened() {
+        let widen = |ty: &str| {
+            let (schema, is_largest) = classify(ty).unwrap();
+            schema.widen(is_largest, quote!(value)).to_string()
+        };
+
+        assert_eq!(quote!(value).to_string(), widen("u64"));
+        assert_eq!(quote!(u64::from(value)).to_string(), widen("u8"));
+        assert_eq!(quote!(i64::from(value)).to_string(), widen("i16"));
+        assert_eq!(quote!(f64::from(value)).to_string(), widen("f32"));
+        assert_eq!(quote!(value).to_string(), widen("f64"));
+    }
+}
@@ -1,14 +1,17 @@
-use std::ops::Range;
+use std::{
+    cmp::Ordering,
+    ops::{Range, RangeInclusive},
+};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tantivy::{
     collector::{Collector, SegmentCollector},
-    query::Query,
-    schema::{IntOptions, Schema, SchemaBuilder},
+    query::{AllQuery, BooleanQuery, Occur, Query},
+    schema::{Field, IntOptions, Schema, SchemaBuilder},
     DocId, Document, Result, Score, SegmentOrdinal, SegmentReader,
 };
 
-pub use cantine_derive_internal::{Aggregable, Filterable};
+pub use cantine_derive_internal::{Aggregable, Filterable, Sortable};
 
 pub trait Filterable: Sized {
     type Query;
@@ -21,61 +24,317 @@ pub trait Filterable: Sized {
 pub trait FilterableSchema<T, Q>: Sized {
     fn add_to_doc(&self, doc: &mut Document, item: &T);
     fn interpret(&self, query: &Q) -> Vec<Box<dyn Query>>;
+
+    /// Builds a query that matches every document *except* those
+    /// matching any of the ranges in the given query. Yields `None`
+    /// if there's nothing to exclude
+    ///
+    /// Documents without a value for an optional field never fall
+    /// within its range, so they aren't excluded because of it
+    fn interpret_exclusion(&self, query: &Q) -> Option<Box<dyn Query>> {
+        let clauses = self.interpret(query);
+
+        if clauses.is_empty() {
+            return None;
+        }
+
+        let mut subqueries: Vec<(Occur, Box<dyn Query>)> = Vec::with_capacity(clauses.len() + 1);
+        subqueries.push((Occur::Must, Box::new(AllQuery)));
+        subqueries.extend(clauses.into_iter().map(|clause| (Occur::MustNot, clause)));
+
+        Some(Box::new(BooleanQuery::from(subqueries)))
+    }
+}
+
+pub trait Sortable: Filterable {
+    type Sort;
+}
+
+/// What a sort orders items by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortTarget {
+    /// The score computed by the query
+    Relevance,
+    /// The value of a fast field of the `Filterable` schema
+    Field(Field, FilterableFieldKind),
+}
+
+/// A range of values to filter on
+///
+/// It's half-open (`[start, end)`) like `std::ops::Range` unless
+/// `inclusive` is set, in which case `end` matches too. On the wire
+/// it's either `{"start": 1, "end": 2, "inclusive": true}` or simply
+/// `[1, 2, true]`, with `inclusive` being optional.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FilterRange<T> {
+    pub start: T,
+    pub end: T,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub inclusive: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl<T> From<Range<T>> for FilterRange<T> {
+    fn from(src: Range<T>) -> Self {
+        Self {
+            start: src.start,
+            end: src.end,
+            inclusive: false,
+        }
+    }
+}
+
+impl<T> From<RangeInclusive<T>> for FilterRange<T> {
+    fn from(src: RangeInclusive<T>) -> Self {
+        let (start, end) = src.into_inner();
+        Self {
+            start,
+            end,
+            inclusive: true,
+        }
+    }
+}
+
+/// How a filterable field is indexed
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterableFieldKind {
+    U64,
+    I64,
+    F64,
 }
 
+/// Metadata about a field of a `Filterable` struct, as returned
+/// by the generated `fields()` function of its schema
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterableFieldInfo {
+    pub name: &'static str,
+    pub kind: FilterableFieldKind,
+    pub is_optional: bool,
+}
+
+/// Statistics about the values collected for an aggregation range
+///
+/// `min` and `max` are only known after at least one value gets
+/// collected, so they are `None` for ranges that matched nothing.
+///
+/// Values collected via `collect_weighted` also accumulate into
+/// `weighted_sum` and `weight_sum`, which are left out of the
+/// serialized output while zero.
 #[derive(Serialize, Debug, Clone)]
 pub struct RangeStats<T> {
-    pub min: T,
-    pub max: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<T>,
     pub count: u64,
+    pub sum: f64,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub weighted_sum: f64,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub weight_sum: f64,
+}
+
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
 }
 
 impl<T> RangeStats<T>
 where
-    T: PartialOrd + Copy,
+    T: PartialOrd + Copy + AsF64,
 {
     pub fn collect(&mut self, value: T) {
-        if self.min > value {
-            self.min = value;
-        }
-
-        if self.max < value {
-            self.max = value;
-        }
+        self.collect_min(value);
+        self.collect_max(value);
 
         self.count += 1;
+        self.sum += value.as_f64();
+    }
+
+    /// Like `collect`, but also accounts for the value's `weight`
+    /// in the weighted average
+    pub fn collect_weighted(&mut self, value: T, weight: f64) {
+        self.collect(value);
+
+        self.weighted_sum += value.as_f64() * weight;
+        self.weight_sum += weight;
     }
 
     pub fn merge(&mut self, other: &Self) {
-        if self.min > other.min {
-            self.min = other.min;
+        if let Some(other_min) = other.min {
+            self.collect_min(other_min);
         }
 
-        if self.max < other.max {
-            self.max = other.max;
+        if let Some(other_max) = other.max {
+            self.collect_max(other_max);
         }
 
         self.count += other.count;
+        self.sum += other.sum;
+        self.weighted_sum += other.weighted_sum;
+        self.weight_sum += other.weight_sum;
+    }
+
+    fn collect_min(&mut self, value: T) {
+        if self.min.map_or(true, |min| min > value) {
+            self.min = Some(value);
+        }
+    }
+
+    fn collect_max(&mut self, value: T) {
+        if self.max.map_or(true, |max| max < value) {
+            self.max = Some(value);
+        }
     }
+
+    pub fn avg(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.sum / self.count as f64)
+        }
+    }
+
+    /// The average of the values collected via `collect_weighted`,
+    /// or `None` if their weights add up to zero
+    pub fn weighted_avg(&self) -> Option<f64> {
+        if self.weight_sum == 0.0 {
+            None
+        } else {
+            Some(self.weighted_sum / self.weight_sum)
+        }
+    }
+}
+
+/// Lossy conversion of the supported primitives into `f64`, so that
+/// `RangeStats` can accumulate a sum regardless of the field type
+pub trait AsF64: Copy {
+    fn as_f64(self) -> f64;
+}
+
+macro_rules! impl_as_f64 {
+    ($($type: ty),+) => {
+        $(
+            impl AsF64 for $type {
+                fn as_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )+
+    };
+}
+
+impl_as_f64!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl<T> RangeStats<T> {
+    /// Extracts the counts from a list of stats, i.e.: the bars of
+    /// a histogram when the stats came from `Bucketable::histogram`
+    pub fn counts(stats: &[Self]) -> Vec<u64> {
+        stats.iter().map(|s| s.count).collect()
+    }
+}
+
+/// Types that can be split into evenly sized buckets
+pub trait Bucketable: Copy + PartialOrd {
+    /// Splits `[min, max)` into (at most) `num_buckets` contiguous
+    /// ranges of equal width
+    ///
+    /// Integer types never yield empty ranges: when the span is
+    /// smaller than `num_buckets` you get one bucket per value
+    /// instead. An empty span or zero buckets yields no ranges.
+    fn histogram(min: Self, max: Self, num_buckets: usize) -> Vec<Range<Self>>;
+}
+
+macro_rules! impl_bucketable_int {
+    ($($type: ty),+) => {
+        $(
+            impl Bucketable for $type {
+                fn histogram(min: Self, max: Self, num_buckets: usize) -> Vec<Range<Self>> {
+                    if num_buckets == 0 || min >= max {
+                        return Vec::new();
+                    }
+
+                    // Wide enough to never overflow for any supported type
+                    let start = min as i128;
+                    let span = max as i128 - start;
+                    let num_buckets = span.min(num_buckets as i128);
+
+                    (0..num_buckets)
+                        .map(|idx| {
+                            let lower = start + span * idx / num_buckets;
+                            let upper = start + span * (idx + 1) / num_buckets;
+                            (lower as $type)..(upper as $type)
+                        })
+                        .collect()
+                }
+            }
+        )+
+    };
 }
 
+macro_rules! impl_bucketable_float {
+    ($($type: ty),+) => {
+        $(
+            impl Bucketable for $type {
+                fn histogram(min: Self, max: Self, num_buckets: usize) -> Vec<Range<Self>> {
+                    // Also bails out when any of the boundaries is NaN
+                    if num_buckets == 0 || min.partial_cmp(&max) != Some(Ordering::Less) {
+                        return Vec::new();
+                    }
+
+                    let (start, end) = (f64::from(min), f64::from(max));
+                    // Interpolating instead of accumulating a width so that
+                    // spans as wide as the whole domain don't overflow
+                    let boundary = |idx: usize| {
+                        if idx == 0 {
+                            min
+                        } else if idx == num_buckets {
+                            max
+                        } else {
+                            let ratio = idx as f64 / num_buckets as f64;
+                            (start * (1.0 - ratio) + end * ratio) as $type
+                        }
+                    };
+
+                    (0..num_buckets)
+                        .map(|idx| boundary(idx)..boundary(idx + 1))
+                        .collect()
+                }
+            }
+        )+
+    };
+}
+
+impl_bucketable_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+impl_bucketable_float!(f32, f64);
+
 impl<T> From<&Range<T>> for RangeStats<T>
 where
     T: PartialOrd + Copy,
 {
-    fn from(src: &Range<T>) -> Self {
+    fn from(_src: &Range<T>) -> Self {
         Self {
-            min: src.end,
-            max: src.start,
+            min: None,
+            max: None,
             count: 0,
+            sum: 0.0,
+            weighted_sum: 0.0,
+            weight_sum: 0.0,
         }
     }
 }
 
 pub trait Aggregator<Q, F>: Send + Sync {
-    fn merge_same_size(&mut self, other: &Self);
+    /// Merges `other` into `self`, failing if they were created
+    /// from queries with a different number of ranges
+    fn merge_same_size(&mut self, other: &Self) -> Result<()>;
     fn collect(&mut self, query: &Q, feature: &F);
     fn from_query(query: &Q) -> Self;
+    /// Records that an item couldn't be read, so there was
+    /// nothing to collect. Does nothing by default
+    fn skip(&mut self) {}
 }
 
 pub trait Aggregable: Sized + Send + Sync {
@@ -83,6 +342,47 @@ pub trait Aggregable: Sized + Send + Sync {
     type Agg: Aggregator<Self::Query, Self>;
 }
 
+/// An `Aggregable` that aggregates nothing, for code that's generic
+/// over whether to aggregate at all: every item is ignored and
+/// merging always succeeds
+///
+/// ```rust
+/// # use tantivy::{query::AllQuery, schema::SchemaBuilder, DocId, Index, SegmentReader};
+/// use cantine_derive::{AggregableCollector, NoopAggregable, NoopAggregator};
+///
+/// # let index = Index::create_in_ram(SchemaBuilder::new().build());
+/// # let searcher = index.reader()?.searcher();
+/// let collector = AggregableCollector::<NoopAggregable, _>::new((), |_: &SegmentReader| {
+///     |_: DocId| Some(NoopAggregable)
+/// });
+///
+/// let NoopAggregator = searcher.search(&AllQuery, &collector)?;
+/// # Ok::<(), tantivy::TantivyError>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoopAggregable;
+
+/// The (empty) result of aggregating `NoopAggregable` items
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoopAggregator;
+
+impl<Q, F> Aggregator<Q, F> for NoopAggregator {
+    fn merge_same_size(&mut self, _other: &Self) -> Result<()> {
+        Ok(())
+    }
+
+    fn collect(&mut self, _query: &Q, _feature: &F) {}
+
+    fn from_query(_query: &Q) -> Self {
+        NoopAggregator
+    }
+}
+
+impl Aggregable for NoopAggregable {
+    type Query = ();
+    type Agg = NoopAggregator;
+}
+
 pub trait AggregableForSegment<T>: Send + Sync {
     type Output: AggregableForDoc<T>;
     fn for_segment(&self, reader: &SegmentReader) -> Self::Output;
@@ -100,9 +400,60 @@ where
     }
 }
 
-pub struct AggregableCollector<T: Aggregable, F> {
+/// Decides, for each segment, which documents get aggregated
+pub trait AggregationFilterForSegment: Send + Sync {
+    type Output: AggregationFilterForDoc;
+    fn for_segment(&self, reader: &SegmentReader) -> Self::Output;
+}
+
+impl<F, O> AggregationFilterForSegment for F
+where
+    F: Send + Sync + Fn(&SegmentReader) -> O,
+    O: AggregationFilterForDoc,
+{
+    type Output = O;
+
+    fn for_segment(&self, reader: &SegmentReader) -> Self::Output {
+        (self)(reader)
+    }
+}
+
+pub trait AggregationFilterForDoc {
+    fn accepts(&self, doc: DocId) -> bool;
+}
+
+impl<F> AggregationFilterForDoc for F
+where
+    F: Fn(DocId) -> bool,
+{
+    fn accepts(&self, doc: DocId) -> bool {
+        (self)(doc)
+    }
+}
+
+/// The default filter of an `AggregableCollector`: every matching
+/// document gets aggregated
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AggregateAll;
+
+impl AggregationFilterForSegment for AggregateAll {
+    type Output = AggregateAll;
+
+    fn for_segment(&self, _reader: &SegmentReader) -> Self::Output {
+        AggregateAll
+    }
+}
+
+impl AggregationFilterForDoc for AggregateAll {
+    fn accepts(&self, _doc: DocId) -> bool {
+        true
+    }
+}
+
+pub struct AggregableCollector<T: Aggregable, F, P = AggregateAll> {
     query: T::Query,
     reader_factory: F,
+    filter_factory: P,
 }
 
 impl<T, F, O> AggregableCollector<T, F>
@@ -115,18 +466,40 @@ where
         Self {
             query,
             reader_factory,
+            filter_factory: AggregateAll,
         }
     }
 }
 
-impl<T, F, O> Collector for AggregableCollector<T, F>
+impl<T, F, P> AggregableCollector<T, F, P>
+where
+    T: 'static + Aggregable,
+{
+    /// Only aggregates the documents accepted by the filter, which
+    /// is created for every segment like the item reader is. Other
+    /// matching documents aren't read at all
+    pub fn with_filter<N>(self, filter_factory: N) -> AggregableCollector<T, F, N>
+    where
+        N: AggregationFilterForSegment,
+    {
+        AggregableCollector {
+            query: self.query,
+            reader_factory: self.reader_factory,
+            filter_factory,
+        }
+    }
+}
+
+impl<T, F, O, P> Collector for AggregableCollector<T, F, P>
 where
     T: 'static + Aggregable,
     F: Send + AggregableForSegment<T, Output = O>,
     O: 'static + AggregableForDoc<T>,
+    P: AggregationFilterForSegment,
+    P::Output: 'static,
 {
     type Fruit = T::Agg;
-    type Child = AggregableSegmentCollector<T, O>;
+    type Child = AggregableSegmentCollector<T, O, P::Output>;
 
     fn for_segment(
         &self,
@@ -137,6 +510,7 @@ where
             agg: T::Agg::from_query(&self.query),
             query: self.query.clone(),
             reader: self.reader_factory.for_segment(segment_reader),
+            filter: self.filter_factory.for_segment(segment_reader),
         })
     }
 
@@ -152,7 +526,7 @@ where
             .unwrap_or_else(|| T::Agg::from_query(&self.query));
 
         for fruit in iter {
-            first.merge_same_size(&fruit);
+            first.merge_same_size(&fruit)?;
         }
 
         Ok(first)
@@ -172,22 +546,30 @@ where
     }
 }
 
-pub struct AggregableSegmentCollector<T: Aggregable, F> {
+pub struct AggregableSegmentCollector<T: Aggregable, F, P = AggregateAll> {
     agg: T::Agg,
     query: T::Query,
     reader: F,
+    filter: P,
 }
 
-impl<T, F> SegmentCollector for AggregableSegmentCollector<T, F>
+impl<T, F, P> SegmentCollector for AggregableSegmentCollector<T, F, P>
 where
     T: 'static + Aggregable,
     F: 'static + AggregableForDoc<T>,
+    P: 'static + AggregationFilterForDoc,
 {
     type Fruit = T::Agg;
 
     fn collect(&mut self, doc: DocId, _score: Score) {
+        if !self.filter.accepts(doc) {
+            return;
+        }
+
         if let Some(item) = self.reader.for_doc(doc) {
             self.agg.collect(&self.query, &item);
+        } else {
+            self.agg.skip();
         }
     }
 
@@ -210,10 +592,11 @@ mod tests {
 
     // XXX Who will test the tests?
     impl Aggregator<Vec<Range<i16>>, i16> for Vec<i16> {
-        fn merge_same_size(&mut self, other: &Self) {
+        fn merge_same_size(&mut self, other: &Self) -> Result<()> {
             for (idx, tally) in other.iter().enumerate() {
                 self[idx] += tally;
             }
+            Ok(())
         }
         fn collect(&mut self, query: &Vec<Range<i16>>, feature: &i16) {
             for (idx, range) in query.iter().enumerate() {
@@ -273,4 +656,69 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn weighted_stats() {
+        let mut stats = RangeStats::from(&(0..1000));
+        assert_eq!(None, stats.weighted_avg());
+
+        stats.collect_weighted(100, 3.0);
+        stats.collect_weighted(200, 1.0);
+        // Unweighted values don't affect the weighted average
+        stats.collect(900);
+
+        assert_eq!(3, stats.count);
+        assert_eq!(Some(400.0), stats.avg());
+        assert_eq!(Some(125.0), stats.weighted_avg());
+
+        let mut other = RangeStats::from(&(0..1000));
+        other.collect_weighted(500, 4.0);
+        stats.merge(&other);
+
+        assert_eq!(4, stats.count);
+        assert_eq!(Some(312.5), stats.weighted_avg());
+    }
+
+    #[test]
+    fn integer_histogram() {
+        assert_eq!(vec![0..5, 5..10], u8::histogram(0, 10, 2));
+        assert_eq!(vec![-3..0, 0..3, 3..7], i32::histogram(-3, 7, 3));
+
+        // More buckets than values: one bucket per value
+        assert_eq!(vec![10..11, 11..12, 12..13], u64::histogram(10, 13, 20));
+
+        // Extremes don't overflow
+        let buckets = i64::histogram(std::i64::MIN, std::i64::MAX, 4);
+        assert_eq!(4, buckets.len());
+        assert_eq!(std::i64::MIN, buckets[0].start);
+        assert_eq!(std::i64::MAX, buckets[3].end);
+
+        assert!(u16::histogram(0, 10, 0).is_empty());
+        assert!(u16::histogram(10, 10, 3).is_empty());
+        assert!(u16::histogram(10, 0, 3).is_empty());
+    }
+
+    #[test]
+    fn float_histogram() {
+        assert_eq!(vec![0.0..0.5, 0.5..1.0], f64::histogram(0.0, 1.0, 2));
+
+        let buckets = f32::histogram(0.0, 1.0, 3);
+        assert_eq!(3, buckets.len());
+        // Contiguous and ending exactly at max
+        assert_eq!(buckets[0].end, buckets[1].start);
+        assert_eq!(buckets[1].end, buckets[2].start);
+        assert_eq!(1.0, buckets[2].end);
+
+        // Works for the whole domain
+        let buckets = f64::histogram(std::f64::MIN, std::f64::MAX, 4);
+        assert_eq!(4, buckets.len());
+        assert!(buckets
+            .iter()
+            .all(|r| r.start.is_finite() && r.start < r.end));
+        assert_eq!(std::f64::MAX, buckets[3].end);
+
+        assert!(f64::histogram(0.0, 1.0, 0).is_empty());
+        assert!(f64::histogram(1.0, 0.0, 2).is_empty());
+        assert!(f64::histogram(std::f64::NAN, 1.0, 2).is_empty());
+    }
 }
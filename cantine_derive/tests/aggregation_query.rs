@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use tantivy::{query::AllQuery, schema::SchemaBuilder, Document, Index, SegmentReader};
+use tantivy::{query::AllQuery, schema::SchemaBuilder, DocId, Document, Index, SegmentReader};
 
 use cantine_derive::{Aggregable, AggregableCollector, RangeStats};
 
@@ -96,6 +96,207 @@ fn collect_works_as_intended() {
     assert_eq!(vec![2], agg_counts(&agg.c));
 }
 
+#[test]
+#[allow(clippy::float_cmp)]
+fn sum_and_avg_are_tracked() {
+    let query = FeatAggregationQuery {
+        a: vec![0..10, 100..200],
+        ..FeatAggregationQuery::default()
+    };
+
+    let mut agg = FeatAggregationResult::from(&query);
+    let mut other = FeatAggregationResult::from(&query);
+
+    for a in 1..=4 {
+        agg.collect(
+            &query,
+            &Feat {
+                a,
+                ..Feat::default()
+            },
+        );
+    }
+
+    assert_eq!(10.0, agg.a[0].sum);
+    assert_eq!(Some(2.5), agg.a[0].avg());
+    // Nothing collected, so there's no average
+    assert_eq!(None, agg.a[1].avg());
+
+    other.collect(
+        &query,
+        &Feat {
+            a: 5,
+            ..Feat::default()
+        },
+    );
+
+    agg.merge_same_size(&other).unwrap();
+
+    assert_eq!(15.0, agg.a[0].sum);
+    assert_eq!(Some(3.0), agg.a[0].avg());
+}
+
+#[test]
+fn empty_ranges_have_no_min_max() {
+    let query = FeatAggregationQuery {
+        a: vec![0..10, 10..20],
+        b: vec![-5..5],
+        ..FeatAggregationQuery::default()
+    };
+
+    let mut agg = FeatAggregationResult::from(&query);
+
+    agg.collect(
+        &query,
+        &Feat {
+            a: 7,
+            ..Feat::default()
+        },
+    );
+
+    // Never collected: no bogus min/max
+    assert_eq!(0, agg.a[1].count);
+    assert_eq!(None, agg.a[1].min);
+    assert_eq!(None, agg.a[1].max);
+    assert_eq!(0, agg.b[0].count);
+    assert_eq!(None, agg.b[0].min);
+    assert_eq!(None, agg.b[0].max);
+
+    assert_eq!(1, agg.a[0].count);
+    assert_eq!(Some(7), agg.a[0].min);
+    assert_eq!(Some(7), agg.a[0].max);
+
+    // Merging with empty stats keeps things as they were
+    let empty = FeatAggregationResult::from(&query);
+    agg.merge_same_size(&empty).unwrap();
+
+    assert_eq!(Some(7), agg.a[0].min);
+    assert_eq!(Some(7), agg.a[0].max);
+    assert_eq!(None, agg.a[1].min);
+
+    let serialized = serde_json::to_value(&agg.a[1]).unwrap();
+    assert!(serialized.get("min").is_none());
+    assert!(serialized.get("max").is_none());
+}
+
+#[test]
+fn merging_different_sizes_fails() {
+    let mut agg = FeatAggregationResult::from(FeatAggregationQuery {
+        a: vec![0..10, 10..20],
+        c: vec![0.0..1.0],
+        ..FeatAggregationQuery::default()
+    });
+
+    let query = FeatAggregationQuery {
+        a: vec![0..10],
+        c: vec![0.0..1.0],
+        ..FeatAggregationQuery::default()
+    };
+    let mut other = FeatAggregationResult::from(&query);
+    other.collect(
+        &query,
+        &Feat {
+            a: 1,
+            c: 0.5,
+            ..Feat::default()
+        },
+    );
+
+    assert!(agg.merge_same_size(&other).is_err());
+
+    // Nothing got merged
+    assert_eq!(vec![0, 0], agg_counts(&agg.a));
+    assert_eq!(vec![0], agg_counts(&agg.c));
+}
+
+#[test]
+fn histogram_aggregation() {
+    let query = FeatAggregationQuery {
+        a: FeatAggregationQuery::histogram(0, 100, 4),
+        ..FeatAggregationQuery::default()
+    };
+
+    let mut agg = FeatAggregationResult::from(&query);
+
+    for a in &[1, 30, 42, 99, 100] {
+        agg.collect(
+            &query,
+            &Feat {
+                a: *a,
+                ..Feat::default()
+            },
+        );
+    }
+
+    assert_eq!(vec![1, 2, 0, 1], RangeStats::counts(&agg.a));
+}
+
+#[test]
+fn missing_optional_values_are_counted() {
+    let query = FeatAggregationQuery {
+        b: vec![0..10],
+        ..FeatAggregationQuery::default()
+    };
+
+    let mut agg = FeatAggregationResult::from(&query);
+
+    agg.collect(&query, &Feat::default());
+    agg.collect(
+        &query,
+        &Feat {
+            b: Some(1),
+            d: Some(0.5),
+            ..Feat::default()
+        },
+    );
+
+    assert_eq!(1, agg.b_missing);
+    // Counted even when the field has no ranges to aggregate on
+    assert_eq!(1, agg.d_missing);
+
+    let mut other = FeatAggregationResult::from(&query);
+    other.collect(&query, &Feat::default());
+
+    agg.merge_same_size(&other).unwrap();
+
+    assert_eq!(2, agg.b_missing);
+    assert_eq!(2, agg.d_missing);
+}
+
+#[test]
+fn float_features_skip_nan() {
+    let query = FeatAggregationQuery {
+        c: vec![0.0..1.0, 1.0..10.0],
+        d: vec![std::f64::MIN..std::f64::MAX],
+        ..FeatAggregationQuery::default()
+    };
+
+    let mut agg = FeatAggregationResult::from(&query);
+
+    for (c, d) in &[
+        (0.5, Some(0.5)),
+        (1.0, None),
+        (std::f32::NAN, Some(std::f64::NAN)),
+    ] {
+        agg.collect(
+            &query,
+            &Feat {
+                c: *c,
+                d: *d,
+                ..Feat::default()
+            },
+        );
+    }
+
+    assert_eq!(vec![1, 1], agg_counts(&agg.c));
+    assert_eq!(vec![1], agg_counts(&agg.d));
+    // NaN is a value, even if a useless one
+    assert_eq!(1, agg.d_missing);
+
+    assert_eq!(Some(0.5), agg.c[0].max);
+    assert_eq!(Some(1.0), agg.c[1].min);
+}
+
 #[test]
 fn agg_query_full_range_generation() {
     assert_eq!(
@@ -109,6 +310,27 @@ fn agg_query_full_range_generation() {
     );
 }
 
+#[test]
+fn agg_query_full_range_bucketed_generation() {
+    let query = FeatAggregationQuery::full_range_bucketed(3);
+
+    assert_eq!(3, query.a.len());
+    assert_eq!(std::u64::MIN, query.a[0].start);
+    assert_eq!(std::u64::MAX, query.a[2].end);
+
+    assert_eq!(std::i16::MIN, query.b[0].start);
+    assert_eq!(std::i16::MAX, query.b[2].end);
+
+    assert_eq!(3, query.c.len());
+    assert_eq!(3, query.d.len());
+    assert!(query.d.iter().all(|r| r.start < r.end));
+
+    assert_eq!(
+        FeatAggregationQuery::full_range(),
+        FeatAggregationQuery::full_range_bucketed(1)
+    );
+}
+
 #[test]
 fn collector_integration() -> tantivy::Result<()> {
     let mut builder = SchemaBuilder::new();
@@ -175,3 +397,115 @@ fn collector_integration() -> tantivy::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn unreadable_items_are_counted() -> tantivy::Result<()> {
+    let mut builder = SchemaBuilder::new();
+
+    let bytes_field = builder.add_bytes_field("bincode_feat", tantivy::schema::FAST);
+
+    let index = Index::create_in_ram(builder.build());
+    let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+    let encoded = bincode::serialize(&Feat {
+        a: 1,
+        ..Feat::default()
+    })
+    .expect("serialize ok");
+
+    for bytes in &[encoded.clone(), encoded[..encoded.len() / 2].to_vec()] {
+        let mut doc = Document::new();
+        doc.add_bytes(bytes_field, bytes.clone());
+        writer.add_document(doc);
+    }
+
+    writer.commit()?;
+
+    let query = FeatAggregationQuery {
+        a: vec![0..10],
+        ..FeatAggregationQuery::default()
+    };
+
+    let collector =
+        AggregableCollector::<Feat, _>::new(query, move |seg_reader: &SegmentReader| {
+            let reader = seg_reader.fast_fields().bytes(bytes_field).unwrap();
+            move |doc| bincode::deserialize(reader.get_bytes(doc)).ok()
+        });
+
+    let agg_result = index.reader()?.searcher().search(&AllQuery, &collector)?;
+
+    // The truncated one is not collected, but it's accounted for
+    assert_eq!(vec![1], agg_counts(&agg_result.a));
+    assert_eq!(1, agg_result.skipped);
+
+    Ok(())
+}
+
+#[test]
+fn filtered_out_items_are_not_aggregated() -> tantivy::Result<()> {
+    let mut builder = SchemaBuilder::new();
+
+    let bytes_field = builder.add_bytes_field("bincode_feat", tantivy::schema::FAST);
+
+    let index = Index::create_in_ram(builder.build());
+    let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+    for a in 1..=3 {
+        let mut doc = Document::new();
+        doc.add_bytes(
+            bytes_field,
+            bincode::serialize(&Feat {
+                a,
+                ..Feat::default()
+            })
+            .expect("serialize ok"),
+        );
+        writer.add_document(doc);
+    }
+
+    writer.commit()?;
+
+    let query = FeatAggregationQuery {
+        a: vec![0..2, 2..4],
+        ..FeatAggregationQuery::default()
+    };
+
+    let collector =
+        AggregableCollector::<Feat, _>::new(query, move |seg_reader: &SegmentReader| {
+            let reader = seg_reader.fast_fields().bytes(bytes_field).unwrap();
+            move |doc| bincode::deserialize(reader.get_bytes(doc)).ok()
+        })
+        .with_filter(|_: &SegmentReader| |doc: DocId| doc != 1);
+
+    let agg_result = index.reader()?.searcher().search(&AllQuery, &collector)?;
+
+    // Only a=1 and a=3 pass, and the other one isn't even read
+    assert_eq!(vec![1, 1], agg_counts(&agg_result.a));
+    assert_eq!(0, agg_result.skipped);
+
+    Ok(())
+}
+
+#[derive(Aggregable, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Renamed {
+    pub num_things: u64,
+    #[serde(rename = "kcal")]
+    pub calories: Option<u32>,
+}
+
+#[test]
+fn serde_renames_carry_over() {
+    let query: <Renamed as Aggregable>::Query =
+        serde_json::from_str(r#"{"numThings": [[0, 10]], "kcal": [[0, 100]]}"#).unwrap();
+    assert_eq!(vec![0..10], query.num_things);
+    assert_eq!(vec![0..100], query.calories);
+
+    let agg = <Renamed as Aggregable>::Agg::from(&query);
+    let serialized = serde_json::to_value(&agg).unwrap();
+
+    assert!(serialized.get("numThings").is_some());
+    assert!(serialized.get("kcal").is_some());
+    assert!(serialized.get("kcal_missing").is_some());
+    assert!(serialized.get("calories").is_none());
+}
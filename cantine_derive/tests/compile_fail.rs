@@ -0,0 +1,4 @@
+#[test]
+fn unsupported_fields_are_explained() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -1,9 +1,25 @@
+use serde::{Deserialize, Serialize};
 use tantivy::{
+    collector::Count,
+    query::{BooleanQuery, Occur},
     schema::{SchemaBuilder, Value, FAST, INDEXED},
-    Document,
+    Document, Index,
 };
 
-use cantine_derive::Filterable;
+use cantine_derive::{Filterable, FilterableFieldInfo, FilterableFieldKind};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Diet {
+    Vegan,
+    Keto,
+    Paleo,
+}
+
+impl From<Diet> for u64 {
+    fn from(diet: Diet) -> u64 {
+        diet as u64
+    }
+}
 
 #[derive(Filterable, Default)]
 pub struct Feat {
@@ -11,6 +27,9 @@ pub struct Feat {
     pub b: Option<i16>,
     pub c: f32,
     pub d: Option<f64>,
+    #[filterable(enum)]
+    pub e: Option<Diet>,
+    pub f: Vec<u64>,
 }
 
 type Query = <Feat as Filterable>::Query;
@@ -41,7 +60,7 @@ fn filter_query_interpretation() {
         1,
         fields
             .interpret(&Query {
-                a: Some(0..10),
+                a: Some((0..10).into()),
                 ..Query::default()
             })
             .len()
@@ -51,8 +70,19 @@ fn filter_query_interpretation() {
         2,
         fields
             .interpret(&Query {
-                a: Some(0..10),
-                c: Some(1.1..2.2),
+                a: Some((0..10).into()),
+                c: Some((1.1..2.2).into()),
+                ..Query::default()
+            })
+            .len()
+    );
+
+    // Any-of matching yields a single query
+    assert_eq!(
+        1,
+        fields
+            .interpret(&Query {
+                e: Some(vec![Diet::Vegan, Diet::Paleo]),
                 ..Query::default()
             })
             .len()
@@ -71,6 +101,8 @@ fn add_to_doc_sets_fields_properly() {
         &Feat {
             a: 10,
             d: Some(0.42),
+            e: Some(Diet::Keto),
+            f: vec![4, 2],
             ..Feat::default()
         },
     );
@@ -79,6 +111,211 @@ fn add_to_doc_sets_fields_properly() {
     assert_eq!(Some(&Value::U64(10)), doc.get_first(fields.a));
     assert_eq!(Some(&Value::F64(0.0)), doc.get_first(fields.c));
     assert_eq!(Some(&Value::F64(0.42)), doc.get_first(fields.d));
+    // Enums are indexed as their u64 representation
+    assert_eq!(Some(&Value::U64(1)), doc.get_first(fields.e));
+    // Every element of multi-valued fields gets added
+    assert_eq!(
+        vec![&Value::U64(4), &Value::U64(2)],
+        doc.get_all(fields.f).collect::<Vec<_>>()
+    );
     // Unsed optional values aren't added
     assert_eq!(None, doc.get_first(fields.b));
 }
+
+#[test]
+fn builder_matches_manual_construction() {
+    assert_eq!(Query::default(), Query::builder().build());
+
+    assert_eq!(
+        Query {
+            a: Some((0..10).into()),
+            b: Some((-5..5).into()),
+            e: Some(vec![Diet::Vegan]),
+            ..Query::default()
+        },
+        Query::builder()
+            .a(0..10)
+            .b(-5..5)
+            .e(vec![Diet::Vegan])
+            .build()
+    );
+}
+
+#[test]
+fn multi_valued_fields_match_any_element() -> tantivy::Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let fields = Feat::create_schema(&mut builder, INDEXED);
+
+    let index = Index::create_in_ram(builder.build());
+    let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+    for f in &[vec![], vec![1], vec![1, 2, 3], vec![10, 20]] {
+        let mut doc = Document::new();
+        fields.add_to_doc(
+            &mut doc,
+            &Feat {
+                f: f.clone(),
+                ..Feat::default()
+            },
+        );
+        writer.add_document(doc);
+    }
+
+    writer.commit()?;
+
+    let searcher = index.reader()?.searcher();
+    let count = |query: Query| {
+        let subqueries = fields
+            .interpret(&query)
+            .into_iter()
+            .map(|q| (Occur::Must, q))
+            .collect::<Vec<_>>();
+        searcher.search(&BooleanQuery::from(subqueries), &Count)
+    };
+
+    // Several matching elements still count the document once
+    assert_eq!(2, count(Query::builder().f(0..5).build())?);
+    assert_eq!(1, count(Query::builder().f(2..3).build())?);
+    assert_eq!(1, count(Query::builder().f(5..15).build())?);
+    assert_eq!(0, count(Query::builder().f(100..200).build())?);
+
+    // Inclusive ranges match their upper bound too
+    assert_eq!(1, count(Query::builder().f(3..10).build())?);
+    assert_eq!(2, count(Query::builder().f(3..=10).build())?);
+
+    Ok(())
+}
+
+#[test]
+fn exclusion_keeps_missing_values() -> tantivy::Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let fields = Feat::create_schema(&mut builder, INDEXED);
+
+    let index = Index::create_in_ram(builder.build());
+    let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+    for b in &[None, Some(1), Some(10)] {
+        let mut doc = Document::new();
+        fields.add_to_doc(
+            &mut doc,
+            &Feat {
+                b: *b,
+                ..Feat::default()
+            },
+        );
+        writer.add_document(doc);
+    }
+
+    writer.commit()?;
+
+    let searcher = index.reader()?.searcher();
+    let count = |query: Query| {
+        let exclusion = fields
+            .interpret_exclusion(&query)
+            .expect("query has something to exclude");
+        searcher.search(&exclusion, &Count)
+    };
+
+    // Nothing to exclude
+    assert!(fields.interpret_exclusion(&Query::default()).is_none());
+
+    // The document without a value for `b` is never excluded
+    assert_eq!(2, count(Query::builder().b(-5..5).build())?);
+    assert_eq!(1, count(Query::builder().b(0..20).build())?);
+    assert_eq!(3, count(Query::builder().b(100..200).build())?);
+
+    // Every clause excludes on its own
+    assert_eq!(0, count(Query::builder().a(0..1).b(100..200).build())?);
+
+    Ok(())
+}
+
+#[test]
+fn field_metadata_is_exposed() {
+    let field = |name, kind, is_optional| FilterableFieldInfo {
+        name,
+        kind,
+        is_optional,
+    };
+
+    assert_eq!(
+        &[
+            field("a", FilterableFieldKind::U64, false),
+            field("b", FilterableFieldKind::I64, true),
+            field("c", FilterableFieldKind::F64, false),
+            field("d", FilterableFieldKind::F64, true),
+            field("e", FilterableFieldKind::U64, true),
+            field("f", FilterableFieldKind::U64, false),
+        ],
+        <Feat as Filterable>::Schema::fields()
+    );
+}
+
+#[test]
+fn filter_ranges_deserialize_from_json() {
+    let query: Query = serde_json::from_str(
+        r#"{"a": [1, 10], "b": [-1, 1, true], "c": {"start": 0.5, "end": 1.5}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        Query::builder().a(1..10).b(-1..=1).c(0.5..1.5).build(),
+        query
+    );
+}
+
+#[derive(Filterable, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Renamed {
+    pub num_things: u64,
+    #[serde(rename = "kcal")]
+    pub calories: Option<u32>,
+}
+
+#[test]
+fn serde_renames_carry_over() {
+    type RenamedQuery = <Renamed as Filterable>::Query;
+
+    let query = RenamedQuery::builder()
+        .num_things(1..2)
+        .calories(100..200)
+        .build();
+
+    let serialized = serde_json::to_value(&query).unwrap();
+    assert!(serialized.get("numThings").is_some());
+    assert!(serialized.get("kcal").is_some());
+    assert!(serialized.get("calories").is_none());
+
+    assert_eq!(
+        query,
+        serde_json::from_str(r#"{"numThings": [1, 2], "kcal": [100, 200]}"#).unwrap()
+    );
+}
+
+#[derive(Filterable, Default)]
+#[filterable(prefix = "first_")]
+pub struct First {
+    pub a: u64,
+}
+
+#[derive(Filterable, Default)]
+#[filterable(prefix = "second_")]
+pub struct Second {
+    pub a: u64,
+}
+
+#[test]
+fn custom_prefixes_avoid_collisions() {
+    let mut builder = SchemaBuilder::new();
+    let first = First::create_schema(&mut builder, INDEXED);
+    let second = Second::create_schema(&mut builder, INDEXED);
+
+    assert_ne!(first.a, second.a);
+
+    let schema = builder.build();
+    assert_eq!("first_a", schema.get_field_name(first.a));
+    assert_eq!("second_a", schema.get_field_name(second.a));
+
+    assert_eq!(first, First::load_schema(&schema).unwrap());
+    assert_eq!(second, Second::load_schema(&schema).unwrap());
+}
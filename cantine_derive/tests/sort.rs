@@ -0,0 +1,79 @@
+use tantivy::schema::{SchemaBuilder, INDEXED};
+
+use cantine_derive::{Filterable, FilterableFieldKind, SortTarget, Sortable};
+
+#[derive(Filterable, Sortable, Default)]
+pub struct Feat {
+    pub a: u64,
+    pub num_things: Option<i16>,
+    pub c: f32,
+    #[sortable(skip)]
+    pub d: Option<f64>,
+    pub e: Vec<u64>,
+}
+
+type Sort = <Feat as Sortable>::Sort;
+
+#[test]
+fn every_numeric_field_is_sortable() {
+    assert_eq!(
+        &[
+            Sort::Relevance,
+            Sort::RelevanceAsc,
+            Sort::A,
+            Sort::AAsc,
+            Sort::NumThings,
+            Sort::NumThingsAsc,
+            Sort::C,
+            Sort::CAsc,
+        ],
+        Sort::VALUES
+    );
+}
+
+#[test]
+fn sort_direction() {
+    let ascending: Vec<_> = Sort::VALUES
+        .iter()
+        .filter(|sort| sort.is_ascending())
+        .collect();
+
+    assert_eq!(
+        vec![
+            &Sort::RelevanceAsc,
+            &Sort::AAsc,
+            &Sort::NumThingsAsc,
+            &Sort::CAsc
+        ],
+        ascending
+    );
+}
+
+#[test]
+fn sorts_target_schema_fields() {
+    let mut builder = SchemaBuilder::new();
+    let fields = Feat::create_schema(&mut builder, INDEXED);
+
+    assert_eq!(SortTarget::Relevance, Sort::RelevanceAsc.target(&fields));
+    assert_eq!(
+        SortTarget::Field(fields.num_things, FilterableFieldKind::I64),
+        Sort::NumThings.target(&fields)
+    );
+    assert_eq!(
+        SortTarget::Field(fields.c, FilterableFieldKind::F64),
+        Sort::CAsc.target(&fields)
+    );
+}
+
+#[test]
+fn sorts_serialize_as_snake_case() {
+    assert_eq!(
+        "\"num_things_asc\"",
+        serde_json::to_string(&Sort::NumThingsAsc).unwrap()
+    );
+    assert_eq!(
+        Sort::Relevance,
+        serde_json::from_str::<Sort>("\"relevance\"").unwrap()
+    );
+    assert!(serde_json::from_str::<Sort>("\"d\"").is_err());
+}
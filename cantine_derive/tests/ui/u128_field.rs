@@ -0,0 +1,9 @@
+use cantine_derive::Filterable;
+
+#[derive(Filterable)]
+pub struct Feat {
+    pub small: u8,
+    pub big: Option<u128>,
+}
+
+fn main() {}
@@ -0,0 +1,189 @@
+use std::marker::PhantomData;
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    DocId, Result, Score, SegmentOrdinal, SegmentReader,
+};
+
+use super::{
+    topk::{TopK, TopKProvider},
+    traits::{CheckCondition, ConditionForSegment},
+};
+
+/// A collector that only counts matching documents, taking conditions
+/// into account but without keeping track of any of them.
+///
+/// It's useful when you only need to know how many documents there
+/// are (or how many are left after a pagination condition) without
+/// paying for maintaining a top-k.
+///
+/// The ordering is required because conditions may depend on it, as
+/// is the case with pagination conditions.
+///
+/// ```no_run
+/// # use tique::conditional_collector::{CountCollector, Descending};
+/// # use tantivy::{DocAddress, Score};
+/// # let after = (0.42, DocAddress{segment_ord: 0, doc_id: 1});
+/// // How many documents come after `after`?
+/// let collector = CountCollector::<Descending, _>::new(after);
+/// ```
+///
+/// NOTE: When there's no condition (i.e.: it's `true`), tantivy's
+/// `Count` collector is cheaper as it doesn't require scoring.
+pub struct CountCollector<P, CF> {
+    condition_for_segment: CF,
+    _provider: PhantomData<P>,
+}
+
+impl<P, CF> CountCollector<P, CF>
+where
+    P: TopKProvider<Score, DocId>,
+    CF: ConditionForSegment<Score>,
+{
+    /// Creates a new CountCollector respecting the given
+    /// `ConditionForSegment` implementation.
+    pub fn new(condition_for_segment: CF) -> Self {
+        Self {
+            condition_for_segment,
+            _provider: PhantomData,
+        }
+    }
+}
+
+impl<P, CF> Collector for CountCollector<P, CF>
+where
+    P: 'static + Send + Sync + TopKProvider<Score, DocId>,
+    CF: Send + Sync + ConditionForSegment<Score>,
+{
+    type Fruit = CountResult;
+    type Child = CountSegmentCollector<CF::Type>;
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, children: Vec<Self::Fruit>) -> Result<Self::Fruit> {
+        Ok(children
+            .into_iter()
+            .fold(CountResult::default(), |acc, child| CountResult {
+                total: acc.total + child.total,
+                visited: acc.visited + child.visited,
+            }))
+    }
+
+    fn for_segment(
+        &self,
+        segment_ord: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(CountSegmentCollector {
+            segment_ord,
+            ascending: <P::Child as TopK<Score, DocId>>::ASCENDING,
+            condition: self.condition_for_segment.for_segment(reader),
+            result: CountResult::default(),
+        })
+    }
+}
+
+pub struct CountSegmentCollector<C> {
+    segment_ord: SegmentOrdinal,
+    ascending: bool,
+    condition: C,
+    result: CountResult,
+}
+
+impl<C: CheckCondition<Score>> SegmentCollector for CountSegmentCollector<C> {
+    type Fruit = CountResult;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        self.result.total += 1;
+        if self
+            .condition
+            .check(self.segment_ord, doc, score, self.ascending)
+        {
+            self.result.visited += 1;
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.result
+    }
+}
+
+/// The result of a `CountCollector`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CountResult {
+    /// How many documents were seen. Analogous to the result of a
+    /// simple count collector.
+    pub total: usize,
+    /// How many of the documents we saw actually passed our
+    /// condition
+    pub visited: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conditional_collector::{Ascending, Descending};
+
+    use tantivy::{query::AllQuery, schema::SchemaBuilder, DocAddress, Document, Index};
+
+    #[test]
+    fn condition_is_respected() {
+        let mut collector = CountSegmentCollector {
+            segment_ord: 0,
+            ascending: true,
+            condition: |_sid, doc_id, _score, _asc| doc_id % 2 == 0,
+            result: CountResult::default(),
+        };
+
+        for doc_id in 0..10 {
+            collector.collect(doc_id, 1.0);
+        }
+
+        assert_eq!(
+            CountResult {
+                total: 10,
+                visited: 5
+            },
+            collector.harvest()
+        );
+    }
+
+    #[test]
+    fn count_collector_integration() -> Result<()> {
+        let builder = SchemaBuilder::new();
+        let index = Index::create_in_ram(builder.build());
+
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for _ in 0..10 {
+            writer.add_document(Document::new());
+        }
+
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let everything = searcher.search(&AllQuery, &CountCollector::<Descending, _>::new(true))?;
+        assert_eq!(10, everything.total);
+        assert_eq!(10, everything.visited);
+
+        // Every doc has the same score, so ties are broken by
+        // address: only the docs after doc_id=3 are visited
+        let after = (
+            1.0,
+            DocAddress {
+                segment_ord: 0,
+                doc_id: 3,
+            },
+        );
+
+        let remaining = searcher.search(&AllQuery, &CountCollector::<Ascending, _>::new(after))?;
+        assert_eq!(10, remaining.total);
+        assert_eq!(6, remaining.visited);
+
+        Ok(())
+    }
+}
@@ -19,6 +19,7 @@ where
     C: ConditionForSegment<T>,
 {
     limit: usize,
+    offset: usize,
     scorer_for_segment: S,
     condition_for_segment: C,
     _score: PhantomData<T>,
@@ -31,9 +32,15 @@ where
     P: TopKProvider<T, DocId>,
     C: ConditionForSegment<T>,
 {
-    pub fn new(limit: usize, condition_for_segment: C, scorer_for_segment: S) -> Self {
+    pub fn new(
+        limit: usize,
+        offset: usize,
+        condition_for_segment: C,
+        scorer_for_segment: S,
+    ) -> Self {
         Self {
             limit,
+            offset,
             scorer_for_segment,
             condition_for_segment,
             _score: PhantomData,
@@ -44,7 +51,7 @@ where
 
 impl<T, P, C, S> Collector for CustomScoreTopCollector<T, P, C, S>
 where
-    T: 'static + PartialOrd + Copy + Send + Sync,
+    T: 'static + PartialOrd + Clone + Send + Sync,
     P: 'static + Send + Sync + TopKProvider<T, DocId>,
     C: Send + Sync + ConditionForSegment<T>,
     S: Send + CustomScorer<T>,
@@ -57,7 +64,7 @@ where
     }
 
     fn merge_fruits(&self, children: Vec<Self::Fruit>) -> Result<Self::Fruit> {
-        Ok(P::merge_many(self.limit, children))
+        Ok(P::merge_many(self.limit.saturating_add(self.offset), children).skip(self.offset))
     }
 
     fn for_segment(
@@ -68,7 +75,7 @@ where
         let scorer = self.scorer_for_segment.segment_scorer(reader)?;
         Ok(CustomScoreTopSegmentCollector::new(
             segment_id,
-            P::new_topk(self.limit),
+            P::new_topk(self.limit.saturating_add(self.offset)),
             scorer,
             self.condition_for_segment.for_segment(reader),
         ))
@@ -86,7 +93,7 @@ where
 
 impl<T, C, S, K> CustomScoreTopSegmentCollector<T, C, S, K>
 where
-    T: Copy,
+    T: Clone,
     C: CheckCondition<T>,
     K: TopK<T, DocId>,
 {
@@ -100,7 +107,7 @@ where
 
 impl<T, C, S, K> SegmentCollector for CustomScoreTopSegmentCollector<T, C, S, K>
 where
-    T: 'static + PartialOrd + Copy + Send + Sync,
+    T: 'static + PartialOrd + Clone + Send + Sync,
     K: 'static + TopK<T, DocId>,
     C: CheckCondition<T>,
     S: CustomSegmentScorer<T>,
@@ -162,7 +169,7 @@ mod tests {
         let searcher = reader.searcher();
 
         let colletor =
-            CustomScoreTopCollector::<_, Descending, _, _>::new(2, true, |_: &SegmentReader| {
+            CustomScoreTopCollector::<_, Descending, _, _>::new(2, 0, true, |_: &SegmentReader| {
                 |doc_id: DocId| u64::from(doc_id * 10)
             });
 
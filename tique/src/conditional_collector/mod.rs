@@ -96,11 +96,47 @@
 //! going without ever having to increase `limit`.
 //!
 //! Check `examples/conditional_collector_tutorial.rs` for more details.
+//!
+//! # Tweaking the Score
+//!
+//! Sometimes you don't want to fully replace the score computed by
+//! the query, only nudge it a little. That's what a score modifier
+//! is for: it receives the document and its original score and
+//! yields the score to rank with.
+//!
+//! Say you want to boost documents by popularity:
+//!
+//! ```no_run
+//! # use tantivy::{fastfield::FastFieldReader, schema::Field, DocId, Score, SegmentReader};
+//! # use tique::conditional_collector::{TopCollector,Descending};
+//! # let popularity_field = Field::from_field_id(0);
+//! # let limit = 10;
+//! # let condition_for_segment = true;
+//! let modifier_for_segment = move |reader: &SegmentReader| {
+//!     let popularity = reader.fast_fields().u64(popularity_field).unwrap();
+//!     move |doc_id: DocId, score: Score| {
+//!         let boost = (2 + popularity.get(doc_id)) as Score;
+//!         score * boost.ln()
+//!     }
+//! };
+//!
+//! let collector =
+//!     TopCollector::<Score, Descending, _>::new(limit, condition_for_segment)
+//!         .with_score_modifier(modifier_for_segment);
+//! ```
+//!
+//! Ordering, conditions and pagination work just the same.
+mod count;
 mod custom_score;
 mod top_collector;
 pub(crate) mod topk;
 mod traits;
+mod tweaked_score;
 
-pub use top_collector::{CollectionResult, TopCollector};
-pub use topk::{Ascending, Descending};
+pub use count::{CountCollector, CountResult};
+pub use top_collector::{CollectionResult, FastFieldScore, TopCollector};
+pub use topk::{Ascending, AscendingTopK, Descending, DescendingTopK, TopK, TopKProvider};
 pub use traits::*;
+pub use tweaked_score::{
+    ModifierForSegment, ScoreModifier, TweakedScoreTopCollector, WithRelevance,
+};
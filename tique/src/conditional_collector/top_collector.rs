@@ -1,15 +1,20 @@
-use std::marker::PhantomData;
+use std::{
+    cmp::{Ordering, Reverse},
+    marker::PhantomData,
+};
 
 use tantivy::{
     collector::{Collector, CustomScorer, SegmentCollector},
-    fastfield::FastFieldReader,
+    fastfield::{DynamicFastFieldReader, FastFieldReader},
+    schema::Field,
     DocAddress, DocId, Result, Score, SegmentOrdinal, SegmentReader,
 };
 
 use super::{
     custom_score::CustomScoreTopCollector,
-    topk::{TopK, TopKProvider},
+    topk::{is_nan, TopK, TopKProvider},
     traits::{CheckCondition, ConditionForSegment},
+    tweaked_score::{KeepRelevance, ModifierForSegment, TweakedScoreTopCollector, WithRelevance},
 };
 
 /// A TopCollector like tantivy's, with added support for ordering
@@ -84,8 +89,42 @@ use super::{
 ///     TopCollector::<u64, Descending, _>::new(limit, condition)
 ///         .top_fast_field(id_field);
 /// ```
+///
+/// Sorting by a bytes fast field (like a normalized title) works
+/// similarly, ordering lexicographically:
+///
+/// ```no_run
+/// # use tique::conditional_collector::{Ascending, TopCollector};
+/// # let title_field = tantivy::schema::Field::from_field_id(0);
+/// # let limit = 10;
+/// # let condition = true;
+/// let alphabetical_collector =
+///     TopCollector::<Vec<u8>, Ascending, _>::new(limit, condition)
+///         .top_bytes_fast_field(title_field);
+/// ```
+///
+/// ## Sorting by two fast fields
+///
+/// Ties on the first field can be broken by a second one, which
+/// may be wrapped in `std::cmp::Reverse` to flip its ordering.
+/// The score becomes a tuple, so `(score, DocAddress)` pagination
+/// conditions keep working with the compound ordering.
+///
+/// ```no_run
+/// # use std::cmp::Reverse;
+/// # use tique::conditional_collector::{Ascending, TopCollector};
+/// # let total_time = tantivy::schema::Field::from_field_id(0);
+/// # let calories = tantivy::schema::Field::from_field_id(1);
+/// # let limit = 10;
+/// # let condition = true;
+/// // Quickest first and, among those, the most caloric first
+/// let collector =
+///     TopCollector::<(u64, Reverse<u64>), Ascending, _>::new(limit, condition)
+///         .top_fast_field_then(total_time, calories);
+/// ```
 pub struct TopCollector<T, P, CF> {
     limit: usize,
+    offset: usize,
     condition_for_segment: CF,
     _score: PhantomData<T>,
     _provider: PhantomData<P>,
@@ -101,11 +140,26 @@ where
     /// and respecting the given `ConditionForSegment`
     /// implementation.
     pub fn new(limit: usize, condition_for_segment: CF) -> Self {
+        Self::with_offset(limit, 0, condition_for_segment)
+    }
+
+    /// Creates a new TopCollector that skips the first `offset`
+    /// items, yielding at most `limit` items after that.
+    ///
+    /// Useful for "jump to page N" scenarios, but keep in mind that
+    /// it needs to keep up to `limit + offset` items around during
+    /// collection. For deep pagination prefer using a condition.
+    ///
+    /// The skipped items are not accounted for in the result's
+    /// `visited` count, so `CollectionResult::has_next` keeps
+    /// working as expected.
+    pub fn with_offset(limit: usize, offset: usize, condition_for_segment: CF) -> Self {
         if limit < 1 {
             panic!("Limit must be greater than 0");
         }
         TopCollector {
             limit,
+            offset,
             condition_for_segment,
             _score: PhantomData,
             _provider: PhantomData,
@@ -115,7 +169,7 @@ where
 
 impl<T, P, CF> TopCollector<T, P, CF>
 where
-    T: 'static + Copy + Send + Sync + PartialOrd,
+    T: 'static + Clone + Send + Sync + PartialOrd,
     P: 'static + Send + Sync + TopKProvider<T, DocId>,
     CF: Send + Sync + ConditionForSegment<T>,
 {
@@ -127,10 +181,69 @@ where
     ) -> impl Collector<Fruit = CollectionResult<T>> {
         CustomScoreTopCollector::<T, P, _, _>::new(
             self.limit,
+            self.offset,
             self.condition_for_segment,
             custom_scorer,
         )
     }
+
+    /// Transforms this collector into one that ranks by the score
+    /// yielded by the given modifier, which receives the original
+    /// score computed by the query.
+    ///
+    /// The modifier may yield any score type, so it can also be
+    /// used to extend the relevance, e.g.: to break ties via a
+    /// fast field.
+    pub fn with_score_modifier<M: Send + ModifierForSegment<T>>(
+        self,
+        modifier_for_segment: M,
+    ) -> TweakedScoreTopCollector<T, P, CF, M> {
+        TweakedScoreTopCollector::new(
+            self.limit,
+            self.offset,
+            self.condition_for_segment,
+            modifier_for_segment,
+        )
+    }
+}
+
+impl<T, P, CF> TopCollector<WithRelevance<T>, P, CF>
+where
+    T: 'static + Copy + Send + Sync + PartialOrd,
+    P: 'static + Send + Sync + TopKProvider<WithRelevance<T>, DocId>,
+    CF: Send + Sync + ConditionForSegment<WithRelevance<T>>,
+{
+    /// Like `with_custom_scorer`, but the original relevance score
+    /// of each item is kept together with the custom score used for
+    /// ranking.
+    ///
+    /// Since it needs the relevance, this collector requires scoring
+    /// to be enabled.
+    ///
+    /// ```no_run
+    /// # use tique::conditional_collector::{TopCollector, Descending, WithRelevance};
+    /// # use tantivy::{SegmentReader, DocId};
+    /// # let limit = 10;
+    /// # let condition = true;
+    /// let scorer = |reader: &SegmentReader| {
+    ///     |doc_id: DocId| u64::from(doc_id)
+    /// };
+    ///
+    /// let collector =
+    ///     TopCollector::<WithRelevance<u64>, Descending, _>::new(limit, condition)
+    ///         .with_custom_scorer_keeping_relevance(scorer);
+    /// ```
+    pub fn with_custom_scorer_keeping_relevance<C: Send + CustomScorer<T>>(
+        self,
+        custom_scorer: C,
+    ) -> impl Collector<Fruit = CollectionResult<WithRelevance<T>>> {
+        TweakedScoreTopCollector::<WithRelevance<T>, P, _, _>::new(
+            self.limit,
+            self.offset,
+            self.condition_for_segment,
+            KeepRelevance(custom_scorer),
+        )
+    }
 }
 
 macro_rules! impl_top_fast_field {
@@ -153,6 +266,7 @@ macro_rules! impl_top_fast_field {
                 };
                 CustomScoreTopCollector::<$type, P, _, _>::new(
                     self.limit,
+                    self.offset,
                     self.condition_for_segment,
                     scorer_for_segment,
                 )
@@ -165,6 +279,112 @@ impl_top_fast_field!(u64, "Field is not a fast u64 field");
 impl_top_fast_field!(i64, "Field is not a fast i64 field");
 impl_top_fast_field!(f64, "Field is not a fast f64 field");
 
+impl<P, CF> TopCollector<Vec<u8>, P, CF>
+where
+    P: 'static + Send + Sync + TopKProvider<Vec<u8>, DocId>,
+    CF: Send + Sync + ConditionForSegment<Vec<u8>>,
+{
+    /// Transforms this collector into one that sorts lexicographically
+    /// by the contents of the given bytes fast field. Will panic if the
+    /// field is not a FAST bytes field.
+    ///
+    /// Every visited document has its value copied, so this is
+    /// considerably more expensive than sorting by a numeric field.
+    pub fn top_bytes_fast_field(
+        self,
+        field: tantivy::schema::Field,
+    ) -> impl Collector<Fruit = CollectionResult<Vec<u8>>> {
+        let scorer_for_segment = move |reader: &SegmentReader| {
+            let ff = reader
+                .fast_fields()
+                .bytes(field)
+                .expect("Field is not a fast bytes field");
+            move |doc_id| ff.get_bytes(doc_id).to_vec()
+        };
+        CustomScoreTopCollector::<Vec<u8>, P, _, _>::new(
+            self.limit,
+            self.offset,
+            self.condition_for_segment,
+            scorer_for_segment,
+        )
+    }
+}
+
+/// A value that can be read straight from a fast field and used
+/// as (part of) a score
+pub trait FastFieldScore: 'static + Copy + Send + Sync + PartialOrd {
+    /// The reader responsible for fetching values for a segment
+    type Reader: 'static;
+
+    /// Creates the reader for the given field. Will panic if the
+    /// field is not FAST or the wrong type.
+    fn fast_field_reader(reader: &SegmentReader, field: Field) -> Self::Reader;
+
+    /// Reads the value for the given document
+    fn get(reader: &Self::Reader, doc_id: DocId) -> Self;
+}
+
+macro_rules! impl_fast_field_score {
+    ($type: ident, $err: literal) => {
+        impl FastFieldScore for $type {
+            type Reader = DynamicFastFieldReader<$type>;
+
+            fn fast_field_reader(reader: &SegmentReader, field: Field) -> Self::Reader {
+                reader.fast_fields().$type(field).expect($err)
+            }
+
+            fn get(reader: &Self::Reader, doc_id: DocId) -> Self {
+                reader.get(doc_id)
+            }
+        }
+    };
+}
+
+impl_fast_field_score!(u64, "Field is not a fast u64 field");
+impl_fast_field_score!(i64, "Field is not a fast i64 field");
+impl_fast_field_score!(f64, "Field is not a fast f64 field");
+
+impl<T: FastFieldScore> FastFieldScore for Reverse<T> {
+    type Reader = T::Reader;
+
+    fn fast_field_reader(reader: &SegmentReader, field: Field) -> Self::Reader {
+        T::fast_field_reader(reader, field)
+    }
+
+    fn get(reader: &Self::Reader, doc_id: DocId) -> Self {
+        Reverse(T::get(reader, doc_id))
+    }
+}
+
+impl<A, B, P, CF> TopCollector<(A, B), P, CF>
+where
+    A: FastFieldScore,
+    B: FastFieldScore,
+    P: 'static + Send + Sync + TopKProvider<(A, B), DocId>,
+    CF: Send + Sync + ConditionForSegment<(A, B)>,
+{
+    /// Transforms this collector into one that sorts by the `primary`
+    /// fast field, breaking ties with the `secondary` one. Will panic
+    /// if the fields are not FAST or the wrong type.
+    pub fn top_fast_field_then(
+        self,
+        primary: Field,
+        secondary: Field,
+    ) -> impl Collector<Fruit = CollectionResult<(A, B)>> {
+        let scorer_for_segment = move |reader: &SegmentReader| {
+            let first = A::fast_field_reader(reader, primary);
+            let second = B::fast_field_reader(reader, secondary);
+            move |doc_id| (A::get(&first, doc_id), B::get(&second, doc_id))
+        };
+        CustomScoreTopCollector::<(A, B), P, _, _>::new(
+            self.limit,
+            self.offset,
+            self.condition_for_segment,
+            scorer_for_segment,
+        )
+    }
+}
+
 impl<P, CF> Collector for TopCollector<Score, P, CF>
 where
     P: 'static + Send + Sync + TopKProvider<Score, DocId>,
@@ -178,7 +398,7 @@ where
     }
 
     fn merge_fruits(&self, children: Vec<Self::Fruit>) -> Result<Self::Fruit> {
-        Ok(P::merge_many(self.limit, children))
+        Ok(P::merge_many(self.limit.saturating_add(self.offset), children).skip(self.offset))
     }
 
     fn for_segment(
@@ -188,7 +408,7 @@ where
     ) -> Result<Self::Child> {
         Ok(TopSegmentCollector::new(
             segment_ord,
-            P::new_topk(self.limit),
+            P::new_topk(self.limit.saturating_add(self.offset)),
             self.condition_for_segment.for_segment(reader),
         ))
     }
@@ -205,7 +425,7 @@ pub struct TopSegmentCollector<T, K, C> {
 
 impl<T, K, C> TopSegmentCollector<T, K, C>
 where
-    T: Copy,
+    T: Clone,
     K: TopK<T, DocId>,
     C: CheckCondition<T>,
 {
@@ -225,14 +445,25 @@ where
         self.topk
     }
 
-    pub fn collect(&mut self, doc: DocId, score: T) {
+    pub fn collect(&mut self, doc: DocId, score: T)
+    where
+        T: PartialOrd,
+    {
         self.total += 1;
         if self
             .condition
-            .check(self.segment_ord, doc, score, K::ASCENDING)
+            .check(self.segment_ord, doc, score.clone(), K::ASCENDING)
         {
             self.visited += 1;
-            self.topk.visit(doc, score);
+
+            let is_worse = self
+                .topk
+                .threshold()
+                .map_or(false, |threshold| !beats(&score, threshold, K::ASCENDING));
+
+            if !is_worse {
+                self.topk.visit(doc, score);
+            }
         }
     }
 
@@ -277,10 +508,25 @@ where
     }
 }
 
+// Docs arrive in increasing order, so ties go to the ones already
+// kept: only a strictly better score gets in
+fn beats<T: PartialOrd>(score: &T, threshold: &T, ascending: bool) -> bool {
+    match score.partial_cmp(threshold) {
+        Some(Ordering::Greater) => !ascending,
+        Some(Ordering::Less) => ascending,
+        Some(Ordering::Equal) => false,
+        // NaN is the worst regardless of the ordering
+        None => is_nan(threshold) && !is_nan(score),
+    }
+}
+
 /// The basic result type, containing the top selected items and
 /// additional metadata.
+///
+/// Collectors identify items by their `DocAddress`, but results
+/// can be built for any doc type via `TopKProvider::merge_many`.
 #[derive(Debug)]
-pub struct CollectionResult<T> {
+pub struct CollectionResult<T, D = DocAddress> {
     /// How many documents were seen. Analogous to the result of a
     /// simple count collector.
     pub total: usize,
@@ -288,17 +534,27 @@ pub struct CollectionResult<T> {
     /// condition
     pub visited: usize,
     /// The top found items, as you would get from `tantivy::TopDocs`
-    pub items: Vec<(T, DocAddress)>,
+    pub items: Vec<(T, D)>,
 }
 
-impl<T> CollectionResult<T> {
+impl<T, D> CollectionResult<T, D> {
     /// Wether the same query that created this result would have
     /// more results if we paginated (or increased the top-k limit)
     pub fn has_next(&self) -> bool {
-        self.visited - self.items.len() > 0
+        // Every visited document either made it into `items` or
+        // would show up in the next page
+        self.visited > self.items.len()
+    }
+
+    /// Drops the first `offset` (sorted) items
+    pub(crate) fn skip(mut self, offset: usize) -> Self {
+        let skipped = offset.min(self.items.len());
+        self.items.drain(..skipped);
+        self.visited -= skipped;
+        self
     }
 
-    pub(crate) fn merge_many<K: TopK<T, DocAddress>>(mut topk: K, items: Vec<Self>) -> Self {
+    pub(crate) fn merge_many<K: TopK<T, D>>(mut topk: K, items: Vec<Self>) -> Self {
         let mut total = 0;
         let mut visited = 0;
 
@@ -329,7 +585,7 @@ mod tests {
     use super::*;
     use crate::conditional_collector::{
         topk::{AscendingTopK, DescendingTopK},
-        Ascending, Descending,
+        Ascending, Descending, MinScore,
     };
 
     use tantivy::{
@@ -397,6 +653,87 @@ mod tests {
         );
     }
 
+    // Counts how many items made it to the inner TopK
+    struct CountingTopK<K> {
+        inner: K,
+        visits: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl<K: TopK<Score, DocId>> TopK<Score, DocId> for CountingTopK<K> {
+        const ASCENDING: bool = K::ASCENDING;
+
+        fn visit(&mut self, doc: DocId, score: Score) {
+            self.visits.set(self.visits.get() + 1);
+            self.inner.visit(doc, score);
+        }
+
+        fn threshold(&self) -> Option<&Score> {
+            self.inner.threshold()
+        }
+
+        fn into_sorted_vec(self) -> Vec<(DocId, Score)> {
+            self.inner.into_sorted_vec()
+        }
+
+        fn into_vec(self) -> Vec<(DocId, Score)> {
+            self.inner.into_vec()
+        }
+    }
+
+    #[test]
+    fn items_that_cant_make_it_are_not_visited() {
+        let input = vec![0.5, 0.9, 0.5, 0.2, f32::NAN, 0.7, 0.9, 0.1, 1.0];
+
+        let run = |ascending: bool| {
+            let visits = std::rc::Rc::new(std::cell::Cell::new(0));
+            let wanted = if ascending {
+                let mut topk = AscendingTopK::new(2);
+                for (doc, score) in input.iter().enumerate() {
+                    topk.visit(doc as DocId, *score);
+                }
+                topk.into_sorted_vec()
+            } else {
+                let mut topk = DescendingTopK::new(2);
+                for (doc, score) in input.iter().enumerate() {
+                    topk.visit(doc as DocId, *score);
+                }
+                topk.into_sorted_vec()
+            };
+
+            let got = if ascending {
+                let topk = CountingTopK {
+                    inner: AscendingTopK::new(2),
+                    visits: visits.clone(),
+                };
+                let mut collector = TopSegmentCollector::new(0, topk, true);
+                for (doc, score) in input.iter().enumerate() {
+                    collector.collect(doc as DocId, *score);
+                }
+                assert_eq!(input.len(), collector.visited);
+                collector.into_topk().into_sorted_vec()
+            } else {
+                let topk = CountingTopK {
+                    inner: DescendingTopK::new(2),
+                    visits: visits.clone(),
+                };
+                let mut collector = TopSegmentCollector::new(0, topk, true);
+                for (doc, score) in input.iter().enumerate() {
+                    collector.collect(doc as DocId, *score);
+                }
+                assert_eq!(input.len(), collector.visited);
+                collector.into_topk().into_sorted_vec()
+            };
+
+            assert_eq!(wanted, got);
+            visits.get()
+        };
+
+        // 0.5 and 0.9 while there's room, then 0.5, 0.2 and 0.1
+        assert_eq!(5, run(true));
+        // 0.5 and 0.9 while there's room, then 0.7, 0.9 and 1.0
+        assert_eq!(5, run(false));
+    }
+
     #[test]
     fn collection_with_a_marker_smoke() {
         // XXX property test maybe? Essentially we are creating
@@ -439,17 +776,95 @@ mod tests {
                 // Every doc with a higher score should be picked
                 (0.6, 7),
                 (0.7, 5),
-                // Same score but lower id as well
+                // Same score but lower id have appeared already
                 (0.5, 3),
                 (0.5, 2),
                 // [pivot] The same doc should not be collected
                 (0.5, 4),
                 // Docs with lower scores are discarded
                 (0.0, 1),
-                // Same score but higher doc is discaraded too
+                // Same score but higher doc is picked, like when descending
                 (0.5, 6),
             ],
-            vec![(0.5, 2), (0.5, 3), (0.6, 7), (0.7, 5)],
+            vec![(0.5, 6), (0.6, 7), (0.7, 5)],
+        );
+    }
+
+    // Collects every page for the given input, using the last item
+    // of a page as the condition for the next one
+    fn paginate<K, F>(new_topk: F, input: &[(Score, DocId)]) -> Vec<Vec<DocId>>
+    where
+        K: TopK<Score, DocId>,
+        F: Fn() -> K,
+    {
+        let mut pages = Vec::new();
+        let mut after: Option<(Score, DocAddress)> = None;
+
+        loop {
+            let condition = move |segment_ord, doc_id, score, ascending| match after {
+                Some(marker) => marker.check(segment_ord, doc_id, score, ascending),
+                None => true,
+            };
+
+            let mut collector = TopSegmentCollector::new(0, new_topk(), condition);
+            for &(score, doc) in input {
+                collector.collect(doc, score);
+            }
+
+            let result = CollectionResult {
+                total: collector.total,
+                visited: collector.visited,
+                items: collector
+                    .into_topk()
+                    .into_sorted_vec()
+                    .into_iter()
+                    .map(|(doc_id, score)| {
+                        (
+                            score,
+                            DocAddress {
+                                segment_ord: 0,
+                                doc_id,
+                            },
+                        )
+                    })
+                    .collect(),
+            };
+
+            after = result.items.last().copied();
+            pages.push(result.items.iter().map(|(_, addr)| addr.doc_id).collect());
+
+            if !result.has_next() {
+                break;
+            }
+        }
+
+        pages
+    }
+
+    #[test]
+    fn pagination_has_no_phantom_pages() {
+        // Plenty of ties, to make sure they're broken consistently
+        let input = [(0.5, 0), (0.1, 1), (0.5, 2), (0.5, 3), (0.9, 4), (0.1, 5)];
+
+        // Exactly at the boundary: the last page is full and
+        // there's nothing after it
+        assert_eq!(
+            vec![vec![4, 0], vec![2, 3], vec![1, 5]],
+            paginate(|| DescendingTopK::new(2), &input)
+        );
+        assert_eq!(
+            vec![vec![1, 5, 0], vec![2, 3, 4]],
+            paginate(|| AscendingTopK::new(3), &input)
+        );
+
+        // And with a partially filled last page
+        assert_eq!(
+            vec![vec![4, 0, 2, 3], vec![1, 5]],
+            paginate(|| DescendingTopK::new(4), &input)
+        );
+        assert_eq!(
+            vec![vec![1, 5, 0, 2], vec![3, 4]],
+            paginate(|| AscendingTopK::new(4), &input)
         );
     }
 
@@ -518,6 +933,69 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn min_score_condition() -> Result<()> {
+        let mut builder = schema::SchemaBuilder::new();
+
+        let text_field = builder.add_text_field("text", schema::TEXT);
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for text in &[
+            "the",
+            "the the",
+            "the cat",
+            "the cat sat on the mat",
+            "a dog",
+        ] {
+            let mut doc = Document::new();
+            doc.add_text(text_field, text);
+            writer.add_document(doc);
+        }
+
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "the"),
+            schema::IndexRecordOption::WithFreqsAndPositions,
+        );
+
+        let everything =
+            searcher.search(&query, &TopCollector::<_, Descending, _>::new(10, true))?;
+        assert_eq!(4, everything.items.len());
+
+        // Somewhere between the lowest and highest scores
+        let threshold = (everything.items[0].0 + everything.items[3].0) / 2.0;
+
+        let result = searcher.search(
+            &query,
+            &TopCollector::<_, Descending, _>::new(10, MinScore(threshold)),
+        )?;
+
+        assert!(!result.items.is_empty());
+        assert!(result.items.len() < everything.items.len());
+        for (score, _doc) in result.items {
+            assert!(score >= threshold);
+        }
+
+        // Also works for custom scores
+        let mut segment_collector =
+            TopSegmentCollector::new(0, AscendingTopK::new(10), MinScore(0.5));
+        for (doc, score) in [(0, 0.1), (1, 0.5), (2, 0.7), (3, 0.49)].iter() {
+            segment_collector.collect(*doc, *score);
+        }
+        assert_eq!(
+            vec![(1, 0.5), (2, 0.7)],
+            segment_collector.into_topk().into_sorted_vec()
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn fast_field_collection() -> Result<()> {
         let mut builder = schema::SchemaBuilder::new();
@@ -554,4 +1032,220 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn compound_scores_paginate_with_a_marker() {
+        // Ascending by the first key, descending by the second
+        let input = [
+            ((1, Reverse(10)), 0),
+            ((1, Reverse(20)), 1),
+            ((0, Reverse(5)), 2),
+            ((2, Reverse(0)), 3),
+            ((1, Reverse(15)), 4),
+        ];
+
+        let mut first_page = TopSegmentCollector::new(0, AscendingTopK::new(3), true);
+        for (score, doc) in input.iter() {
+            first_page.collect(*doc, *score);
+        }
+
+        let items = first_page.into_topk().into_sorted_vec();
+        assert_eq!(
+            vec![
+                (2, (0, Reverse(5))),
+                (1, (1, Reverse(20))),
+                (4, (1, Reverse(15)))
+            ],
+            items
+        );
+
+        let (doc_id, score) = items[2];
+        let marker = (
+            score,
+            DocAddress {
+                segment_ord: 0,
+                doc_id,
+            },
+        );
+
+        let mut second_page = TopSegmentCollector::new(0, AscendingTopK::new(3), marker);
+        for (score, doc) in input.iter() {
+            second_page.collect(*doc, *score);
+        }
+
+        assert_eq!(
+            vec![(0, (1, Reverse(10))), (3, (2, Reverse(0)))],
+            second_page.into_topk().into_sorted_vec()
+        );
+    }
+
+    #[test]
+    fn two_fast_fields_collection() -> Result<()> {
+        let mut builder = schema::SchemaBuilder::new();
+
+        let primary = builder.add_u64_field("primary", schema::FAST);
+        let secondary = builder.add_i64_field("secondary", schema::FAST);
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for (a, b) in &[(2, 0), (1, -1), (1, 5), (0, 3), (1, 2)] {
+            let mut doc = Document::new();
+            doc.add_u64(primary, *a);
+            doc.add_i64(secondary, *b);
+            writer.add_document(doc);
+        }
+
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let collector = TopCollector::<(u64, Reverse<i64>), Ascending, _>::new(5, true)
+            .top_fast_field_then(primary, secondary);
+
+        let result = searcher.search(&AllQuery, &collector)?;
+
+        assert_eq!(
+            vec![
+                (0, Reverse(3)),
+                (1, Reverse(5)),
+                (1, Reverse(2)),
+                (1, Reverse(-1)),
+                (2, Reverse(0))
+            ],
+            result
+                .items
+                .into_iter()
+                .map(|(score, _doc)| score)
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn bytes_fast_field_collection() -> Result<()> {
+        let mut builder = schema::SchemaBuilder::new();
+
+        let field = builder.add_bytes_field("field", schema::FAST);
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for title in &["pancakes", "bacon", "waffles", "bacon and eggs"] {
+            let mut doc = Document::new();
+            doc.add_bytes(field, title.as_bytes().to_vec());
+            writer.add_document(doc);
+        }
+
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let collector = TopCollector::<_, Ascending, _>::new(3, true).top_bytes_fast_field(field);
+        let result = searcher.search(&AllQuery, &collector)?;
+
+        assert_eq!(
+            vec![&b"bacon"[..], b"bacon and eggs", b"pancakes"],
+            result
+                .items
+                .iter()
+                .map(|(title, _doc)| title.as_slice())
+                .collect::<Vec<_>>()
+        );
+
+        // Paginating works just like with numeric fields
+        let (last, address) = result.items[2].clone();
+        let collector =
+            TopCollector::<_, Ascending, _>::new(3, (last, address)).top_bytes_fast_field(field);
+        let result = searcher.search(&AllQuery, &collector)?;
+
+        assert_eq!(1, result.items.len());
+        assert_eq!(b"waffles".to_vec(), result.items[0].0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_keeps_has_next_accurate() {
+        let address = |doc_id| DocAddress {
+            segment_ord: 0,
+            doc_id,
+        };
+
+        let result = CollectionResult {
+            total: 10,
+            visited: 5,
+            items: vec![(0.9, address(0)), (0.8, address(1)), (0.7, address(2))],
+        };
+
+        let skipped = result.skip(2);
+        assert_eq!(10, skipped.total);
+        assert_eq!(vec![(0.7, address(2))], skipped.items);
+        assert!(skipped.has_next());
+
+        let skipped = skipped.skip(5);
+        assert!(skipped.items.is_empty());
+        assert!(skipped.has_next());
+    }
+
+    #[test]
+    fn offset_collection() -> Result<()> {
+        let mut builder = schema::SchemaBuilder::new();
+
+        let field = builder.add_u64_field("field", schema::FAST);
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        const NUM_DOCS: u64 = 10;
+        for v in 0..NUM_DOCS {
+            let mut doc = Document::new();
+            doc.add_u64(field, v);
+            writer.add_document(doc);
+        }
+
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let window = |limit, offset| {
+            let collector = TopCollector::<u64, Ascending, _>::with_offset(limit, offset, true)
+                .top_fast_field(field);
+            searcher.search(&AllQuery, &collector)
+        };
+
+        let result = window(3, 4)?;
+        assert_eq!(NUM_DOCS as usize, result.total);
+        assert_eq!(
+            vec![4, 5, 6],
+            result.items.iter().map(|(v, _)| *v).collect::<Vec<_>>()
+        );
+        assert!(result.has_next());
+
+        // The last page is partial and has nothing after it
+        let result = window(3, 8)?;
+        assert_eq!(
+            vec![8, 9],
+            result.items.iter().map(|(v, _)| *v).collect::<Vec<_>>()
+        );
+        assert!(!result.has_next());
+
+        // Going past the end yields nothing
+        let result = window(3, 20)?;
+        assert_eq!(NUM_DOCS as usize, result.total);
+        assert!(result.items.is_empty());
+        assert!(!result.has_next());
+
+        // Even absurdly so
+        let result = window(3, usize::MAX)?;
+        assert_eq!(NUM_DOCS as usize, result.total);
+        assert!(result.items.is_empty());
+        assert!(!result.has_next());
+
+        Ok(())
+    }
 }
@@ -3,20 +3,55 @@ use std::{
     collections::BinaryHeap,
 };
 
+use tantivy::DocId;
+
 use super::CollectionResult;
 
-pub trait TopK<T, D> {
+// Limits can be huge (e.g.: deep offsets), so past this the heap
+// only grows as items actually get visited
+const MAX_PREALLOCATED: usize = 1_000;
+
+/// A bounded container that keeps the top `limit` visited items
+///
+/// The `doc` type can be anything `Ord`: besides tantivy's `DocId`
+/// it may be, for example, an id read from a fast field. Ties in
+/// score are broken by the lowest `doc`.
+///
+/// Scores that can't be compared to themselves (i.e.: NaN) are
+/// considered worse than any other, so they always sort last.
+pub trait TopK<T, D = DocId> {
+    /// Wether the items are kept in ascending order
     const ASCENDING: bool;
+    /// Considers the given item for inclusion in the top
     fn visit(&mut self, doc: D, score: T);
+    /// The score of the worst item kept, once the container is at
+    /// capacity. Items must be better than it to get in, so callers
+    /// can skip the work of producing ones that aren't
+    fn threshold(&self) -> Option<&T>;
+    /// Consumes the container, yielding the items in order
     fn into_sorted_vec(self) -> Vec<(D, T)>;
+    /// Consumes the container, yielding the items in no particular order
     fn into_vec(self) -> Vec<(D, T)>;
 }
 
-pub trait TopKProvider<T: PartialOrd, D: Ord> {
+/// Creates `TopK` instances with a given ordering. This is
+/// what `Ascending` and `Descending` implement.
+pub trait TopKProvider<T: PartialOrd, D: Ord = DocId> {
+    /// The `TopK` implementation that's created
     type Child: TopK<T, D>;
 
+    /// Creates a container that keeps at most `limit` items
     fn new_topk(limit: usize) -> Self::Child;
-    fn merge_many(limit: usize, items: Vec<CollectionResult<T>>) -> CollectionResult<T>;
+    /// Merges the results of many collections (e.g.: one per segment)
+    /// into a single one with at most `limit` items
+    ///
+    /// The merged doc type is independent of `D`: collectors merge
+    /// per-segment `DocId`s as `DocAddress`es, but items identified
+    /// by anything `Ord` (say, an id from a fast field) work too
+    fn merge_many<M: Ord>(
+        limit: usize,
+        items: Vec<CollectionResult<T, M>>,
+    ) -> CollectionResult<T, M>;
 }
 
 /// Marker to create a TopCollector in *ascending* order
@@ -29,7 +64,10 @@ impl<T: PartialOrd, D: Ord> TopKProvider<T, D> for Ascending {
         AscendingTopK::new(limit)
     }
 
-    fn merge_many(limit: usize, items: Vec<CollectionResult<T>>) -> CollectionResult<T> {
+    fn merge_many<M: Ord>(
+        limit: usize,
+        items: Vec<CollectionResult<T, M>>,
+    ) -> CollectionResult<T, M> {
         CollectionResult::merge_many(AscendingTopK::new(limit), items)
     }
 }
@@ -41,47 +79,55 @@ impl<T: PartialOrd, D: Ord> TopKProvider<T, D> for Descending {
     type Child = DescendingTopK<T, D>;
 
     fn new_topk(limit: usize) -> Self::Child {
-        DescendingTopK {
-            limit,
-            heap: BinaryHeap::with_capacity(limit),
-        }
+        DescendingTopK::new(limit)
     }
 
-    fn merge_many(limit: usize, items: Vec<CollectionResult<T>>) -> CollectionResult<T> {
+    fn merge_many<M: Ord>(
+        limit: usize,
+        items: Vec<CollectionResult<T, M>>,
+    ) -> CollectionResult<T, M> {
         CollectionResult::merge_many(DescendingTopK::new(limit), items)
     }
 }
 
+/// A `TopK` that keeps the items with the *lowest* scores
 pub struct AscendingTopK<S, D> {
     limit: usize,
-    heap: BinaryHeap<Scored<S, Reverse<D>>>,
+    heap: BinaryHeap<Reverse<Scored<Lowest<S>, D>>>,
 }
 
+/// A `TopK` that keeps the items with the *highest* scores
 pub struct DescendingTopK<S, D> {
     limit: usize,
     heap: BinaryHeap<Reverse<Scored<S, D>>>,
 }
 
 impl<T: PartialOrd, D: Ord> AscendingTopK<T, D> {
-    pub(crate) fn new(limit: usize) -> Self {
+    /// Creates a container that keeps the `limit` items with the
+    /// lowest scores
+    pub fn new(limit: usize) -> Self {
         Self {
             limit,
-            heap: BinaryHeap::with_capacity(limit),
+            heap: BinaryHeap::with_capacity(limit.min(MAX_PREALLOCATED)),
         }
     }
 
     fn visit(&mut self, doc: D, score: T) {
-        let scored = Scored {
-            score,
-            doc: Reverse(doc),
-        };
+        visit_bounded(
+            &mut self.heap,
+            self.limit,
+            Scored {
+                score: Lowest(score),
+                doc,
+            },
+        );
+    }
+
+    fn threshold(&self) -> Option<&T> {
         if self.heap.len() < self.limit {
-            self.heap.push(scored);
-        } else if let Some(mut head) = self.heap.peek_mut() {
-            if head.cmp(&scored) == Ordering::Greater {
-                head.score = scored.score;
-                head.doc = scored.doc;
-            }
+            None
+        } else {
+            self.heap.peek().map(|head| &(head.0).score.0)
         }
     }
 
@@ -89,7 +135,7 @@ impl<T: PartialOrd, D: Ord> AscendingTopK<T, D> {
         self.heap
             .into_sorted_vec()
             .into_iter()
-            .map(|s| (s.doc.0, s.score))
+            .map(|s| (s.0.doc, s.0.score.0))
             .collect()
     }
 
@@ -97,28 +143,30 @@ impl<T: PartialOrd, D: Ord> AscendingTopK<T, D> {
         self.heap
             .into_vec()
             .into_iter()
-            .map(|s| (s.doc.0, s.score))
+            .map(|s| (s.0.doc, s.0.score.0))
             .collect()
     }
 }
 
 impl<T: PartialOrd, D: Ord> DescendingTopK<T, D> {
-    pub(crate) fn new(limit: usize) -> Self {
+    /// Creates a container that keeps the `limit` items with the
+    /// highest scores
+    pub fn new(limit: usize) -> Self {
         Self {
             limit,
-            heap: BinaryHeap::with_capacity(limit),
+            heap: BinaryHeap::with_capacity(limit.min(MAX_PREALLOCATED)),
         }
     }
 
     fn visit(&mut self, doc: D, score: T) {
-        let scored = Reverse(Scored { score, doc });
+        visit_bounded(&mut self.heap, self.limit, Scored { score, doc });
+    }
+
+    fn threshold(&self) -> Option<&T> {
         if self.heap.len() < self.limit {
-            self.heap.push(scored);
-        } else if let Some(mut head) = self.heap.peek_mut() {
-            if head.cmp(&scored) == Ordering::Greater {
-                head.0.score = scored.0.score;
-                head.0.doc = scored.0.doc;
-            }
+            None
+        } else {
+            self.heap.peek().map(|head| &(head.0).score)
         }
     }
 
@@ -139,6 +187,22 @@ impl<T: PartialOrd, D: Ord> DescendingTopK<T, D> {
     }
 }
 
+// The heap's head is the worst item kept so far, so it gets
+// replaced whenever something better shows up
+fn visit_bounded<S: PartialOrd, D: Ord>(
+    heap: &mut BinaryHeap<Reverse<Scored<S, D>>>,
+    limit: usize,
+    scored: Scored<S, D>,
+) {
+    if heap.len() < limit {
+        heap.push(Reverse(scored));
+    } else if let Some(mut head) = heap.peek_mut() {
+        if head.0 < scored {
+            head.0 = scored;
+        }
+    }
+}
+
 impl<T: PartialOrd, D: Ord> TopK<T, D> for AscendingTopK<T, D> {
     const ASCENDING: bool = true;
 
@@ -146,6 +210,10 @@ impl<T: PartialOrd, D: Ord> TopK<T, D> for AscendingTopK<T, D> {
         AscendingTopK::visit(self, doc, score);
     }
 
+    fn threshold(&self) -> Option<&T> {
+        AscendingTopK::threshold(self)
+    }
+
     fn into_sorted_vec(self) -> Vec<(D, T)> {
         AscendingTopK::into_sorted_vec(self)
     }
@@ -162,6 +230,10 @@ impl<T: PartialOrd, D: Ord> TopK<T, D> for DescendingTopK<T, D> {
         DescendingTopK::visit(self, doc, score);
     }
 
+    fn threshold(&self) -> Option<&T> {
+        DescendingTopK::threshold(self)
+    }
+
     fn into_sorted_vec(self) -> Vec<(D, T)> {
         DescendingTopK::into_sorted_vec(self)
     }
@@ -171,17 +243,38 @@ impl<T: PartialOrd, D: Ord> TopK<T, D> for DescendingTopK<T, D> {
     }
 }
 
-pub(crate) struct Scored<S, D> {
-    pub score: S,
-    pub doc: D,
+/// Tells wether the given score can't be compared to itself,
+/// like a NaN float.
+pub(crate) fn is_nan<T: PartialOrd>(score: &T) -> bool {
+    score.partial_cmp(score).is_none()
+}
+
+// Flips the ordering of the score so that the lowest one is
+// the greatest
+struct Lowest<S>(S);
+
+impl<S: PartialOrd> PartialOrd for Lowest<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        other.0.partial_cmp(&self.0)
+    }
 }
 
-impl<S: PartialOrd, D: Ord> Scored<S, D> {
-    pub(crate) fn new(score: S, doc: D) -> Self {
-        Self { score, doc }
+impl<S: PartialOrd> PartialEq for Lowest<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
     }
 }
 
+/// An item in the top-k, where greater means better.
+///
+/// Scores that can't be compared (NaN) are always the worst,
+/// so they sort last regardless of the ordering and break
+/// even by the lowest id amongst themselves.
+pub(crate) struct Scored<S, D> {
+    pub score: S,
+    pub doc: D,
+}
+
 impl<S: PartialOrd, D: Ord> PartialOrd for Scored<S, D> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -193,11 +286,16 @@ impl<S: PartialOrd, D: Ord> Ord for Scored<S, D> {
     fn cmp(&self, other: &Self) -> Ordering {
         // Highest score first
         match self.score.partial_cmp(&other.score) {
-            Some(Ordering::Equal) | None => {
+            Some(Ordering::Equal) => {
                 // Break even by lowest id
                 other.doc.cmp(&self.doc)
             }
             Some(rest) => rest,
+            None => match (is_nan(&self.score), is_nan(&other.score)) {
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                _ => other.doc.cmp(&self.doc),
+            },
         }
     }
 }
@@ -278,4 +376,112 @@ mod tests {
             vec![(0.5, 4), (0.5, 5), (0.1, 1), (0.1, 2), (0.1, 3)],
         );
     }
+
+    #[test]
+    fn nan_sorts_last() {
+        let input = vec![(f32::NAN, 1), (0.5, 2), (f32::NAN, 0), (0.1, 3), (0.9, 4)];
+
+        let sorted =
+            |topk: Vec<(u32, f32)>| -> Vec<u32> { topk.into_iter().map(|(doc, _)| doc).collect() };
+
+        let mut asc = AscendingTopK::new(5);
+        let mut desc = DescendingTopK::new(5);
+        for (score, doc) in input.iter() {
+            asc.visit(*doc, *score);
+            desc.visit(*doc, *score);
+        }
+
+        assert_eq!(vec![3, 2, 4, 0, 1], sorted(asc.into_sorted_vec()));
+        assert_eq!(vec![4, 2, 3, 0, 1], sorted(desc.into_sorted_vec()));
+
+        // NaN never replaces a proper score
+        let mut desc = DescendingTopK::new(2);
+        for (score, doc) in input.iter() {
+            desc.visit(*doc, *score);
+        }
+        assert_eq!(vec![4, 2], sorted(desc.into_sorted_vec()));
+
+        // But they're still collected when there's room
+        let mut asc = AscendingTopK::new(4);
+        for (score, doc) in input {
+            asc.visit(doc, score);
+        }
+        assert_eq!(vec![3, 2, 4, 0], sorted(asc.into_sorted_vec()));
+    }
+
+    #[test]
+    fn arbitrary_doc_types() {
+        let mut topk = <Descending as TopKProvider<f32, u64>>::new_topk(2);
+
+        topk.visit(1_000, 0.5);
+        topk.visit(42, 0.9);
+        topk.visit(7, 0.5);
+
+        assert_eq!(vec![(42, 0.9), (7, 0.5)], topk.into_sorted_vec());
+
+        check_topk(
+            AscendingTopK::new(3),
+            vec![(1, "c"), (2, "a"), (1, "b"), (0, "z")],
+            vec![(0, "z"), (1, "b"), (1, "c")],
+        );
+    }
+
+    #[test]
+    fn merge_arbitrary_doc_types() {
+        let segment = |visited, items| CollectionResult {
+            total: visited,
+            visited,
+            items,
+        };
+
+        let merged = <Ascending as TopKProvider<u64>>::merge_many(
+            3,
+            vec![
+                segment(2, vec![(10, 1_000u64), (30, 42)]),
+                segment(3, vec![(10, 7), (20, 99), (40, 1)]),
+            ],
+        );
+
+        assert_eq!(5, merged.total);
+        assert!(merged.has_next());
+        assert_eq!(vec![(10, 7), (10, 1_000), (20, 99)], merged.items);
+    }
+
+    #[test]
+    fn huge_limits_dont_preallocate() {
+        check_topk(
+            AscendingTopK::new(usize::MAX),
+            vec![(0.5, 1), (0.2, 2)],
+            vec![(0.2, 2), (0.5, 1)],
+        );
+        check_topk(
+            DescendingTopK::new(usize::MAX),
+            vec![(0.5, 1)],
+            vec![(0.5, 1)],
+        );
+    }
+
+    #[test]
+    fn threshold_is_the_worst_kept_score() {
+        let input = vec![(0.8, 1), (0.2, 3), (0.3, 5), (0.9, 7), (-0.2, 9)];
+
+        let mut asc = AscendingTopK::new(3);
+        let mut desc = DescendingTopK::new(3);
+        for (score, doc) in input.iter().take(2) {
+            asc.visit(*doc, *score);
+            desc.visit(*doc, *score);
+        }
+
+        // There's still room for anything
+        assert_eq!(None, asc.threshold());
+        assert_eq!(None, desc.threshold());
+
+        for (score, doc) in input.into_iter().skip(2) {
+            asc.visit(doc, score);
+            desc.visit(doc, score);
+        }
+
+        assert_eq!(Some(&0.3), asc.threshold());
+        assert_eq!(Some(&0.3), desc.threshold());
+    }
 }
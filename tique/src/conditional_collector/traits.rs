@@ -2,7 +2,7 @@ use std::cmp::Ordering;
 
 use tantivy::{DocAddress, DocId, SegmentOrdinal, SegmentReader};
 
-use super::topk::Scored;
+use super::topk::is_nan;
 
 /// A trait that allows defining arbitrary conditions to be checked
 /// before considering a matching document for inclusion in the
@@ -15,6 +15,24 @@ pub trait ConditionForSegment<T>: Clone {
     /// the matching documents in the given segment reader are to
     /// be considered as collection candidates.
     fn for_segment(&self, reader: &SegmentReader) -> Self::Type;
+
+    /// Combines this condition with `other`, accepting only the
+    /// documents that pass both
+    fn and<O: ConditionForSegment<T>>(self, other: O) -> And<Self, O>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    /// Combines this condition with `other`, accepting the documents
+    /// that pass any of them
+    fn or<O: ConditionForSegment<T>>(self, other: O) -> Or<Self, O>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
 }
 
 impl<T, C, F> ConditionForSegment<T> for F
@@ -37,11 +55,11 @@ impl<T> ConditionForSegment<T> for bool {
 
 impl<T> ConditionForSegment<T> for (T, DocAddress)
 where
-    T: 'static + PartialOrd + Copy,
+    T: 'static + PartialOrd + Clone,
 {
     type Type = Self;
     fn for_segment(&self, _reader: &SegmentReader) -> Self::Type {
-        *self
+        self.clone()
     }
 }
 
@@ -73,21 +91,247 @@ where
 
 impl<T> CheckCondition<T> for (T, DocAddress)
 where
-    T: 'static + PartialOrd + Copy,
+    T: 'static + PartialOrd + Clone,
 {
     fn check(&self, segment_ord: SegmentOrdinal, doc_id: DocId, score: T, ascending: bool) -> bool {
-        let wanted = if ascending {
-            Ordering::Less
-        } else {
-            Ordering::Greater
-        };
-
-        Scored::new(self.0, self.1).cmp(&Scored::new(
-            score,
+        let after_marker = || {
             DocAddress {
                 segment_ord,
                 doc_id,
+            } > self.1
+        };
+
+        match score.partial_cmp(&self.0) {
+            Some(Ordering::Greater) => ascending,
+            Some(Ordering::Less) => !ascending,
+            // Regardless of the ordering, the top-k breaks even by
+            // the lowest address, so that's what comes next
+            Some(Ordering::Equal) => after_marker(),
+            // NaN sorts last in both orderings
+            None => match (is_nan(&score), is_nan(&self.0)) {
+                (true, false) => true,
+                (false, true) => false,
+                _ => after_marker(),
             },
-        )) == wanted
+        }
+    }
+}
+
+/// A condition that only accepts documents scoring at least
+/// the given threshold.
+///
+/// ```no_run
+/// # use tique::conditional_collector::{TopCollector, Descending, MinScore};
+/// let collector =
+///     TopCollector::<_, Descending, _>::new(10, MinScore(0.5));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MinScore<T>(pub T);
+
+impl<T> ConditionForSegment<T> for MinScore<T>
+where
+    T: 'static + PartialOrd + Clone,
+{
+    type Type = Self;
+    fn for_segment(&self, _reader: &SegmentReader) -> Self::Type {
+        self.clone()
+    }
+}
+
+impl<T> CheckCondition<T> for MinScore<T>
+where
+    T: 'static + PartialOrd + Clone,
+{
+    fn check(&self, _: SegmentOrdinal, _: DocId, score: T, _: bool) -> bool {
+        score >= self.0
+    }
+}
+
+/// A condition that passes when both of its conditions pass. Usually
+/// created via `ConditionForSegment::and`.
+#[derive(Debug, Clone, Copy)]
+pub struct And<A, B>(pub A, pub B);
+
+impl<T, A, B> ConditionForSegment<T> for And<A, B>
+where
+    T: Clone,
+    A: ConditionForSegment<T>,
+    B: ConditionForSegment<T>,
+{
+    type Type = And<A::Type, B::Type>;
+    fn for_segment(&self, reader: &SegmentReader) -> Self::Type {
+        And(self.0.for_segment(reader), self.1.for_segment(reader))
+    }
+}
+
+impl<T, A, B> CheckCondition<T> for And<A, B>
+where
+    T: Clone,
+    A: CheckCondition<T>,
+    B: CheckCondition<T>,
+{
+    fn check(&self, segment_ord: SegmentOrdinal, doc_id: DocId, score: T, ascending: bool) -> bool {
+        self.0.check(segment_ord, doc_id, score.clone(), ascending)
+            && self.1.check(segment_ord, doc_id, score, ascending)
+    }
+}
+
+/// A condition that passes when any of its conditions pass. Usually
+/// created via `ConditionForSegment::or`.
+#[derive(Debug, Clone, Copy)]
+pub struct Or<A, B>(pub A, pub B);
+
+impl<T, A, B> ConditionForSegment<T> for Or<A, B>
+where
+    T: Clone,
+    A: ConditionForSegment<T>,
+    B: ConditionForSegment<T>,
+{
+    type Type = Or<A::Type, B::Type>;
+    fn for_segment(&self, reader: &SegmentReader) -> Self::Type {
+        Or(self.0.for_segment(reader), self.1.for_segment(reader))
+    }
+}
+
+impl<T, A, B> CheckCondition<T> for Or<A, B>
+where
+    T: Clone,
+    A: CheckCondition<T>,
+    B: CheckCondition<T>,
+{
+    fn check(&self, segment_ord: SegmentOrdinal, doc_id: DocId, score: T, ascending: bool) -> bool {
+        self.0.check(segment_ord, doc_id, score.clone(), ascending)
+            || self.1.check(segment_ord, doc_id, score, ascending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{query::AllQuery, schema::SchemaBuilder, Document, Index, Result};
+
+    use crate::conditional_collector::{Descending, TopCollector};
+
+    #[test]
+    fn and_or_check_both_conditions() {
+        let even = |_sid, doc_id: DocId, _score: f32, _asc| doc_id % 2 == 0;
+        let high = |_sid, _doc_id, score: f32, _asc| score > 0.5;
+
+        let and = And(even, high);
+        let or = Or(even, high);
+
+        let check = |cond: &dyn Fn(DocId, f32) -> bool| {
+            vec![cond(0, 0.1), cond(1, 0.1), cond(2, 0.9), cond(3, 0.9)]
+        };
+
+        assert_eq!(
+            vec![false, false, true, false],
+            check(&|doc, score| and.check(0, doc, score, true))
+        );
+        assert_eq!(
+            vec![true, false, true, true],
+            check(&|doc, score| or.check(0, doc, score, true))
+        );
+    }
+
+    #[test]
+    fn nan_scores_paginate_last() {
+        use crate::conditional_collector::topk::{AscendingTopK, DescendingTopK, TopK};
+
+        let items = vec![
+            (f32::NAN, 0),
+            (0.3, 1),
+            (f32::NAN, 2),
+            (0.7, 3),
+            (0.3, 4),
+            (f32::NAN, 5),
+        ];
+
+        fn paginate<K: TopK<f32, DocAddress>>(
+            new_topk: impl Fn() -> K,
+            items: &[(f32, DocId)],
+            ascending: bool,
+        ) -> Vec<DocId> {
+            let mut collected = Vec::new();
+            let mut marker: Option<(f32, DocAddress)> = None;
+
+            loop {
+                let mut topk = new_topk();
+                for (score, doc_id) in items {
+                    let passes = marker
+                        .as_ref()
+                        .map(|m| m.check(0, *doc_id, *score, ascending))
+                        .unwrap_or(true);
+                    if passes {
+                        topk.visit(
+                            DocAddress {
+                                segment_ord: 0,
+                                doc_id: *doc_id,
+                            },
+                            *score,
+                        );
+                    }
+                }
+
+                let page = topk.into_sorted_vec();
+                if page.is_empty() {
+                    break;
+                }
+
+                for (addr, score) in page {
+                    collected.push(addr.doc_id);
+                    marker = Some((score, addr));
+                }
+            }
+
+            collected
+        }
+
+        assert_eq!(
+            vec![3, 1, 4, 0, 2, 5],
+            paginate(|| DescendingTopK::new(2), &items, false)
+        );
+        assert_eq!(
+            vec![1, 4, 3, 0, 2, 5],
+            paginate(|| AscendingTopK::new(2), &items, true)
+        );
+    }
+
+    #[test]
+    fn combinators_integration() -> Result<()> {
+        let builder = SchemaBuilder::new();
+        let index = Index::create_in_ram(builder.build());
+
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for _ in 0..10 {
+            writer.add_document(Document::new());
+        }
+
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let even = |_: &SegmentReader| |_sid, doc_id: DocId, _score, _asc| doc_id % 2 == 0;
+        let small = |_: &SegmentReader| |_sid, doc_id: DocId, _score, _asc| doc_id < 3;
+
+        let and = TopCollector::<_, Descending, _>::new(10, even.and(small));
+        let or = TopCollector::<_, Descending, _>::new(10, even.or(small));
+
+        let (and_result, or_result) = searcher.search(&AllQuery, &(and, or))?;
+
+        let doc_ids = |items: Vec<(f32, DocAddress)>| {
+            items
+                .into_iter()
+                .map(|(_score, addr)| addr.doc_id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec![0, 2], doc_ids(and_result.items));
+        assert_eq!(vec![0, 1, 2, 4, 6, 8], doc_ids(or_result.items));
+
+        Ok(())
     }
 }
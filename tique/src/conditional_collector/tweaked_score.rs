@@ -0,0 +1,358 @@
+use std::{cmp::Ordering, marker::PhantomData};
+
+use tantivy::{
+    collector::{Collector, CustomScorer, CustomSegmentScorer, SegmentCollector},
+    DocId, Result, Score, SegmentOrdinal, SegmentReader,
+};
+
+use super::{
+    top_collector::TopSegmentCollector,
+    topk::{TopK, TopKProvider},
+    traits::{CheckCondition, ConditionForSegment},
+    CollectionResult,
+};
+
+/// A custom score that also carries the original relevance score
+/// of the document it was computed for.
+///
+/// Ordering (and equality) only take `value` into account, so the
+/// relevance is just along for the ride.
+#[derive(Debug, Clone, Copy)]
+pub struct WithRelevance<T> {
+    /// The custom score, used for ranking
+    pub value: T,
+    /// The score the query yielded for the document
+    pub relevance: Score,
+}
+
+impl<T: PartialOrd> PartialOrd for WithRelevance<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl<T: PartialEq> PartialEq for WithRelevance<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+/// Computes the final score of a document given its id and the
+/// score the query assigned to it.
+///
+/// Closures `FnMut(DocId, Score) -> T` implement this trait.
+pub trait ScoreModifier<T>: 'static {
+    /// Yields the modified score for `doc`
+    fn modify(&mut self, doc: DocId, score: Score) -> T;
+}
+
+impl<T, F> ScoreModifier<T> for F
+where
+    F: 'static + FnMut(DocId, Score) -> T,
+{
+    fn modify(&mut self, doc: DocId, score: Score) -> T {
+        (self)(doc, score)
+    }
+}
+
+/// Creates a `ScoreModifier` for each segment. This is where you
+/// would fetch fast field readers and alike.
+pub trait ModifierForSegment<T>: Sync {
+    /// The concrete type of the result from calling `for_segment`
+    type Type: ScoreModifier<T>;
+
+    /// Creates the `ScoreModifier` for the given segment
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Type>;
+}
+
+impl<T, M, F> ModifierForSegment<T> for F
+where
+    F: Sync + Fn(&SegmentReader) -> M,
+    M: ScoreModifier<T>,
+{
+    type Type = M;
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Type> {
+        Ok((self)(reader))
+    }
+}
+
+/// Wraps a `CustomScorer` so that the query score is kept
+pub(crate) struct KeepRelevance<C>(pub C);
+
+pub(crate) struct KeepRelevanceSegment<S>(S);
+
+impl<T, C> ModifierForSegment<WithRelevance<T>> for KeepRelevance<C>
+where
+    T: 'static,
+    C: CustomScorer<T>,
+{
+    type Type = KeepRelevanceSegment<C::Child>;
+
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Type> {
+        Ok(KeepRelevanceSegment(self.0.segment_scorer(reader)?))
+    }
+}
+
+impl<T, S> ScoreModifier<WithRelevance<T>> for KeepRelevanceSegment<S>
+where
+    S: CustomSegmentScorer<T>,
+{
+    fn modify(&mut self, doc: DocId, score: Score) -> WithRelevance<T> {
+        WithRelevance {
+            value: self.0.score(doc),
+            relevance: score,
+        }
+    }
+}
+
+/// A top collector that uses a `ModifierForSegment` to tweak the
+/// score computed by the query, instead of replacing it.
+///
+/// Built via `TopCollector::with_score_modifier`.
+pub struct TweakedScoreTopCollector<T, P, C, M>
+where
+    T: PartialOrd,
+    P: TopKProvider<T, DocId>,
+    C: ConditionForSegment<T>,
+{
+    limit: usize,
+    offset: usize,
+    modifier_for_segment: M,
+    condition_for_segment: C,
+    _score: PhantomData<T>,
+    _provider: PhantomData<P>,
+}
+
+impl<T, P, C, M> TweakedScoreTopCollector<T, P, C, M>
+where
+    T: PartialOrd,
+    P: TopKProvider<T, DocId>,
+    C: ConditionForSegment<T>,
+{
+    pub(crate) fn new(
+        limit: usize,
+        offset: usize,
+        condition_for_segment: C,
+        modifier_for_segment: M,
+    ) -> Self {
+        Self {
+            limit,
+            offset,
+            modifier_for_segment,
+            condition_for_segment,
+            _score: PhantomData,
+            _provider: PhantomData,
+        }
+    }
+}
+
+impl<T, P, C, M> Collector for TweakedScoreTopCollector<T, P, C, M>
+where
+    T: 'static + PartialOrd + Clone + Send + Sync,
+    P: 'static + Send + Sync + TopKProvider<T, DocId>,
+    C: Send + Sync + ConditionForSegment<T>,
+    M: Send + ModifierForSegment<T>,
+{
+    type Fruit = CollectionResult<T>;
+    type Child = TweakedScoreTopSegmentCollector<T, C::Type, M::Type, P::Child>;
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, children: Vec<Self::Fruit>) -> Result<Self::Fruit> {
+        Ok(P::merge_many(self.limit.saturating_add(self.offset), children).skip(self.offset))
+    }
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let modifier = self.modifier_for_segment.for_segment(reader)?;
+        Ok(TweakedScoreTopSegmentCollector {
+            modifier,
+            collector: TopSegmentCollector::new(
+                segment_id,
+                P::new_topk(self.limit.saturating_add(self.offset)),
+                self.condition_for_segment.for_segment(reader),
+            ),
+        })
+    }
+}
+
+pub struct TweakedScoreTopSegmentCollector<T, C, M, K>
+where
+    C: CheckCondition<T>,
+    K: TopK<T, DocId>,
+{
+    modifier: M,
+    collector: TopSegmentCollector<T, K, C>,
+}
+
+impl<T, C, M, K> SegmentCollector for TweakedScoreTopSegmentCollector<T, C, M, K>
+where
+    T: 'static + PartialOrd + Clone + Send + Sync,
+    K: 'static + TopK<T, DocId>,
+    C: CheckCondition<T>,
+    M: ScoreModifier<T>,
+{
+    type Fruit = CollectionResult<T>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        let score = self.modifier.modify(doc, score);
+        self.collector.collect(doc, score);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.collector.into_unsorted_collection_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conditional_collector::{Descending, TopCollector};
+
+    use tantivy::{
+        query::TermQuery,
+        schema::{self, IndexRecordOption},
+        Document, Index, Term,
+    };
+
+    #[test]
+    fn ordering_ignores_relevance() {
+        let low = WithRelevance {
+            value: 1,
+            relevance: 10.0,
+        };
+        let high = WithRelevance {
+            value: 2,
+            relevance: 0.1,
+        };
+
+        assert!(low < high);
+        assert_eq!(
+            low,
+            WithRelevance {
+                value: 1,
+                relevance: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn score_modifier_tweaks_the_original_score() -> Result<()> {
+        let mut builder = schema::SchemaBuilder::new();
+
+        let text_field = builder.add_text_field("text", schema::TEXT);
+        let boost_field = builder.add_u64_field("boost", schema::FAST);
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // Same text: every doc gets the same score from the query
+        for boost in &[1, 10, 5] {
+            let mut doc = Document::new();
+            doc.add_text(text_field, "the");
+            doc.add_u64(boost_field, *boost);
+            writer.add_document(doc);
+        }
+
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "the"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let original = searcher.search(&query, &TopCollector::<_, Descending, _>::new(1, true))?;
+        let (original_score, _) = original.items[0];
+
+        let collector = TopCollector::<Score, Descending, _>::new(3, true).with_score_modifier(
+            move |reader: &SegmentReader| {
+                let boosts = reader.fast_fields().u64(boost_field).unwrap();
+                move |doc_id, score: Score| {
+                    score * tantivy::fastfield::FastFieldReader::get(&boosts, doc_id) as Score
+                }
+            },
+        );
+        let result = searcher.search(&query, &collector)?;
+
+        assert_eq!(
+            vec![1, 2, 0],
+            result
+                .items
+                .iter()
+                .map(|(_, addr)| addr.doc_id)
+                .collect::<Vec<_>>()
+        );
+
+        let (top_score, _) = result.items[0];
+        assert!((original_score * 10.0 - top_score).abs() < f32::EPSILON);
+
+        Ok(())
+    }
+
+    #[test]
+    fn relevance_is_kept_alongside_custom_score() -> Result<()> {
+        let mut builder = schema::SchemaBuilder::new();
+
+        let text_field = builder.add_text_field("text", schema::TEXT);
+        let rank_field = builder.add_u64_field("rank", schema::FAST);
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for (text, rank) in &[("the", 1), ("the the the", 3), ("the cat", 2)] {
+            let mut doc = Document::new();
+            doc.add_text(text_field, text);
+            doc.add_u64(rank_field, *rank);
+            writer.add_document(doc);
+        }
+
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "the"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let relevance = searcher.search(&query, &TopCollector::<_, Descending, _>::new(3, true))?;
+
+        let collector = TopCollector::<WithRelevance<u64>, Descending, _>::new(3, true)
+            .with_custom_scorer_keeping_relevance(move |reader: &SegmentReader| {
+                let ranks = reader.fast_fields().u64(rank_field).unwrap();
+                move |doc_id| tantivy::fastfield::FastFieldReader::get(&ranks, doc_id)
+            });
+        let result = searcher.search(&query, &collector)?;
+
+        // Ordered by the custom score
+        assert_eq!(
+            vec![3, 2, 1],
+            result
+                .items
+                .iter()
+                .map(|(score, _)| score.value)
+                .collect::<Vec<_>>()
+        );
+
+        // But still aware of the original relevance
+        for (score, addr) in result.items {
+            let (wanted, _) = relevance
+                .items
+                .iter()
+                .find(|(_, other)| *other == addr)
+                .expect("same docs are matched");
+            assert!((wanted - score.relevance).abs() < f32::EPSILON);
+        }
+
+        Ok(())
+    }
+}
@@ -17,8 +17,10 @@ use tantivy::{
 ///
 #[derive(Debug)]
 pub struct DisMaxQuery {
-    disjuncts: Vec<Box<dyn Query>>,
+    disjuncts: Vec<(Box<dyn Query>, f32)>,
     tiebreaker: f32,
+    boost: f32,
+    min_match: usize,
 }
 
 impl DisMaxQuery {
@@ -26,19 +28,66 @@ impl DisMaxQuery {
     ///
     /// Panics if tiebreaker is not within the `[0,1]` range
     pub fn new(disjuncts: Vec<Box<dyn Query>>, tiebreaker: f32) -> Self {
+        Self::with_weights(
+            disjuncts.into_iter().map(|d| (d, 1.0)).collect(),
+            tiebreaker,
+        )
+    }
+
+    /// Create a DisMax query where each disjunct contributes its score
+    /// multiplied by the given weight
+    ///
+    /// Useful to make matching on a field (say: title) more important
+    /// than matching on others. The tiebreaker formula operates on the
+    /// weighted scores.
+    ///
+    /// Panics if tiebreaker is not within the `[0,1]` range or if any
+    /// weight is negative
+    pub fn with_weights(disjuncts: Vec<(Box<dyn Query>, f32)>, tiebreaker: f32) -> Self {
         assert!((0.0..=1.0).contains(&tiebreaker));
+        assert!(
+            disjuncts.iter().all(|(_, weight)| *weight >= 0.0),
+            "weights must not be negative"
+        );
         Self {
             disjuncts,
             tiebreaker,
+            boost: 1.0,
+            min_match: 1,
         }
     }
+
+    /// Multiply the final score by the given boost
+    ///
+    /// Less than 1.0 means lower importance, greater means higher. Same
+    /// as wrapping the query in a `BoostQuery`.
+    pub fn with_boost(mut self, boost: f32) -> Self {
+        self.boost = boost;
+        self
+    }
+
+    /// Only match documents that match at least `min_match` disjuncts
+    ///
+    /// By default, matching a single disjunct is enough. A `min_match`
+    /// greater than the number of disjuncts matches nothing and zero
+    /// is the same as one.
+    pub fn with_min_match(mut self, min_match: usize) -> Self {
+        self.min_match = min_match.max(1);
+        self
+    }
 }
 
 impl Clone for DisMaxQuery {
     fn clone(&self) -> Self {
         Self {
-            disjuncts: self.disjuncts.iter().map(|q| q.box_clone()).collect(),
+            disjuncts: self
+                .disjuncts
+                .iter()
+                .map(|(q, weight)| (q.box_clone(), *weight))
+                .collect(),
             tiebreaker: self.tiebreaker,
+            boost: self.boost,
+            min_match: self.min_match,
         }
     }
 }
@@ -48,38 +97,57 @@ impl Query for DisMaxQuery {
         Ok(Box::new(DisMaxWeight::new(
             self.disjuncts
                 .iter()
-                .map(|d| d.weight(searcher, scoring_enabled))
+                .map(|(d, weight)| Ok((d.weight(searcher, scoring_enabled)?, *weight)))
                 .collect::<Result<Vec<_>>>()?,
             self.tiebreaker,
+            self.boost,
+            self.min_match,
         )))
     }
 }
 
 struct DisMaxWeight {
-    weights: Vec<Box<dyn Weight>>,
+    weights: Vec<(Box<dyn Weight>, f32)>,
     tiebreaker: f32,
+    boost: f32,
+    min_match: usize,
 }
 
 impl DisMaxWeight {
-    fn new(weights: Vec<Box<dyn Weight>>, tiebreaker: f32) -> Self {
+    fn new(
+        weights: Vec<(Box<dyn Weight>, f32)>,
+        tiebreaker: f32,
+        boost: f32,
+        min_match: usize,
+    ) -> Self {
         Self {
             weights,
             tiebreaker,
+            boost,
+            min_match,
         }
     }
 }
 
 impl Weight for DisMaxWeight {
     fn scorer(&self, reader: &SegmentReader, boost: f32) -> Result<Box<dyn Scorer>> {
+        // Scaling every disjunct scales the final score just the same,
+        // while the per-disjunct weight scales each score individually
+        let boost = boost * self.boost;
         match self.weights.len() {
+            num_weights if num_weights < self.min_match => Ok(Box::new(EmptyScorer)),
             0 => Ok(Box::new(EmptyScorer)),
-            1 => self.weights.get(0).unwrap().scorer(reader, boost),
-            _ => Ok(Box::new(DisMaxScorer::new(
+            1 => {
+                let (w, weight) = self.weights.get(0).unwrap();
+                w.scorer(reader, boost * weight)
+            }
+            _ => Ok(Box::new(DisMaxScorer::with_min_match(
                 self.weights
                     .iter()
-                    .map(|w| w.scorer(reader, boost))
+                    .map(|(w, weight)| w.scorer(reader, boost * weight))
                     .collect::<Result<Vec<_>>>()?,
                 self.tiebreaker,
+                self.min_match,
             ))),
         }
     }
@@ -93,15 +161,27 @@ impl Weight for DisMaxWeight {
 
         let mut explanation = Explanation::new(
             format!(
-                "DisMaxQuery. Score = max + (sum - max) * {}",
-                self.tiebreaker
+                "DisMaxQuery. Score = (max + (sum - max) * {}) * {}",
+                self.tiebreaker, self.boost
             ),
             scorer.score(),
         );
 
-        for weight in &self.weights {
-            if let Ok(sub_explanation) = weight.explain(reader, doc) {
-                explanation.add_detail(sub_explanation);
+        if self.min_match > 1 {
+            explanation.add_detail(Explanation::new(
+                "Minimum matching disjuncts",
+                self.min_match as Score,
+            ));
+        }
+
+        for (w, weight) in &self.weights {
+            if let Ok(sub_explanation) = w.explain(reader, doc) {
+                let mut weighted = Explanation::new(
+                    format!("Disjunct. Weight = {}", weight),
+                    sub_explanation.value() * weight,
+                );
+                weighted.add_detail(sub_explanation);
+                explanation.add_detail(weighted);
             }
         }
 
@@ -113,16 +193,103 @@ struct DisMaxScorer {
     scorers: Vec<Box<dyn Scorer>>,
     current: DocId,
     tiebreaker: f32,
+    min_match: usize,
 }
 
 impl DisMaxScorer {
     fn new(scorers: Vec<Box<dyn Scorer>>, tiebreaker: f32) -> Self {
+        Self::with_min_match(scorers, tiebreaker, 1)
+    }
+
+    fn with_min_match(scorers: Vec<Box<dyn Scorer>>, tiebreaker: f32, min_match: usize) -> Self {
         let current = scorers.iter().map(|s| s.doc()).min().unwrap_or(TERMINATED);
-        Self {
+        let mut scorer = Self {
             scorers,
             current,
             tiebreaker,
+            min_match,
+        };
+        scorer.skip_unmatched();
+        scorer
+    }
+
+    // Moves to the next doc in the union of every scorer
+    fn advance_union(&mut self) {
+        let mut next_target = TERMINATED;
+        let mut idx = 0;
+
+        while idx < self.scorers.len() {
+            let scorer = &mut self.scorers[idx];
+
+            // Advance every scorer that's on target or behind
+            if scorer.doc() <= self.current && scorer.advance() == TERMINATED {
+                // Order doesn't matter for a union, so the exhausted
+                // scorer can be replaced by the last one, which is
+                // then visited in the next iteration
+                self.scorers.swap_remove(idx);
+                continue;
+            }
+
+            let doc = scorer.doc();
+
+            if doc < next_target {
+                next_target = doc;
+            }
+
+            idx += 1;
         }
+
+        self.current = next_target;
+    }
+
+    // Moves to the first doc in the union that's >= target
+    fn seek_union(&mut self, target: DocId) {
+        let mut next_target = TERMINATED;
+        let mut idx = 0;
+
+        while idx < self.scorers.len() {
+            let scorer = &mut self.scorers[idx];
+
+            // Only the scorers that are behind need to move, and they
+            // may be able to skip straight to the target
+            if scorer.doc() < target && scorer.seek(target) == TERMINATED {
+                self.scorers.swap_remove(idx);
+                continue;
+            }
+
+            let doc = scorer.doc();
+
+            if doc < next_target {
+                next_target = doc;
+            }
+
+            idx += 1;
+        }
+
+        self.current = next_target;
+    }
+
+    fn skip_unmatched(&mut self) -> DocId {
+        while self.current != TERMINATED {
+            if self.scorers.len() < self.min_match {
+                self.current = TERMINATED;
+                break;
+            }
+
+            let num_matching = self
+                .scorers
+                .iter()
+                .filter(|scorer| scorer.doc() == self.current)
+                .count();
+
+            if num_matching >= self.min_match {
+                break;
+            }
+
+            self.advance_union();
+        }
+
+        self.current
     }
 }
 
@@ -148,29 +315,17 @@ impl Scorer for DisMaxScorer {
 
 impl DocSet for DisMaxScorer {
     fn advance(&mut self) -> DocId {
-        let mut next_target = TERMINATED;
-        let mut to_remove = Vec::new();
-
-        for (idx, scorer) in self.scorers.iter_mut().enumerate() {
-            // Advance every scorer that's on target or behind
-            if scorer.doc() <= self.current && scorer.advance() == TERMINATED {
-                to_remove.push(idx);
-                continue;
-            }
-
-            let doc = scorer.doc();
-
-            if doc < next_target {
-                next_target = doc;
-            }
-        }
+        self.advance_union();
+        self.skip_unmatched()
+    }
 
-        while let Some(idx) = to_remove.pop() {
-            self.scorers.remove(idx);
+    fn seek(&mut self, target: DocId) -> DocId {
+        if self.current >= target {
+            return self.current;
         }
 
-        self.current = next_target;
-        next_target
+        self.seek_union(target);
+        self.skip_unmatched()
     }
 
     fn doc(&self) -> tantivy::DocId {
@@ -189,6 +344,7 @@ mod tests {
     use std::ops::Range;
 
     use tantivy::{
+        collector::{Count, TopDocs},
         doc,
         query::TermQuery,
         schema::{IndexRecordOption, SchemaBuilder, TEXT},
@@ -230,6 +386,19 @@ mod tests {
             self.doc()
         }
 
+        fn seek(&mut self, target: DocId) -> DocId {
+            let pos = match self.doc_ids.binary_search(&target) {
+                Ok(pos) => pos,
+                Err(pos) => pos,
+            };
+            if pos > self.cursor {
+                self.cursor = pos;
+            }
+
+            self.current = self.doc_ids.get(self.cursor).copied().unwrap_or(TERMINATED);
+            self.doc()
+        }
+
         fn doc(&self) -> DocId {
             self.current
         }
@@ -269,6 +438,165 @@ mod tests {
         );
     }
 
+    #[test]
+    fn many_disjuncts_advance_as_union() {
+        // Dozens of overlapping, disjoint and empty doc sets that get
+        // exhausted at very different points
+        let ranges = (0..50)
+            .map(|i: DocId| {
+                let start = (i * 37) % 101;
+                start..(start + (i * 13) % 29)
+            })
+            .collect::<Vec<_>>();
+
+        let expected = ranges
+            .iter()
+            .cloned()
+            .flatten()
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let collect_union = |ranges: Vec<Range<DocId>>| {
+            let mut dismax =
+                DisMaxScorer::new(ranges.into_iter().map(make_test_scorer).collect(), 0.0);
+
+            let mut docs = Vec::new();
+            while dismax.doc() != TERMINATED {
+                docs.push(dismax.doc());
+                dismax.advance();
+            }
+            docs
+        };
+
+        assert_eq!(expected, collect_union(ranges.clone()));
+
+        // The order of the disjuncts doesn't matter
+        let mut reversed = ranges.clone();
+        reversed.reverse();
+        assert_eq!(expected, collect_union(reversed));
+
+        let mut interleaved = ranges;
+        interleaved.sort_by_key(|range| (range.end, range.start));
+        assert_eq!(expected, collect_union(interleaved));
+    }
+
+    #[test]
+    fn seek_lands_on_union_docs() {
+        let ranges = vec![0..3, 10..12, 11..20, 50..51, 100..150, 7..7];
+
+        let union = ranges
+            .iter()
+            .cloned()
+            .flatten()
+            .collect::<std::collections::BTreeSet<_>>();
+
+        let make_dismax =
+            || DisMaxScorer::new(ranges.iter().cloned().map(make_test_scorer).collect(), 0.0);
+
+        for target in 0..160 {
+            let expected = union.range(target..).next().copied().unwrap_or(TERMINATED);
+
+            let mut dismax = make_dismax();
+            assert_eq!(expected, dismax.seek(target), "seek({})", target);
+            assert_eq!(expected, dismax.doc());
+
+            // Advancing after seeking keeps walking the union
+            let expected_next = union
+                .range(expected.saturating_add(1)..)
+                .next()
+                .copied()
+                .unwrap_or(TERMINATED);
+            if expected != TERMINATED {
+                assert_eq!(expected_next, dismax.advance(), "after seek({})", target);
+            }
+        }
+
+        // Seeking backwards or in place does nothing
+        let mut dismax = make_dismax();
+        assert_eq!(50, dismax.seek(21));
+        assert_eq!(50, dismax.seek(13));
+        assert_eq!(50, dismax.seek(50));
+        assert_eq!(100, dismax.seek(51));
+        assert_eq!(TERMINATED, dismax.seek(150));
+        assert_eq!(TERMINATED, dismax.seek(151));
+    }
+
+    #[test]
+    fn min_match_skips_docs() {
+        let ranges = vec![0..10, 5..15, 8..20, 30..40, 9..9];
+
+        let docs_matching = |min_match| {
+            let mut dismax = DisMaxScorer::with_min_match(
+                ranges.iter().cloned().map(make_test_scorer).collect(),
+                0.0,
+                min_match,
+            );
+
+            let mut docs = Vec::new();
+            while dismax.doc() != TERMINATED {
+                docs.push(dismax.doc());
+                dismax.advance();
+            }
+            docs
+        };
+
+        assert_eq!((0..20).chain(30..40).collect::<Vec<_>>(), docs_matching(1));
+        assert_eq!((5..15).collect::<Vec<_>>(), docs_matching(2));
+        assert_eq!(vec![8, 9], docs_matching(3));
+        assert!(docs_matching(4).is_empty());
+        assert!(docs_matching(10).is_empty());
+
+        let mut dismax = DisMaxScorer::with_min_match(
+            ranges.iter().cloned().map(make_test_scorer).collect(),
+            0.0,
+            2,
+        );
+        assert_eq!(5, dismax.doc());
+        assert_eq!(12, dismax.seek(12));
+        assert_eq!(TERMINATED, dismax.seek(15));
+    }
+
+    #[test]
+    fn min_match_query() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(title => "bacon", body => "bacon"));
+        writer.add_document(doc!(title => "eggs", body => "bacon"));
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let query_for = |field| -> Box<dyn Query> {
+            Box::new(TermQuery::new(
+                Term::from_field_text(field, "bacon"),
+                IndexRecordOption::Basic,
+            ))
+        };
+
+        let dismax = DisMaxQuery::new(vec![query_for(title), query_for(body)], 0.0);
+
+        assert_eq!(2, searcher.search(&dismax, &Count)?);
+        assert_eq!(
+            2,
+            searcher.search(&dismax.clone().with_min_match(0), &Count)?
+        );
+
+        let found = searcher.search(&dismax.clone().with_min_match(2), &TopDocs::with_limit(2))?;
+        assert_eq!(1, found.len());
+        assert_eq!(0, found[0].1.doc_id);
+
+        // More than the number of disjuncts: nothing matches
+        assert_eq!(0, searcher.search(&dismax.with_min_match(3), &Count)?);
+
+        Ok(())
+    }
+
     #[test]
     #[allow(clippy::float_cmp)]
     fn tiebreaker() {
@@ -342,6 +670,23 @@ mod tests {
             "Shouldn't be able to explain a non-matching doc"
         );
 
+        let boosted = dismax.clone().with_boost(2.0);
+        let found = searcher.search(&dismax, &TopDocs::with_limit(3))?;
+        let boosted_found = searcher.search(&boosted, &TopDocs::with_limit(3))?;
+
+        assert_eq!(found.len(), boosted_found.len());
+        for ((score, addr), (boosted_score, boosted_addr)) in found.iter().zip(&boosted_found) {
+            assert_eq!(addr, boosted_addr);
+            assert!((score * 2.0 - boosted_score).abs() < 1e-4);
+        }
+
+        let explanation = boosted.explain(&searcher, found[0].1)?;
+        assert!((explanation.value() - found[0].0 * 2.0).abs() < 1e-4);
+        assert!(explanation.to_pretty_json().contains(") * 2"));
+
+        let explanation = boosted.explain(&searcher, found[0].1)?;
+        assert!(explanation.to_pretty_json().contains("Weight = 1"));
+
         // Ensure every other doc can be explained
         for doc_id in 0..3 {
             let explanation = dismax.explain(
@@ -356,4 +701,56 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn weighted_disjuncts() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(title => "bacon", body => "eggs"));
+        writer.add_document(doc!(title => "eggs", body => "bacon"));
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let query_for = |field| -> Box<dyn Query> {
+            Box::new(TermQuery::new(
+                Term::from_field_text(field, "bacon"),
+                IndexRecordOption::Basic,
+            ))
+        };
+
+        let plain = DisMaxQuery::new(vec![query_for(title), query_for(body)], 0.0);
+        let weighted =
+            DisMaxQuery::with_weights(vec![(query_for(title), 3.0), (query_for(body), 1.0)], 0.0);
+
+        let found = searcher.search(&plain, &TopDocs::with_limit(2))?;
+        let weighted_found = searcher.search(&weighted, &TopDocs::with_limit(2))?;
+
+        // Both fields look the same, so the scores are the same
+        assert_eq!(2, found.len());
+        assert!((found[0].0 - found[1].0).abs() < 1e-4);
+
+        // But now matching on the title is way more important
+        assert_eq!(2, weighted_found.len());
+        assert_eq!(0, weighted_found[0].1.doc_id);
+        assert!((weighted_found[0].0 - found[0].0 * 3.0).abs() < 1e-4);
+        assert!((weighted_found[1].0 - found[0].0).abs() < 1e-4);
+
+        let explanation = weighted.explain(&searcher, weighted_found[0].1)?;
+        assert!((explanation.value() - weighted_found[0].0).abs() < 1e-4);
+        assert!(explanation.to_pretty_json().contains("Weight = 3"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_weights_are_rejected() {
+        DisMaxQuery::with_weights(vec![(Box::new(tantivy::query::AllQuery), -1.0)], 0.0);
+    }
 }
@@ -44,9 +44,12 @@ pub mod conditional_collector;
 pub mod topterms;
 
 #[cfg(feature = "queryparser")]
-mod queryparser;
+pub mod queryparser;
 #[cfg(feature = "queryparser")]
 pub use queryparser::QueryParser;
 
 mod dismax;
 pub use dismax::DisMaxQuery;
+
+mod min_should_match;
+pub use min_should_match::MinShouldMatchQuery;
@@ -0,0 +1,269 @@
+use tantivy::{
+    self,
+    query::{EmptyScorer, Explanation, Query, Scorer, Weight},
+    DocId, DocSet, Result, Score, Searcher, SegmentReader, TantivyError, TERMINATED,
+};
+
+/// A disjunction query that only matches documents that match at
+/// least a given number of its sub-queries
+///
+/// Matching documents are scored by the sum of the scores of every
+/// sub-query they match, just like a "should" BooleanQuery would.
+///
+/// A minimum of zero behaves the same as one: every document that
+/// matches any of the sub-queries is a match.
+#[derive(Debug)]
+pub struct MinShouldMatchQuery {
+    disjuncts: Vec<Box<dyn Query>>,
+    min: usize,
+}
+
+impl MinShouldMatchQuery {
+    /// Create a union-like query that requires at least `min` of
+    /// the given sub-queries to match
+    pub fn new(disjuncts: Vec<Box<dyn Query>>, min: usize) -> Self {
+        Self {
+            disjuncts,
+            min: min.max(1),
+        }
+    }
+}
+
+impl Clone for MinShouldMatchQuery {
+    fn clone(&self) -> Self {
+        Self {
+            disjuncts: self.disjuncts.iter().map(|q| q.box_clone()).collect(),
+            min: self.min,
+        }
+    }
+}
+
+impl Query for MinShouldMatchQuery {
+    fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        Ok(Box::new(MinShouldMatchWeight {
+            weights: self
+                .disjuncts
+                .iter()
+                .map(|d| d.weight(searcher, scoring_enabled))
+                .collect::<Result<Vec<_>>>()?,
+            min: self.min,
+        }))
+    }
+}
+
+struct MinShouldMatchWeight {
+    weights: Vec<Box<dyn Weight>>,
+    min: usize,
+}
+
+impl Weight for MinShouldMatchWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: f32) -> Result<Box<dyn Scorer>> {
+        if self.min > self.weights.len() {
+            Ok(Box::new(EmptyScorer))
+        } else {
+            Ok(Box::new(MinShouldMatchScorer::new(
+                self.weights
+                    .iter()
+                    .map(|w| w.scorer(reader, boost))
+                    .collect::<Result<Vec<_>>>()?,
+                self.min,
+            )))
+        }
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+
+        if scorer.doc() > doc || scorer.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument("Not a match".to_owned()));
+        }
+
+        let mut explanation = Explanation::new(
+            format!("MinShouldMatchQuery. Matched at least {}", self.min),
+            scorer.score(),
+        );
+
+        for weight in &self.weights {
+            if let Ok(sub_explanation) = weight.explain(reader, doc) {
+                explanation.add_detail(sub_explanation);
+            }
+        }
+
+        Ok(explanation)
+    }
+}
+
+struct MinShouldMatchScorer {
+    scorers: Vec<Box<dyn Scorer>>,
+    current: DocId,
+    min: usize,
+}
+
+impl MinShouldMatchScorer {
+    fn new(scorers: Vec<Box<dyn Scorer>>, min: usize) -> Self {
+        let current = scorers.iter().map(|s| s.doc()).min().unwrap_or(TERMINATED);
+        let mut scorer = Self {
+            scorers,
+            current,
+            min,
+        };
+
+        if current != TERMINATED && scorer.num_matches() < min {
+            scorer.advance();
+        }
+
+        scorer
+    }
+
+    fn num_matches(&self) -> usize {
+        self.scorers
+            .iter()
+            .filter(|s| s.doc() == self.current)
+            .count()
+    }
+
+    // Moves to the next doc that matches any of the scorers
+    fn advance_union(&mut self) -> DocId {
+        let mut next_target = TERMINATED;
+        let mut to_remove = Vec::new();
+
+        for (idx, scorer) in self.scorers.iter_mut().enumerate() {
+            if scorer.doc() <= self.current && scorer.advance() == TERMINATED {
+                to_remove.push(idx);
+                continue;
+            }
+
+            let doc = scorer.doc();
+
+            if doc < next_target {
+                next_target = doc;
+            }
+        }
+
+        while let Some(idx) = to_remove.pop() {
+            self.scorers.remove(idx);
+        }
+
+        self.current = next_target;
+        next_target
+    }
+}
+
+impl Scorer for MinShouldMatchScorer {
+    fn score(&mut self) -> Score {
+        let current = self.current;
+        self.scorers
+            .iter_mut()
+            .filter(|s| s.doc() == current)
+            .map(|s| s.score())
+            .sum()
+    }
+}
+
+impl DocSet for MinShouldMatchScorer {
+    fn advance(&mut self) -> DocId {
+        loop {
+            // Not enough scorers left to ever match again
+            if self.scorers.len() < self.min {
+                self.current = TERMINATED;
+                return TERMINATED;
+            }
+
+            let doc = self.advance_union();
+
+            if doc == TERMINATED || self.num_matches() >= self.min {
+                return doc;
+            }
+        }
+    }
+
+    fn doc(&self) -> tantivy::DocId {
+        self.current
+    }
+
+    fn size_hint(&self) -> u32 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::DocSetCollector,
+        doc,
+        query::TermQuery,
+        schema::{IndexRecordOption, SchemaBuilder, TEXT},
+        DocAddress, Index, Term,
+    };
+
+    #[test]
+    fn only_matches_docs_with_enough_terms() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "a b c"));
+        writer.add_document(doc!(field => "a b"));
+        writer.add_document(doc!(field => "a"));
+        writer.add_document(doc!(field => "b c"));
+        writer.add_document(doc!(field => "d"));
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let query = |min| {
+            let disjuncts = ["a", "b", "c"]
+                .iter()
+                .map(|text| -> Box<dyn Query> {
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(field, text),
+                        IndexRecordOption::WithFreqs,
+                    ))
+                })
+                .collect();
+            MinShouldMatchQuery::new(disjuncts, min)
+        };
+
+        let matches = |min| -> Result<Vec<DocId>> {
+            let mut found = searcher
+                .search(&query(min), &DocSetCollector)?
+                .into_iter()
+                .map(|addr| addr.doc_id)
+                .collect::<Vec<_>>();
+            found.sort_unstable();
+            Ok(found)
+        };
+
+        assert_eq!(vec![0, 1, 2, 3], matches(0)?);
+        assert_eq!(vec![0, 1, 2, 3], matches(1)?);
+        assert_eq!(vec![0, 1, 3], matches(2)?);
+        assert_eq!(vec![0], matches(3)?);
+        assert!(matches(4)?.is_empty());
+
+        assert!(query(2)
+            .explain(
+                &searcher,
+                DocAddress {
+                    segment_ord: 0,
+                    doc_id: 2
+                }
+            )
+            .is_err());
+        assert!(query(2)
+            .explain(
+                &searcher,
+                DocAddress {
+                    segment_ord: 0,
+                    doc_id: 1
+                }
+            )?
+            .to_pretty_json()
+            .contains("MinShouldMatchQuery"));
+
+        Ok(())
+    }
+}
@@ -5,8 +5,10 @@
 //! logic.
 //!
 //! Supports multiple fields, boosts, required (+) and restricted (-)
-//! items and can generate queries using `DisMaxQuery` for better
-//! results when you have fields with very similar vocabularies.
+//! items, `AND`/`OR` operators, grouping via parentheses, numeric
+//! ranges (`field:[1 TO 10]`) and can generate queries using
+//! `DisMaxQuery` for better results when you have fields with very
+//! similar vocabularies.
 //!
 //! **NOTE**: Requires the `queryparser` compilation feature.
 //!
@@ -17,7 +19,7 @@
 //! # let ingredients = tantivy::schema::Field::from_field_id(1);
 //! let parser = tique::QueryParser::new(&index, vec![name, ingredients])?;
 //!
-//! if let Some(query) = parser.parse(r#"+bacon cheese -ingredients:olive "deep fry""#) {
+//! if let Some(query) = parser.parse(r#"+bacon (cheese OR ham) -ingredients:olive "deep fry""#) {
 //!     // Do your thing with the query...
 //! }
 //!
@@ -28,3 +30,4 @@ mod parser;
 mod raw;
 
 pub use parser::QueryParser;
+pub use raw::{Operator, RawQuery, RawRange};
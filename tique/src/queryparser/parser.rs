@@ -1,12 +1,17 @@
-use super::raw::{parse_query, FieldNameValidator, RawQuery};
+use std::ops::Bound;
+
+use super::raw::{parse_query, FieldNameValidator, Operator, RawQuery};
 use crate::DisMaxQuery;
 
 use tantivy::{
     self,
-    query::{AllQuery, BooleanQuery, BoostQuery, Occur, PhraseQuery, Query, TermQuery},
-    schema::{Field, IndexRecordOption},
+    query::{
+        AllQuery, BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, RangeQuery,
+        RegexQuery, TermQuery,
+    },
+    schema::{Field, FieldType, IndexRecordOption},
     tokenizer::TextAnalyzer,
-    Index, Result, Term,
+    Index, Result, TantivyError, Term,
 };
 
 /// Parse queries from arbitrary end-user input
@@ -33,9 +38,57 @@ use tantivy::{
 /// Which ends up prohibiting documents with "egg" in the "ingredients"
 /// field from showing up.
 ///
+/// Items can also be joined by the (uppercase) `AND` and `OR`
+/// operators:
+///
+/// > chicken OR beef AND rice
+///
+/// `OR` is what happens by default, so `chicken OR beef` is the same
+/// as `chicken beef`. `AND` takes precedence and makes the items
+/// around it required, exactly like prefixing them with `+` would:
+/// the query above is the same as `chicken +beef +rice`, so "chicken"
+/// only affects the ranking. Items that are already required or
+/// prohibited stay that way. Operators that don't sit between two
+/// items are searched for as regular words, and so is a quoted
+/// `"AND"`.
+///
+/// Parentheses group items together, so that they are treated as a
+/// single one:
+///
+/// > (chicken OR beef) AND rice -spicy
+///
+/// Requires "rice" and either "chicken" or "beef". If the parentheses
+/// in the input don't balance out, they are simply taken as part of
+/// the words around them.
+///
+/// A trailing `*` turns a word into a prefix, so `choco*` finds both
+/// "chocolate" and "chocolatey". The prefix is analyzed just like any
+/// other word, so with a stemming analyzer it matches against the
+/// stemmed form of the indexed words.
+///
+/// Appending `^` and a number to an item changes its importance, on
+/// top of any boost set via `QueryParser::set_boost`:
+///
+/// > bacon^2 eggs "fried rice"^0.5
+///
+/// Boosts that aren't plain non-negative numbers are taken as part
+/// of the word instead.
+///
+/// Typos can be tolerated by appending `~` and an edit distance (0,
+/// 1 or 2) to a word: `bananna~1` finds "banana". A bare `~` means
+/// a distance of 1 and anything above 2 is taken as 2. Fuzziness is
+/// only applied when the field analyzer turns the word into a single
+/// token, which is then looked up as usual otherwise.
+///
+/// Fields registered via `QueryParser::add_numeric_field` can be
+/// queried by (inclusive) ranges:
+///
+/// > calories:[100 TO 500] protein:[20 TO *]
+///
 pub struct QueryParser {
     state: Vec<(Option<String>, Option<f32>, Interpreter)>,
     default_indices: Vec<usize>,
+    aliases: Vec<(String, Field)>,
 }
 
 impl QueryParser {
@@ -52,6 +105,7 @@ impl QueryParser {
         let mut parser = QueryParser {
             default_indices: (0..fields.len()).collect(),
             state: Vec::with_capacity(fields.len()),
+            aliases: Vec::new(),
         };
 
         for field in fields {
@@ -60,7 +114,7 @@ impl QueryParser {
                 None,
                 Interpreter {
                     field,
-                    analyzer: index.tokenizer_for_field(field)?,
+                    kind: FieldKind::Text(index.tokenizer_for_field(field)?),
                 },
             ));
         }
@@ -68,6 +122,47 @@ impl QueryParser {
         Ok(parser)
     }
 
+    /// Make a numeric field known to the parser
+    ///
+    /// Numeric fields are never queried by default: they must be
+    /// addressed by name, like `calories:[100 TO 500]` or `calories:42`.
+    /// Use `*` for an open-ended range, as in `calories:[100 TO *]`.
+    ///
+    /// # Errors
+    ///
+    /// Will yield an error if `field` is not a u64 nor a f64 field in
+    /// the schema of the given `index`
+    pub fn add_numeric_field(&mut self, index: &Index, field: Field) -> Result<()> {
+        let schema = index.schema();
+        let entry = schema.get_field_entry(field);
+
+        let kind = match entry.field_type() {
+            FieldType::U64(_) => FieldKind::U64,
+            FieldType::F64(_) => FieldKind::F64,
+            _ => {
+                return Err(TantivyError::SchemaError(format!(
+                    "Field {} is not a u64 nor a f64 field",
+                    entry.name()
+                )))
+            }
+        };
+
+        if let Some(row) = self
+            .position_by_field(field)
+            .and_then(|pos| self.state.get_mut(pos))
+        {
+            row.2.kind = kind;
+        } else {
+            self.state.push((
+                Some(entry.name().to_owned()),
+                None,
+                Interpreter { field, kind },
+            ));
+        }
+
+        Ok(())
+    }
+
     /// Configure the importance of a field
     ///
     /// By default, every field has a boost of `None`, which is equivalent
@@ -99,6 +194,40 @@ impl QueryParser {
         }
     }
 
+    /// Make a field addressable by an additional name
+    ///
+    /// Aliases work just like the names set via `QueryParser::set_name`,
+    /// so after `parser.add_alias(calories, "cals".to_owned())` both
+    /// `cals:` and `calories:` address the same field. A field may
+    /// have any number of aliases.
+    ///
+    /// Field names always win over aliases: if you rename a field to
+    /// something that's an alias of another, the alias stops working.
+    ///
+    /// # Errors
+    ///
+    /// Will yield an error if `field` is not known by this parser or
+    /// if `alias` is the name or an alias of a different field
+    pub fn add_alias(&mut self, field: Field, alias: String) -> Result<()> {
+        if self.position_by_field(field).is_none() {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Field {:?} is not known by this parser",
+                field
+            )));
+        }
+
+        match self.position_by_name(&alias) {
+            Some(pos) if self.state[pos].2.field != field => Err(TantivyError::InvalidArgument(
+                format!("Alias {} is already used by another field", alias),
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.aliases.push((alias, field));
+                Ok(())
+            }
+        }
+    }
+
     /// Configure which fields are queried by default
     ///
     /// When a query input doesn't specify a field name explicitly, the
@@ -155,32 +284,53 @@ impl QueryParser {
         })
     }
 
+    /// Parse arbitrary user input into the items this parser understands,
+    /// without turning them into a query
+    ///
+    /// Useful for inspecting what was actually asked for: fields, phrases,
+    /// prohibited items and so on. Only field names known by this parser
+    /// are recognized as such, exactly like when parsing into a query.
+    pub fn parse_to_raw<'a>(&self, input: &'a str) -> Vec<RawQuery<'a>> {
+        parse_query(input, self)
+            .map(|(_, parsed)| parsed)
+            .unwrap_or_default()
+    }
+
     fn parse_inner<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
         &self,
         input: &str,
         // Guaranteed to receive a vec of len > 1 if called
         many_handler: F,
     ) -> Option<Box<dyn Query>> {
-        let (_, parsed) = parse_query(input, self).ok()?;
+        self.query_from_raws(&self.parse_to_raw(input), &many_handler)
+    }
+
+    // Groups recurse back here, so every group ends up as a
+    // (nested) query of its own
+    fn query_from_raws<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
+        &self,
+        raws: &[RawQuery],
+        many_handler: &F,
+    ) -> Option<Box<dyn Query>> {
         let mut clauses = Vec::new();
         let mut num_must_not = 0;
 
-        parsed
-            .into_iter()
-            .map(|raw| (self.queries_from_raw(&raw), raw))
+        raws.iter()
+            .zip(occurs_with_conjunctions(raws))
+            .map(|(raw, occur)| (self.queries_from_raw(raw, many_handler), occur))
             .filter(|(queries, _)| !queries.is_empty())
-            .for_each(|(queries, raw)| {
-                if raw.occur == Occur::MustNot {
+            .for_each(|(queries, occur)| {
+                if occur == Occur::MustNot {
                     for query in queries {
                         num_must_not += 1;
                         clauses.push((Occur::MustNot, query));
                     }
                 } else if queries.len() == 1 {
-                    clauses.push((raw.occur, queries.into_iter().next().unwrap()));
+                    clauses.push((occur, queries.into_iter().next().unwrap()));
                 } else {
                     // Now we have multiple positive queries that were generated
                     // out of a single raw query.
-                    clauses.push((raw.occur, many_handler(queries)));
+                    clauses.push((occur, many_handler(queries)));
                 }
             });
 
@@ -207,7 +357,19 @@ impl QueryParser {
         }
     }
 
-    fn queries_from_raw(&self, raw_query: &RawQuery) -> Vec<Box<dyn Query>> {
+    fn queries_from_raw<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
+        &self,
+        raw_query: &RawQuery,
+        many_handler: &F,
+    ) -> Vec<Box<dyn Query>> {
+        if let Some(items) = &raw_query.group {
+            return self
+                .query_from_raws(items, many_handler)
+                .map(|query| boosted(query, raw_query.boost))
+                .into_iter()
+                .collect();
+        }
+
         let indices = if let Some(position) = raw_query
             .field_name
             .and_then(|field_name| self.position_by_name(field_name))
@@ -221,13 +383,13 @@ impl QueryParser {
             .into_iter()
             .flat_map(|i| self.state.get(i))
             .flat_map(|(_, boost, interpreter)| {
-                interpreter.to_query(raw_query).map(|query| {
-                    if let Some(val) = boost {
-                        Box::new(BoostQuery::new(query, *val))
-                    } else {
-                        query
-                    }
-                })
+                let boost = match (boost, raw_query.boost) {
+                    (Some(field_boost), Some(item_boost)) => Some(field_boost * item_boost),
+                    (field_boost, item_boost) => field_boost.or(item_boost),
+                };
+                interpreter
+                    .to_query(raw_query)
+                    .map(|query| boosted(query, boost))
             })
             .collect()
     }
@@ -238,6 +400,12 @@ impl QueryParser {
             .position(|(opt_name, _opt_boost, _interpreter)| {
                 opt_name.as_ref().map_or(false, |name| name == field_name)
             })
+            .or_else(|| {
+                self.aliases
+                    .iter()
+                    .find(|(alias, _field)| alias == field_name)
+                    .and_then(|(_alias, field)| self.position_by_field(*field))
+            })
     }
 
     fn position_by_field(&self, field: Field) -> Option<usize> {
@@ -249,23 +417,51 @@ impl QueryParser {
 
 impl FieldNameValidator for QueryParser {
     fn check(&self, field_name: &str) -> bool {
-        self.state
-            .iter()
-            .any(|(opt_name, _opt_boost, _interpreter)| {
-                opt_name.as_ref().map_or(false, |name| name == field_name)
-            })
+        self.position_by_name(field_name).is_some()
     }
 }
 
 struct Interpreter {
     field: Field,
-    analyzer: TextAnalyzer,
+    kind: FieldKind,
+}
+
+enum FieldKind {
+    Text(TextAnalyzer),
+    U64,
+    F64,
 }
 
 impl Interpreter {
     fn to_query(&self, raw_query: &RawQuery) -> Option<Box<dyn Query>> {
+        match &self.kind {
+            FieldKind::Text(analyzer) => self.text_query(analyzer, raw_query),
+            FieldKind::U64 => self.numeric_query::<u64>(raw_query),
+            FieldKind::F64 => self.numeric_query::<f64>(raw_query),
+        }
+    }
+
+    fn numeric_query<T: NumericValue>(&self, raw_query: &RawQuery) -> Option<Box<dyn Query>> {
+        if raw_query.is_prefix {
+            None
+        } else if let Some(range) = &raw_query.range {
+            Some(Box::new(T::range_query(
+                self.field,
+                parse_bound(range.start)?,
+                parse_bound(range.end)?,
+            )))
+        } else {
+            let value = raw_query.input.trim().parse().ok()?;
+            Some(Box::new(TermQuery::new(
+                T::term(self.field, value),
+                IndexRecordOption::Basic,
+            )))
+        }
+    }
+
+    fn text_query(&self, analyzer: &TextAnalyzer, raw_query: &RawQuery) -> Option<Box<dyn Query>> {
         let mut terms = Vec::new();
-        let mut stream = self.analyzer.token_stream(raw_query.input);
+        let mut stream = analyzer.token_stream(raw_query.input);
 
         stream.process(&mut |token| {
             terms.push(Term::from_field_text(self.field, &token.text));
@@ -275,7 +471,13 @@ impl Interpreter {
             return None;
         }
 
-        let query: Box<dyn Query> = if terms.len() == 1 {
+        if raw_query.is_prefix {
+            return self.prefix_query(terms);
+        }
+
+        let query: Box<dyn Query> = if let (1, Some(distance)) = (terms.len(), raw_query.fuzzy) {
+            Box::new(FuzzyTermQuery::new(terms.pop().unwrap(), distance, true))
+        } else if terms.len() == 1 {
             Box::new(TermQuery::new(
                 terms.pop().unwrap(),
                 IndexRecordOption::WithFreqs,
@@ -291,6 +493,100 @@ impl Interpreter {
 
         Some(query)
     }
+
+    // The prefix goes through the analyzer like everything else, since
+    // that's how the indexed terms look like: "Choco*" should find
+    // "chocolate" even if the index is lowercased. Only the last token
+    // is taken as a prefix, the others are queried as regular terms
+    fn prefix_query(&self, mut terms: Vec<Term>) -> Option<Box<dyn Query>> {
+        let last = terms.pop()?;
+        let pattern = format!("{}.*", escape_regex(last.text()));
+        let prefix: Box<dyn Query> = Box::new(RegexQuery::from_pattern(&pattern, self.field).ok()?);
+
+        if terms.is_empty() {
+            Some(prefix)
+        } else {
+            let mut clauses = terms
+                .into_iter()
+                .map(|term| {
+                    let query: Box<dyn Query> =
+                        Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+                    (Occur::Should, query)
+                })
+                .collect::<Vec<_>>();
+            clauses.push((Occur::Should, prefix));
+            Some(Box::new(BooleanQuery::from(clauses)))
+        }
+    }
+}
+
+fn boosted(query: Box<dyn Query>, boost: Option<f32>) -> Box<dyn Query> {
+    if let Some(val) = boost {
+        Box::new(BoostQuery::new(query, val))
+    } else {
+        query
+    }
+}
+
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// `a AND b` is the same as `+a +b`
+fn occurs_with_conjunctions(raws: &[RawQuery]) -> Vec<Occur> {
+    let mut occurs = raws.iter().map(|raw| raw.occur).collect::<Vec<_>>();
+
+    for (idx, raw) in raws.iter().enumerate().skip(1) {
+        if raw.operator == Some(Operator::And) {
+            for occur in &mut occurs[idx - 1..=idx] {
+                if *occur == Occur::Should {
+                    *occur = Occur::Must;
+                }
+            }
+        }
+    }
+
+    occurs
+}
+
+// Unparseable bounds yield `None`, open-ended ones `Some(Unbounded)`
+fn parse_bound<T: std::str::FromStr>(bound: Option<&str>) -> Option<Bound<T>> {
+    match bound {
+        Some(value) => value.parse().ok().map(Bound::Included),
+        None => Some(Bound::Unbounded),
+    }
+}
+
+trait NumericValue: std::str::FromStr + Sized {
+    fn term(field: Field, value: Self) -> Term;
+    fn range_query(field: Field, start: Bound<Self>, end: Bound<Self>) -> RangeQuery;
+}
+
+impl NumericValue for u64 {
+    fn term(field: Field, value: u64) -> Term {
+        Term::from_field_u64(field, value)
+    }
+
+    fn range_query(field: Field, start: Bound<u64>, end: Bound<u64>) -> RangeQuery {
+        RangeQuery::new_u64_bounds(field, start, end)
+    }
+}
+
+impl NumericValue for f64 {
+    fn term(field: Field, value: f64) -> Term {
+        Term::from_field_f64(field, value)
+    }
+
+    fn range_query(field: Field, start: Bound<f64>, end: Bound<f64>) -> RangeQuery {
+        RangeQuery::new_f64_bounds(field, start, end)
+    }
 }
 
 #[cfg(test)]
@@ -301,14 +597,14 @@ mod tests {
     use tantivy::{
         collector::TopDocs,
         doc,
-        schema::{SchemaBuilder, TEXT},
+        schema::{SchemaBuilder, INDEXED, TEXT},
         DocAddress, SegmentOrdinal,
     };
 
     fn test_interpreter() -> Interpreter {
         Interpreter {
             field: Field::from_field_id(0),
-            analyzer: TokenizerManager::default().get("en_stem").unwrap(),
+            kind: FieldKind::Text(TokenizerManager::default().get("en_stem").unwrap()),
         }
     }
 
@@ -344,6 +640,41 @@ mod tests {
         assert!(query.as_any().downcast_ref::<TermQuery>().is_some());
     }
 
+    #[test]
+    fn range_raw_on_numeric_field_is_rangequery() {
+        let interpreter = Interpreter {
+            field: Field::from_field_id(0),
+            kind: FieldKind::U64,
+        };
+
+        let query = interpreter
+            .to_query(&RawQuery::new("[1 TO *]").with_range(Some("1"), None))
+            .expect("parses to a Some(Query)");
+        assert!(query.as_any().downcast_ref::<RangeQuery>().is_some());
+
+        let query = interpreter
+            .to_query(&RawQuery::new("42"))
+            .expect("parses to a Some(Query)");
+        assert!(query.as_any().downcast_ref::<TermQuery>().is_some());
+
+        // Bounds that don't parse as the field type yield nothing
+        assert!(interpreter
+            .to_query(&RawQuery::new("[a TO 2]").with_range(Some("a"), Some("2")))
+            .is_none());
+        assert!(interpreter
+            .to_query(&RawQuery::new("[1.5 TO 2]").with_range(Some("1.5"), Some("2")))
+            .is_none());
+    }
+
+    #[test]
+    fn range_raw_on_text_field_is_not_rangequery() {
+        let query = test_interpreter()
+            .to_query(&RawQuery::new("[1 TO 2]").with_range(Some("1"), Some("2")))
+            .expect("parses to a Some(Query)");
+
+        assert!(query.as_any().downcast_ref::<RangeQuery>().is_none());
+    }
+
     fn single_field_test_parser() -> QueryParser {
         QueryParser {
             default_indices: vec![0],
@@ -352,9 +683,10 @@ mod tests {
                 None,
                 Interpreter {
                     field: Field::from_field_id(0),
-                    analyzer: TokenizerManager::default().get("en_stem").unwrap(),
+                    kind: FieldKind::Text(TokenizerManager::default().get("en_stem").unwrap()),
                 },
             )],
+            aliases: Vec::new(),
         }
     }
 
@@ -434,6 +766,333 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn and_operator_requires_neighbours() {
+        let raws = vec![
+            RawQuery::new("a"),
+            RawQuery::new("b").with_operator(Operator::Or),
+            RawQuery::new("c").with_operator(Operator::And),
+            RawQuery::new("d").must_not().with_operator(Operator::And),
+            RawQuery::new("e"),
+        ];
+
+        assert_eq!(
+            vec![
+                Occur::Should,
+                Occur::Must,
+                Occur::Must,
+                Occur::MustNot,
+                Occur::Should
+            ],
+            occurs_with_conjunctions(&raws)
+        );
+    }
+
+    #[test]
+    fn operators_integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(name => "chicken and rice"));
+        writer.add_document(doc!(name => "beef and beans"));
+        writer.add_document(doc!(name => "rice and beans"));
+
+        writer.commit()?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let parser = QueryParser::new(&index, vec![name])?;
+
+        let search = |input: &str| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            let mut found = searcher
+                .search(&query, &TopDocs::with_limit(3))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.doc_id)
+                .collect::<Vec<_>>();
+            found.sort_unstable();
+            found
+        };
+
+        assert_eq!(vec![0, 1], search("chicken OR beef"));
+        assert_eq!(vec![2], search("rice AND beans"));
+        assert_eq!(vec![1, 2], search("chicken OR beef AND beans"));
+        assert_eq!(vec![0], search("rice AND -beans"));
+        // Quoted, it's just a word
+        assert_eq!(vec![0, 1, 2], search("\"AND\""));
+
+        assert_eq!(vec![0, 1], search("(chicken OR beef) -\"rice and beans\""));
+        assert_eq!(vec![0], search("(chicken OR beef) AND rice"));
+        assert_eq!(vec![2], search("-(chicken OR beef)"));
+        assert_eq!(vec![1], search("beef AND (-chicken -rice)"));
+        // Unbalanced: just words
+        assert_eq!(vec![0, 1], search("(chicken OR beef"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn regex_escaping() {
+        assert_eq!("choco", escape_regex("choco"));
+        assert_eq!("c\\+\\+", escape_regex("c++"));
+        assert_eq!("a\\.b\\*\\\\", escape_regex("a.b*\\"));
+    }
+
+    #[test]
+    fn prefix_integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let calories = builder.add_u64_field("calories", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(name => "Chocolate cake", calories => 12u64));
+        writer.add_document(doc!(name => "Chocolatey brownies"));
+        writer.add_document(doc!(name => "Carrot cake"));
+        writer.add_document(doc!(name => "c++ cookies"));
+
+        writer.commit()?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let mut parser = QueryParser::new(&index, vec![name])?;
+        parser.add_numeric_field(&index, calories)?;
+
+        let search = |input: &str| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            let mut found = searcher
+                .search(&query, &TopDocs::with_limit(4))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.doc_id)
+                .collect::<Vec<_>>();
+            found.sort_unstable();
+            found
+        };
+
+        assert_eq!(vec![0, 1], search("choco*"));
+        // Goes through the analyzer, so it's lowercased
+        assert_eq!(vec![0, 1], search("CHOCO*"));
+        assert_eq!(vec![0, 2], search("+ca* -brown*"));
+        assert_eq!(vec![1], search("name:chocolatey*"));
+        // No prefixes for numeric fields
+        assert!(parser.parse("calories:1*").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn item_boosting() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(name => "bacon"));
+        writer.add_document(doc!(name => "eggs"));
+        writer.add_document(doc!(name => "eggs"));
+
+        writer.commit()?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let mut parser = QueryParser::new(&index, vec![name])?;
+
+        let top = |parser: &QueryParser, input: &str| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher
+                .search(&query, &TopDocs::with_limit(3))
+                .expect("working index")
+        };
+
+        // "bacon" is rarer, so it wins by default
+        assert_eq!(doc_addr(0, 0), top(&parser, "bacon eggs")[0].1);
+        assert_ne!(doc_addr(0, 0), top(&parser, "bacon eggs^4")[0].1);
+        assert_ne!(doc_addr(0, 0), top(&parser, "bacon^0.1 eggs")[0].1);
+        assert_ne!(doc_addr(0, 0), top(&parser, "bacon (eggs)^4")[0].1);
+
+        // Item boosts multiply the field boost
+        let item_boosted = top(&parser, "bacon^2")[0].0;
+        parser.set_boost(name, Some(3.0));
+        let both_boosted = top(&parser, "bacon^2")[0].0;
+        assert!((both_boosted - item_boosted * 3.0).abs() < 1e-4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fuzzy_integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(name => "Banana bread"));
+        writer.add_document(doc!(name => "Bandana cake"));
+
+        writer.commit()?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let parser = QueryParser::new(&index, vec![name])?;
+
+        let search = |input: &str| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            let mut found = searcher
+                .search(&query, &TopDocs::with_limit(2))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.doc_id)
+                .collect::<Vec<_>>();
+            found.sort_unstable();
+            found
+        };
+
+        assert!(search("bananna").is_empty());
+        assert_eq!(vec![0], search("bananna~1"));
+        // Analyzed before going fuzzy
+        assert_eq!(vec![0], search("BANANNA~"));
+        assert_eq!(vec![0, 1], search("bananna~2"));
+        assert_eq!(vec![0, 1], search("bananna~9"));
+        assert!(search("bananna~0").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn raw_items_are_exposed() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let index = Index::create_in_ram(builder.build());
+
+        let parser = QueryParser::new(&index, vec![title])?;
+
+        let input = String::from("-title:bacon unknown:field \"deep fry\"");
+        let parsed = parser.parse_to_raw(&input);
+        // Doesn't borrow from the parser
+        drop(parser);
+
+        assert_eq!(3, parsed.len());
+
+        assert_eq!("bacon", parsed[0].input);
+        assert_eq!(Some("title"), parsed[0].field_name);
+        assert_eq!(Occur::MustNot, parsed[0].occur);
+
+        assert_eq!("unknown:field", parsed[1].input);
+        assert_eq!(None, parsed[1].field_name);
+
+        assert_eq!("deep fry", parsed[2].input);
+        assert!(parsed[2].is_phrase);
+
+        Ok(())
+    }
+
+    #[test]
+    fn aliases_resolve_to_the_same_field() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(title => "bacon", body => "eggs"));
+        writer.add_document(doc!(title => "eggs", body => "bacon"));
+
+        writer.commit()?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let mut parser = QueryParser::new(&index, vec![title, body])?;
+
+        parser.add_alias(title, "name".to_owned())?;
+        parser.add_alias(title, "t".to_owned())?;
+        // Re-adding is fine
+        parser.add_alias(title, "t".to_owned())?;
+
+        // Can't shadow names nor aliases of other fields
+        assert!(parser.add_alias(body, "title".to_owned()).is_err());
+        assert!(parser.add_alias(body, "name".to_owned()).is_err());
+
+        assert_eq!(
+            parser.position_by_name("title"),
+            parser.position_by_name("name")
+        );
+        assert_eq!(
+            parser.position_by_name("title"),
+            parser.position_by_name("t")
+        );
+
+        for input in &["title:bacon", "name:bacon", "t:bacon"] {
+            let query = parser.parse(input).expect("given input yields Some()");
+            let found = searcher.search(&query, &TopDocs::with_limit(2))?;
+            assert_eq!(1, found.len(), "input [{}] matched the body", input);
+            assert_eq!(doc_addr(0, 0), found[0].1);
+        }
+
+        // Real names win over aliases
+        parser.set_name(body, Some("t".to_owned()));
+        assert_eq!(parser.position_by_field(body), parser.position_by_name("t"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn numeric_range_integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let calories = builder.add_u64_field("calories", INDEXED);
+        let rating = builder.add_f64_field("rating", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(name => "salad", calories => 150u64, rating => 3.5));
+        writer.add_document(doc!(name => "cake", calories => 500u64, rating => 4.8));
+        writer.add_document(doc!(name => "fries", calories => 900u64, rating => 4.1));
+
+        writer.commit()?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let mut parser = QueryParser::new(&index, vec![name])?;
+
+        // Name is not numeric
+        assert!(parser.add_numeric_field(&index, name).is_err());
+
+        parser.add_numeric_field(&index, calories)?;
+        parser.add_numeric_field(&index, rating)?;
+
+        let search = |input: &str| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            let mut found = searcher
+                .search(&query, &TopDocs::with_limit(3))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.doc_id)
+                .collect::<Vec<_>>();
+            found.sort_unstable();
+            found
+        };
+
+        // Inclusive on both ends
+        assert_eq!(vec![0, 1], search("calories:[150 TO 500]"));
+        assert_eq!(vec![1, 2], search("calories:[200 TO *]"));
+        assert_eq!(vec![0], search("calories:[* TO 499]"));
+        assert_eq!(vec![2], search("calories:900"));
+
+        assert_eq!(vec![1, 2], search("rating:[4 TO *]"));
+        assert_eq!(vec![1], search("+rating:[4 TO *] -calories:[600 TO *]"));
+        assert_eq!(vec![0, 1, 2], search("-calories:[1000 TO *]"));
+
+        // Numeric fields are not queried by default
+        assert!(parser.parse("[100 TO 500]").is_some());
+        assert!(search("[100 TO 500]").is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn field_boosting() -> Result<()> {
         let mut builder = SchemaBuilder::new();
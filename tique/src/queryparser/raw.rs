@@ -1,58 +1,155 @@
 use nom::{
     self,
     branch::alt,
-    bytes::complete::take_while1,
-    character::complete::{char as is_char, multispace0},
-    combinator::{map, map_res},
+    bytes::complete::{tag, take_while1},
+    character::complete::{char as is_char, multispace0, multispace1},
+    combinator::{consumed, cut, map, map_opt, map_res, opt, value},
+    error::{Error, ErrorKind},
     multi::many0,
-    sequence::{delimited, preceded, separated_pair},
+    sequence::{delimited, pair, preceded, separated_pair, terminated},
     IResult,
 };
 use tantivy::query::Occur;
 
-#[derive(Debug, PartialEq)]
+/// An item of the user input, as understood by the `QueryParser`
+///
+/// Borrows from the parsed input, so it's cheap to inspect. Every
+/// field reflects what was typed, before any analysis takes place.
+#[derive(Debug, PartialEq, Clone)]
 pub struct RawQuery<'a> {
+    /// The text of the item, without any of the syntax around it
     pub input: &'a str,
+    /// Whether the item was quoted
     pub is_phrase: bool,
+    /// Whether the item ended with `*`
+    pub is_prefix: bool,
+    /// The field name the item was prefixed with, if known
     pub field_name: Option<&'a str>,
+    /// `Must` for `+item`, `MustNot` for `-item`, `Should` otherwise.
+    /// `AND` operators are not reflected here
     pub occur: Occur,
+    /// Bounds of `[start TO end]` items
+    pub range: Option<RawRange<'a>>,
+    /// The operator between the previous item and this one
+    pub operator: Option<Operator>,
+    /// The items inside parentheses
+    pub group: Option<Vec<RawQuery<'a>>>,
+    /// The `^` boost of the item
+    pub boost: Option<f32>,
+    /// The `~` edit distance of the item
+    pub fuzzy: Option<u8>,
+}
+
+/// An explicit operator between two items
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operator {
+    /// `AND`
+    And,
+    /// `OR`
+    Or,
+}
+
+/// Bounds of a `[start TO end]` item
+#[derive(Debug, PartialEq, Clone)]
+pub struct RawRange<'a> {
+    /// The lower bound, `None` when open-ended (`*`)
+    pub start: Option<&'a str>,
+    /// The upper bound, `None` when open-ended (`*`)
+    pub end: Option<&'a str>,
 }
 
 const FIELD_SEP: char = ':';
+const RANGE_OPEN: char = '[';
+const RANGE_CLOSE: char = ']';
+const RANGE_SEP: &str = "TO";
+const RANGE_UNBOUNDED: &str = "*";
+const PREFIX_MARKER: char = '*';
+const BOOST_SEP: char = '^';
+const FUZZY_SEP: char = '~';
+const DEFAULT_FUZZY_DISTANCE: u8 = 1;
+const MAX_FUZZY_DISTANCE: u8 = 2;
+const GROUP_OPEN: char = '(';
+const GROUP_CLOSE: char = ')';
+// Deeper nesting makes the whole input be parsed as if it had no groups
+const MAX_GROUP_DEPTH: usize = 32;
+const AND: &str = "AND";
+const OR: &str = "OR";
 
 impl<'a> RawQuery<'a> {
-    pub fn new(input: &'a str) -> Self {
+    pub(crate) fn new(input: &'a str) -> Self {
         Self {
             input,
             is_phrase: false,
+            is_prefix: false,
             field_name: None,
             occur: Occur::Should,
+            range: None,
+            operator: None,
+            group: None,
+            boost: None,
+            fuzzy: None,
         }
     }
 
-    pub fn must_not(mut self) -> Self {
+    pub(crate) fn must_not(mut self) -> Self {
         debug_assert_eq!(Occur::Should, self.occur);
         self.occur = Occur::MustNot;
         self
     }
 
-    pub fn must(mut self) -> Self {
+    pub(crate) fn must(mut self) -> Self {
         debug_assert_eq!(Occur::Should, self.occur);
         self.occur = Occur::Must;
         self
     }
 
-    pub fn phrase(mut self) -> Self {
+    pub(crate) fn phrase(mut self) -> Self {
         debug_assert!(!self.is_phrase);
         self.is_phrase = true;
         self
     }
 
-    pub fn with_field(mut self, name: &'a str) -> Self {
+    pub(crate) fn prefix(mut self) -> Self {
+        debug_assert!(!self.is_prefix);
+        self.is_prefix = true;
+        self
+    }
+
+    pub(crate) fn with_field(mut self, name: &'a str) -> Self {
         debug_assert_eq!(None, self.field_name);
         self.field_name = Some(name);
         self
     }
+
+    pub(crate) fn with_range(mut self, start: Option<&'a str>, end: Option<&'a str>) -> Self {
+        debug_assert_eq!(None, self.range);
+        self.range = Some(RawRange { start, end });
+        self
+    }
+
+    pub(crate) fn with_operator(mut self, operator: Operator) -> Self {
+        debug_assert_eq!(None, self.operator);
+        self.operator = Some(operator);
+        self
+    }
+
+    pub(crate) fn with_group(mut self, items: Vec<RawQuery<'a>>) -> Self {
+        debug_assert_eq!(None, self.group);
+        self.group = Some(items);
+        self
+    }
+
+    pub(crate) fn with_boost(mut self, boost: f32) -> Self {
+        debug_assert_eq!(None, self.boost);
+        self.boost = Some(boost);
+        self
+    }
+
+    pub(crate) fn fuzzy(mut self, distance: u8) -> Self {
+        debug_assert_eq!(None, self.fuzzy);
+        self.fuzzy = Some(distance);
+        self
+    }
 }
 
 pub trait FieldNameValidator {
@@ -76,61 +173,148 @@ impl FieldNameValidator for bool {
 
 pub fn parse_query<'a, C: FieldNameValidator>(
     input: &'a str,
-    validator: &'a C,
+    validator: &C,
 ) -> IResult<&'a str, Vec<RawQuery<'a>>> {
-    many0(delimited(
-        multispace0,
-        alt((
-            |input| prohibited_query(input, validator),
-            |input| mandatory_query(input, validator),
-            |input| field_prefixed_query(input, validator),
-            any_field_query,
-        )),
-        multispace0,
+    // Grouping is all or nothing: if the parentheses don't balance
+    // out, they are treated as regular characters
+    match parse_items(input, validator, Mode::Grouped(0)) {
+        Ok((rest, parsed)) if rest.is_empty() => Ok((rest, parsed)),
+        _ => parse_items(input, validator, Mode::Flat),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    // Parentheses are just part of the terms
+    Flat,
+    // Parentheses delimit groups; holds the nesting depth
+    Grouped(usize),
+}
+
+fn parse_items<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &C,
+    mode: Mode,
+) -> IResult<&'a str, Vec<RawQuery<'a>>> {
+    if let Mode::Grouped(depth) = mode {
+        if depth > MAX_GROUP_DEPTH {
+            return Err(nom::Err::Failure(Error::new(input, ErrorKind::TooLarge)));
+        }
+    }
+
+    let item = |input| query_item(input, validator, mode);
+
+    map(
+        pair(
+            // Operators are only recognized *between* items
+            opt(delimited(multispace0, item, multispace0)),
+            many0(delimited(
+                multispace0,
+                alt((
+                    map(pair(parse_operator, item), |(operator, raw)| {
+                        raw.with_operator(operator)
+                    }),
+                    item,
+                )),
+                multispace0,
+            )),
+        ),
+        |(first, rest)| first.into_iter().chain(rest).collect(),
+    )(input)
+}
+
+fn query_item<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &C,
+    mode: Mode,
+) -> IResult<&'a str, RawQuery<'a>> {
+    alt((
+        |input| prohibited_query(input, validator, mode),
+        |input| mandatory_query(input, validator, mode),
+        |input| unprefixed_query(input, validator, mode),
     ))(input)
 }
 
+fn parse_operator(input: &str) -> IResult<&str, Operator> {
+    terminated(
+        alt((value(Operator::And, tag(AND)), value(Operator::Or, tag(OR)))),
+        multispace1,
+    )(input)
+}
+
 fn prohibited_query<'a, C: FieldNameValidator>(
     input: &'a str,
-    validator: &'a C,
+    validator: &C,
+    mode: Mode,
 ) -> IResult<&'a str, RawQuery<'a>> {
     map(
-        preceded(
-            is_char('-'),
-            alt((
-                |input| field_prefixed_query(input, validator),
-                any_field_query,
-            )),
-        ),
+        preceded(is_char('-'), |input| {
+            unprefixed_query(input, validator, mode)
+        }),
         RawQuery::must_not,
     )(input)
 }
 
 fn mandatory_query<'a, C: FieldNameValidator>(
     input: &'a str,
-    validator: &'a C,
+    validator: &C,
+    mode: Mode,
 ) -> IResult<&'a str, RawQuery<'a>> {
     map(
-        preceded(
-            is_char('+'),
-            alt((
-                |input| field_prefixed_query(input, validator),
-                any_field_query,
-            )),
-        ),
+        preceded(is_char('+'), |input| {
+            unprefixed_query(input, validator, mode)
+        }),
         RawQuery::must,
     )(input)
 }
 
+fn unprefixed_query<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &C,
+    mode: Mode,
+) -> IResult<&'a str, RawQuery<'a>> {
+    match mode {
+        Mode::Flat => alt((
+            |input| field_prefixed_query(input, validator, mode),
+            |input| any_field_query(input, mode),
+        ))(input),
+        Mode::Grouped(depth) => alt((
+            boosted(|input| parse_group(input, validator, depth)),
+            |input| field_prefixed_query(input, validator, mode),
+            |input| any_field_query(input, mode),
+        ))(input),
+    }
+}
+
+fn parse_group<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &C,
+    depth: usize,
+) -> IResult<&'a str, RawQuery<'a>> {
+    map(
+        consumed(preceded(
+            is_char(GROUP_OPEN),
+            // No backtracking once a group is open: an unbalanced
+            // parenthesis fails the whole grouped parse
+            cut(terminated(
+                |input| parse_items(input, validator, Mode::Grouped(depth + 1)),
+                is_char(GROUP_CLOSE),
+            )),
+        )),
+        |(input, items)| RawQuery::new(input).with_group(items),
+    )(input)
+}
+
 fn field_prefixed_query<'a, C: FieldNameValidator>(
     input: &'a str,
-    validator: &'a C,
+    validator: &C,
+    mode: Mode,
 ) -> IResult<&'a str, RawQuery<'a>> {
     map_res(
         separated_pair(
             take_while1(|c| c != FIELD_SEP && is_term_char(c)),
             is_char(FIELD_SEP),
-            any_field_query,
+            |input| any_field_query(input, mode),
         ),
         |(name, term)| {
             if validator.check(name) {
@@ -142,8 +326,70 @@ fn field_prefixed_query<'a, C: FieldNameValidator>(
     )(input)
 }
 
-fn any_field_query(input: &str) -> IResult<&str, RawQuery> {
-    alt((parse_phrase, parse_term))(input)
+fn any_field_query(input: &str, mode: Mode) -> IResult<&str, RawQuery> {
+    alt((boosted(parse_range), boosted(parse_phrase), |input| {
+        parse_term(input, mode)
+    }))(input)
+}
+
+// Terms take care of their own boosts, since `^` is a valid term char
+fn boosted<'a, P>(parser: P) -> impl FnMut(&'a str) -> IResult<&'a str, RawQuery<'a>>
+where
+    P: FnMut(&'a str) -> IResult<&'a str, RawQuery<'a>>,
+{
+    map(
+        pair(
+            parser,
+            opt(map_opt(
+                preceded(
+                    is_char(BOOST_SEP),
+                    take_while1(|c: char| c.is_ascii_digit() || c == '.'),
+                ),
+                parse_boost,
+            )),
+        ),
+        |(raw, boost)| match boost {
+            Some(boost) => raw.with_boost(boost),
+            None => raw,
+        },
+    )
+}
+
+// Only plain non-negative numbers are accepted: `2`, `0.5`, `1.`
+fn parse_boost(input: &str) -> Option<f32> {
+    if input.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        input.parse().ok().filter(|boost: &f32| boost.is_finite())
+    } else {
+        None
+    }
+}
+
+fn parse_range(input: &str) -> IResult<&str, RawQuery> {
+    map(
+        consumed(delimited(
+            is_char(RANGE_OPEN),
+            separated_pair(
+                delimited(multispace0, parse_range_bound, multispace1),
+                tag(RANGE_SEP),
+                delimited(multispace1, parse_range_bound, multispace0),
+            ),
+            is_char(RANGE_CLOSE),
+        )),
+        |(input, (start, end))| RawQuery::new(input).with_range(start, end),
+    )(input)
+}
+
+fn parse_range_bound(input: &str) -> IResult<&str, Option<&str>> {
+    map(
+        take_while1(|c| c != RANGE_CLOSE && is_term_char(c)),
+        |bound| {
+            if bound == RANGE_UNBOUNDED {
+                None
+            } else {
+                Some(bound)
+            }
+        },
+    )(input)
 }
 
 fn parse_phrase(input: &str) -> IResult<&str, RawQuery> {
@@ -153,8 +399,51 @@ fn parse_phrase(input: &str) -> IResult<&str, RawQuery> {
     )(input)
 }
 
-fn parse_term(input: &str) -> IResult<&str, RawQuery> {
-    map(take_while1(is_term_char), RawQuery::new)(input)
+fn parse_term(input: &str, mode: Mode) -> IResult<&str, RawQuery> {
+    let (rest, term) = match mode {
+        Mode::Flat => take_while1(is_term_char)(input)?,
+        Mode::Grouped(_) => take_while1(|c| c != GROUP_CLOSE && is_term_char(c))(input)?,
+    };
+
+    let boosted_term = term
+        .rsplit_once(BOOST_SEP)
+        .filter(|(term, _)| !term.is_empty())
+        .and_then(|(term, boost)| Some((term, parse_boost(boost)?)));
+
+    let raw = match boosted_term {
+        Some((term, boost)) => raw_term(term).with_boost(boost),
+        None => raw_term(term),
+    };
+
+    Ok((rest, raw))
+}
+
+fn raw_term(term: &str) -> RawQuery {
+    let fuzzy_term = term
+        .rsplit_once(FUZZY_SEP)
+        .filter(|(term, _)| !term.is_empty())
+        .and_then(|(term, distance)| Some((term, parse_fuzzy_distance(distance)?)));
+
+    if let Some((term, distance)) = fuzzy_term {
+        return RawQuery::new(term).fuzzy(distance);
+    }
+
+    match term.strip_suffix(PREFIX_MARKER) {
+        Some(prefix) if !prefix.is_empty() => RawQuery::new(prefix).prefix(),
+        _ => RawQuery::new(term),
+    }
+}
+
+// Empty means the default distance; anything above the max is capped
+fn parse_fuzzy_distance(input: &str) -> Option<u8> {
+    if input.is_empty() {
+        Some(DEFAULT_FUZZY_DISTANCE)
+    } else if input.chars().all(|c| c.is_ascii_digit()) {
+        let distance = input.parse::<u64>().unwrap_or(u64::MAX);
+        Some(distance.min(u64::from(MAX_FUZZY_DISTANCE)) as u8)
+    } else {
+        None
+    }
 }
 
 fn is_term_char(c: char) -> bool {
@@ -310,6 +599,302 @@ mod tests {
         );
     }
 
+    #[test]
+    fn range_extraction() {
+        assert_eq!(
+            parse_no_fields("[100 TO 500]"),
+            Ok((
+                "",
+                vec![RawQuery::new("[100 TO 500]").with_range(Some("100"), Some("500"))]
+            ))
+        );
+
+        assert_eq!(
+            parse_no_fields("[ 0.5 TO * ]"),
+            Ok((
+                "",
+                vec![RawQuery::new("[ 0.5 TO * ]").with_range(Some("0.5"), None)]
+            ))
+        );
+
+        assert_eq!(
+            parse_query("-calories:[* TO 300] +protein:[10 TO 20]", &true),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("[* TO 300]")
+                        .with_range(None, Some("300"))
+                        .with_field("calories")
+                        .must_not(),
+                    RawQuery::new("[10 TO 20]")
+                        .with_range(Some("10"), Some("20"))
+                        .with_field("protein")
+                        .must(),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn malformed_range_is_a_term() {
+        assert_eq!(
+            parse_no_fields("[100 to 500]"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("[100"),
+                    RawQuery::new("to"),
+                    RawQuery::new("500]"),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_no_fields("[100 TO]"),
+            Ok(("", vec![RawQuery::new("[100"), RawQuery::new("TO]")]))
+        );
+    }
+
+    #[test]
+    fn operator_extraction() {
+        assert_eq!(
+            parse_no_fields("chicken OR beef AND -\"fried rice\""),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("chicken"),
+                    RawQuery::new("beef").with_operator(Operator::Or),
+                    RawQuery::new("fried rice")
+                        .phrase()
+                        .must_not()
+                        .with_operator(Operator::And),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_query("title:rice  AND  +ingredient:beans", &true),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("rice").with_field("title"),
+                    RawQuery::new("beans")
+                        .with_field("ingredient")
+                        .must()
+                        .with_operator(Operator::And),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn operators_out_of_place_are_terms() {
+        assert_eq!(
+            parse_no_fields("AND or OR"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("AND"),
+                    RawQuery::new("or"),
+                    RawQuery::new("OR"),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_no_fields("ANDROID ORacle AND \"AND\""),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("ANDROID"),
+                    RawQuery::new("ORacle"),
+                    RawQuery::new("AND").phrase().with_operator(Operator::And),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_no_fields("a OR OR b AND "),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("a"),
+                    RawQuery::new("OR").with_operator(Operator::Or),
+                    RawQuery::new("b"),
+                    RawQuery::new("AND"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn group_extraction() {
+        assert_eq!(
+            parse_no_fields("(chicken OR beef) -spicy"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("(chicken OR beef)").with_group(vec![
+                        RawQuery::new("chicken"),
+                        RawQuery::new("beef").with_operator(Operator::Or),
+                    ]),
+                    RawQuery::new("spicy").must_not(),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_query("+( title:rice (beans AND \"(or not)\") ) AND -()", &true),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("( title:rice (beans AND \"(or not)\") )")
+                        .with_group(vec![
+                            RawQuery::new("rice").with_field("title"),
+                            RawQuery::new("(beans AND \"(or not)\")").with_group(vec![
+                                RawQuery::new("beans"),
+                                RawQuery::new("(or not)")
+                                    .phrase()
+                                    .with_operator(Operator::And),
+                            ]),
+                        ])
+                        .must(),
+                    RawQuery::new("()")
+                        .with_group(Vec::new())
+                        .must_not()
+                        .with_operator(Operator::And),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn unbalanced_groups_are_terms() {
+        assert_eq!(
+            parse_no_fields("(chicken OR beef"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("(chicken"),
+                    RawQuery::new("beef").with_operator(Operator::Or),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_no_fields("(a) b)"),
+            Ok(("", vec![RawQuery::new("(a)"), RawQuery::new("b)")]))
+        );
+
+        assert_eq!(
+            parse_no_fields("f(x)"),
+            Ok(("", vec![RawQuery::new("f(x)")]))
+        );
+
+        let deep = format!("{}a{}", "(".repeat(100), ")".repeat(100));
+        assert_eq!(
+            parse_no_fields(deep.as_str()),
+            Ok(("", vec![RawQuery::new(deep.as_str())]))
+        );
+
+        let unbalanced = "( ".repeat(10_000);
+        assert_eq!(
+            Ok(10_000),
+            parse_no_fields(unbalanced.as_str()).map(|(_, parsed)| parsed.len())
+        );
+    }
+
+    #[test]
+    fn prefix_extraction() {
+        assert_eq!(
+            parse_query("choco* -title:pie* (cake*) * a*b \"not*\"", &true),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("choco").prefix(),
+                    RawQuery::new("pie").prefix().with_field("title").must_not(),
+                    RawQuery::new("(cake*)").with_group(vec![RawQuery::new("cake").prefix()]),
+                    RawQuery::new("*"),
+                    RawQuery::new("a*b"),
+                    RawQuery::new("not*").phrase(),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn boost_extraction() {
+        assert_eq!(
+            parse_query(
+                "bacon^2 title:eggs^0.5 +choco*^3 \"ham and eggs\"^1.5 (a b)^2 [1 TO 2]^4",
+                &true
+            ),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("bacon").with_boost(2.0),
+                    RawQuery::new("eggs").with_boost(0.5).with_field("title"),
+                    RawQuery::new("choco").prefix().with_boost(3.0).must(),
+                    RawQuery::new("ham and eggs").phrase().with_boost(1.5),
+                    RawQuery::new("(a b)")
+                        .with_group(vec![RawQuery::new("a"), RawQuery::new("b")])
+                        .with_boost(2.0),
+                    RawQuery::new("[1 TO 2]")
+                        .with_range(Some("1"), Some("2"))
+                        .with_boost(4.0),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn malformed_boosts_are_ignored() {
+        assert_eq!(
+            parse_no_fields("bacon^ ^2 a^b c^-1 d^1.2.3 e^inf f^2^3"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("bacon^"),
+                    RawQuery::new("^2"),
+                    RawQuery::new("a^b"),
+                    RawQuery::new("c^-1"),
+                    RawQuery::new("d^1.2.3"),
+                    RawQuery::new("e^inf"),
+                    RawQuery::new("f^2").with_boost(3.0),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_no_fields("\"ham\"^x"),
+            Ok(("", vec![RawQuery::new("ham").phrase(), RawQuery::new("^x")]))
+        );
+    }
+
+    #[test]
+    fn fuzzy_extraction() {
+        assert_eq!(
+            parse_query(
+                "bananna~1 title:aple~ +cake~0 pie~7^2 ~ ~1 a~b \"c d\"~1",
+                &true
+            ),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("bananna").fuzzy(1),
+                    RawQuery::new("aple").fuzzy(1).with_field("title"),
+                    RawQuery::new("cake").fuzzy(0).must(),
+                    RawQuery::new("pie").fuzzy(2).with_boost(2.0),
+                    RawQuery::new("~"),
+                    RawQuery::new("~1"),
+                    RawQuery::new("a~b"),
+                    RawQuery::new("c d").phrase(),
+                    RawQuery::new("~1"),
+                ]
+            ))
+        );
+    }
+
     use quickcheck::QuickCheck;
 
     #[test]
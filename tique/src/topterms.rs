@@ -63,16 +63,37 @@
 //! # }
 //!```
 //!
-use std::{collections::HashMap, str};
+//! Common words can be skipped without writing an acceptor via
+//! `TopTerms::with_stopwords` (or `with_english_stopwords`, when the
+//! `stopwords` feature is enabled).
+//!
+//! Pairs of adjacent words, like "peanut butter", can also be picked
+//! as keywords via `TopTerms::with_bigrams`.
+//!
+//! ## Parallel Extraction
+//!
+//! When the `parallel` feature is enabled, `par_extract_filtered` and
+//! `par_extract_filtered_from_doc` are available: they extract the
+//! keywords of each field concurrently (via rayon) and yield the very
+//! same results as their sequential counterparts.
+//!
+use std::{
+    collections::{HashMap, HashSet},
+    str,
+};
 
 use tantivy::{
-    query::{BooleanQuery, BoostQuery, Occur, Query, TermQuery},
+    collector::Count,
+    query::{BooleanQuery, BoostQuery, Occur, PhraseQuery, Query, TermQuery},
     schema::{Field, FieldType, IndexRecordOption, Schema},
     tokenizer::TextAnalyzer,
     DocAddress, DocSet, Index, IndexReader, Postings, Result, Searcher, Term,
 };
 
-use crate::conditional_collector::topk::{DescendingTopK, TopK};
+use crate::{
+    conditional_collector::topk::{DescendingTopK, TopK},
+    MinShouldMatchQuery,
+};
 
 // Source: Copy-pasta from tantivy::query::bm25::idf
 fn idf(doc_freq: u64, doc_count: u64) -> f32 {
@@ -84,6 +105,11 @@ fn idf(doc_freq: u64, doc_count: u64) -> f32 {
 pub struct TopTerms {
     reader: IndexReader,
     field_tokenizers: Vec<(Field, TextAnalyzer)>,
+    stopwords: HashSet<String>,
+    field_boosts: HashMap<Field, f32>,
+    positional_fields: HashSet<Field>,
+    bigrams: bool,
+    mixed_tokenizers: bool,
 }
 
 /// Allows tuning the algorithm to pick the top keywords
@@ -97,6 +123,9 @@ pub trait KeywordAcceptor {
     /// * doc_freq: Document frequency: How many documents in the
     ///       index contain this term
     /// * num_docs: How many documents are in the index in total
+    ///
+    /// When bigrams are enabled, a bigram is only picked if both of
+    /// its terms are accepted (given the bigram's frequencies).
     fn accept(&self, term: &Term, tf: u32, doc_freq: u64, num_docs: u64) -> bool;
 }
 
@@ -123,13 +152,31 @@ impl TopTerms {
     ///
     /// Will yield an error if the provided fields are unknown or if they
     /// are not `tantivy::schema::TEXT`
+    ///
+    /// # Mixing Tokenizers
+    ///
+    /// Keywords from every field compete for the same top spots, so
+    /// when the fields use different tokenizers (say: one stems words
+    /// and the other doesn't) the scores may not be comparable and
+    /// the results won't make much sense. Check `has_mixed_tokenizers`
+    /// and prefer the `*_per_field` extraction methods in this case.
     pub fn new(index: &Index, fields: Vec<Field>) -> Result<Self> {
         let mut field_tokenizers = Vec::new();
+        let mut positional_fields = HashSet::new();
+        let mut tokenizer_names = HashSet::new();
 
         for field in fields {
             if field_is_valid(&index.schema(), field) {
                 let tok = index.tokenizer_for_field(field)?;
                 field_tokenizers.push((field, tok));
+
+                if let Some(name) = tokenizer_name(&index.schema(), field) {
+                    tokenizer_names.insert(name);
+                }
+
+                if field_has_positions(&index.schema(), field) {
+                    positional_fields.insert(field);
+                }
             } else {
                 let msg = format!(
                     "Field '{}' is not a text field with frequencies (TEXT)",
@@ -142,9 +189,65 @@ impl TopTerms {
         Ok(Self {
             reader: index.reader()?,
             field_tokenizers,
+            stopwords: HashSet::new(),
+            field_boosts: HashMap::new(),
+            positional_fields,
+            bigrams: false,
+            mixed_tokenizers: tokenizer_names.len() > 1,
         })
     }
 
+    /// Tells wether the fields given to `new` use different tokenizers
+    pub fn has_mixed_tokenizers(&self) -> bool {
+        self.mixed_tokenizers
+    }
+
+    /// Never picks any of the given words as keywords, regardless of
+    /// what the `KeywordAcceptor` in use says.
+    ///
+    /// Words are compared to the text of the terms as they are in the
+    /// index, so they should be normalized the same way your fields
+    /// are (usually: lowercased).
+    pub fn with_stopwords(mut self, stopwords: HashSet<String>) -> Self {
+        self.stopwords = stopwords;
+        self
+    }
+
+    /// Multiplies the score of every term picked from the given field
+    /// by `boost`, making them more (or less) likely to be keywords.
+    ///
+    /// Fields have a boost of `1.0` by default.
+    pub fn with_field_boost(mut self, field: Field, boost: f32) -> Self {
+        self.field_boosts.insert(field, boost);
+        self
+    }
+
+    /// Also considers pairs of adjacent terms (e.g.: "peanut butter")
+    /// as keywords. Bigram keywords become a `PhraseQuery` when using
+    /// `Keywords::into_query` and friends.
+    ///
+    /// Only applies to fields indexed with positions; Their document
+    /// frequency is computed by searching for the phrase, so this
+    /// makes extraction considerably more expensive.
+    pub fn with_bigrams(mut self) -> Self {
+        self.bigrams = true;
+        self
+    }
+
+    fn field_options(&self, field: Field) -> FieldOptions {
+        FieldOptions {
+            field,
+            boost: self.field_boosts.get(&field).copied().unwrap_or(1.0),
+            bigrams: self.bigrams && self.positional_fields.contains(&field),
+        }
+    }
+
+    /// Same as `with_stopwords`, using `ENGLISH_STOPWORDS`
+    #[cfg(feature = "stopwords")]
+    pub fn with_english_stopwords(self) -> Self {
+        self.with_stopwords(ENGLISH_STOPWORDS.iter().map(|w| String::from(*w)).collect())
+    }
+
     /// Extracts the `limit` most relevant terms from the input
     pub fn extract(&self, limit: usize, input: &str) -> Keywords {
         self.extract_filtered(limit, input, &())
@@ -155,6 +258,13 @@ impl TopTerms {
         self.extract_filtered_from_doc(limit, addr, &())
     }
 
+    /// Extracts the `limit` most relevant terms from a set of indexed
+    /// documents, as if they were a single one: terms that appear in
+    /// many of them are ranked higher
+    pub fn extract_from_docs(&self, limit: usize, addrs: &[DocAddress]) -> Keywords {
+        self.extract_filtered_from_docs(limit, addrs, &())
+    }
+
     /// Same as `extract`, but with support inspect/filter the terms as
     /// they are being picked.
     pub fn extract_filtered<F: KeywordAcceptor>(
@@ -164,22 +274,22 @@ impl TopTerms {
         acceptor: &F,
     ) -> Keywords {
         let searcher = self.reader.searcher();
-        let num_docs = searcher.num_docs();
-
+        let acceptor = &SkipStopwords(&self.stopwords, acceptor);
         let mut keywords = DescendingTopK::new(limit);
 
         for (field, tokenizer) in &self.field_tokenizers {
-            for (term, tf) in termfreq(&input, *field, tokenizer) {
-                let doc_freq = searcher.doc_freq(&term).unwrap_or(0);
-
-                if doc_freq > 0 && acceptor.accept(&term, tf, doc_freq, num_docs) {
-                    let score = tf as f32 * idf(doc_freq, num_docs);
-                    keywords.visit(term, score);
-                }
-            }
+            let options = self.field_options(*field);
+            visit_input_keywords(
+                &mut keywords,
+                &searcher,
+                &options,
+                tokenizer,
+                input,
+                acceptor,
+            );
         }
 
-        keywords.into()
+        Keywords::from_topk(keywords)
     }
 
     /// Same as `extract_from_doc`, but with support inspect/filter the
@@ -191,34 +301,424 @@ impl TopTerms {
         acceptor: &F,
     ) -> Keywords {
         let searcher = self.reader.searcher();
-        let num_docs = searcher.num_docs();
+        let acceptor = &SkipStopwords(&self.stopwords, acceptor);
+        let mut keywords = DescendingTopK::new(limit);
+
+        for (field, _tokenizer) in &self.field_tokenizers {
+            let options = self.field_options(*field);
+            visit_doc_keywords(&mut keywords, &searcher, &options, &[addr], acceptor);
+        }
+
+        Keywords::from_topk(keywords)
+    }
 
+    /// Same as `extract_from_docs`, but with support inspect/filter the
+    /// terms as they are being picked.
+    pub fn extract_filtered_from_docs<F: KeywordAcceptor>(
+        &self,
+        limit: usize,
+        addrs: &[DocAddress],
+        acceptor: &F,
+    ) -> Keywords {
+        let searcher = self.reader.searcher();
+        let acceptor = &SkipStopwords(&self.stopwords, acceptor);
         let mut keywords = DescendingTopK::new(limit);
 
         for (field, _tokenizer) in &self.field_tokenizers {
-            let _ = termfreq_for_doc(&searcher, *field, addr, |term, term_freq| {
-                let doc_freq = searcher.doc_freq(&term).unwrap_or(0);
-                if acceptor.accept(&term, term_freq, doc_freq, num_docs) {
-                    let score = term_freq as f32 * idf(doc_freq, num_docs);
-                    keywords.visit(term, score);
+            let options = self.field_options(*field);
+            visit_doc_keywords(&mut keywords, &searcher, &options, addrs, acceptor);
+        }
+
+        Keywords::from_topk(keywords)
+    }
+
+    /// Extracts the `limit` most relevant terms from the input for
+    /// each field separately
+    pub fn extract_per_field(&self, limit: usize, input: &str) -> HashMap<Field, Keywords> {
+        self.extract_filtered_per_field(limit, input, &())
+    }
+
+    /// Extracts the `limit` most relevant terms from an indexed
+    /// document for each field separately
+    pub fn extract_from_doc_per_field(
+        &self,
+        limit: usize,
+        addr: DocAddress,
+    ) -> HashMap<Field, Keywords> {
+        self.extract_filtered_from_doc_per_field(limit, addr, &())
+    }
+
+    /// Same as `extract_per_field`, but with support inspect/filter the
+    /// terms as they are being picked.
+    pub fn extract_filtered_per_field<F: KeywordAcceptor>(
+        &self,
+        limit: usize,
+        input: &str,
+        acceptor: &F,
+    ) -> HashMap<Field, Keywords> {
+        let searcher = self.reader.searcher();
+        let acceptor = &SkipStopwords(&self.stopwords, acceptor);
+
+        self.field_tokenizers
+            .iter()
+            .map(|(field, tokenizer)| {
+                let mut keywords = DescendingTopK::new(limit);
+                let options = self.field_options(*field);
+                visit_input_keywords(
+                    &mut keywords,
+                    &searcher,
+                    &options,
+                    tokenizer,
+                    input,
+                    acceptor,
+                );
+                (*field, Keywords::from_topk(keywords))
+            })
+            .collect()
+    }
+
+    /// Same as `extract_from_doc_per_field`, but with support
+    /// inspect/filter the terms as they are being picked.
+    pub fn extract_filtered_from_doc_per_field<F: KeywordAcceptor>(
+        &self,
+        limit: usize,
+        addr: DocAddress,
+        acceptor: &F,
+    ) -> HashMap<Field, Keywords> {
+        let searcher = self.reader.searcher();
+        let acceptor = &SkipStopwords(&self.stopwords, acceptor);
+
+        self.field_tokenizers
+            .iter()
+            .map(|(field, _tokenizer)| {
+                let mut keywords = DescendingTopK::new(limit);
+                let options = self.field_options(*field);
+                visit_doc_keywords(&mut keywords, &searcher, &options, &[addr], acceptor);
+                (*field, Keywords::from_topk(keywords))
+            })
+            .collect()
+    }
+
+    /// Same as `extract_filtered`, but extracts the keywords of each
+    /// field concurrently.
+    ///
+    /// The result is exactly the same as the sequential version's,
+    /// regardless of thread scheduling.
+    #[cfg(feature = "parallel")]
+    pub fn par_extract_filtered<F: KeywordAcceptor + Sync>(
+        &self,
+        limit: usize,
+        input: &str,
+        acceptor: &F,
+    ) -> Keywords {
+        let searcher = self.reader.searcher();
+        let acceptor = &SkipStopwords(&self.stopwords, acceptor);
+
+        self.par_merge(limit, |field, tokenizer| {
+            let mut keywords = DescendingTopK::new(limit);
+            let options = self.field_options(field);
+            visit_input_keywords(
+                &mut keywords,
+                &searcher,
+                &options,
+                tokenizer,
+                input,
+                acceptor,
+            );
+            keywords
+        })
+    }
+
+    /// Same as `extract_filtered_from_doc`, but extracts the keywords
+    /// of each field concurrently.
+    ///
+    /// The result is exactly the same as the sequential version's,
+    /// regardless of thread scheduling.
+    #[cfg(feature = "parallel")]
+    pub fn par_extract_filtered_from_doc<F: KeywordAcceptor + Sync>(
+        &self,
+        limit: usize,
+        addr: DocAddress,
+        acceptor: &F,
+    ) -> Keywords {
+        let searcher = self.reader.searcher();
+        let acceptor = &SkipStopwords(&self.stopwords, acceptor);
+
+        self.par_merge(limit, |field, _tokenizer| {
+            let mut keywords = DescendingTopK::new(limit);
+            let options = self.field_options(field);
+            visit_doc_keywords(&mut keywords, &searcher, &options, &[addr], acceptor);
+            keywords
+        })
+    }
+
+    #[cfg(feature = "parallel")]
+    fn par_merge<E>(&self, limit: usize, extract: E) -> Keywords
+    where
+        E: Fn(Field, &TextAnalyzer) -> KeywordTopK + Sync,
+    {
+        use rayon::prelude::*;
+
+        let per_field = self
+            .field_tokenizers
+            .par_iter()
+            .map(|(field, tokenizer)| extract(*field, tokenizer).into_vec())
+            .collect::<Vec<_>>();
+
+        // The top-k ordering is total (ties break by keyword), so the
+        // visiting order doesn't affect the result
+        let mut keywords = DescendingTopK::new(limit);
+        for (keyword, score) in per_field.into_iter().flatten() {
+            keywords.visit(keyword, score);
+        }
+
+        Keywords::from_topk(keywords)
+    }
+}
+
+struct SkipStopwords<'a, F>(&'a HashSet<String>, &'a F);
+
+impl<'a, F: KeywordAcceptor> KeywordAcceptor for SkipStopwords<'a, F> {
+    fn accept(&self, term: &Term, tf: u32, doc_freq: u64, num_docs: u64) -> bool {
+        !self.0.contains(term.text()) && self.1.accept(term, tf, doc_freq, num_docs)
+    }
+}
+
+type KeywordTopK = DescendingTopK<KeywordScore, Keyword>;
+
+// The score of a keyword, carrying the details used to compute it
+struct KeywordScore {
+    score: f32,
+    tf: u32,
+    doc_freq: u64,
+    num_docs: u64,
+}
+
+impl PartialOrd for KeywordScore {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.score.partial_cmp(&other.score)
+    }
+}
+
+impl PartialEq for KeywordScore {
+    fn eq(&self, other: &Self) -> bool {
+        self.score == other.score
+    }
+}
+
+struct FieldOptions {
+    field: Field,
+    boost: f32,
+    bigrams: bool,
+}
+
+fn visit_keyword<F: KeywordAcceptor>(
+    keywords: &mut KeywordTopK,
+    keyword: Keyword,
+    tf: u32,
+    doc_freq: u64,
+    num_docs: u64,
+    boost: f32,
+    acceptor: &F,
+) {
+    if keyword
+        .terms()
+        .iter()
+        .all(|term| acceptor.accept(term, tf, doc_freq, num_docs))
+    {
+        let score = boost * tf as f32 * idf(doc_freq, num_docs);
+        keywords.visit(
+            keyword,
+            KeywordScore {
+                score,
+                tf,
+                doc_freq,
+                num_docs,
+            },
+        );
+    }
+}
+
+fn keyword_doc_freq(searcher: &Searcher, keyword: &Keyword) -> u64 {
+    match keyword {
+        Keyword::Term(term) => searcher.doc_freq(term).unwrap_or(0),
+        Keyword::Bigram(terms) => searcher
+            .search(&PhraseQuery::new(terms.to_vec()), &Count)
+            .map_or(0, |count| count as u64),
+    }
+}
+
+fn visit_input_keywords<F: KeywordAcceptor>(
+    keywords: &mut KeywordTopK,
+    searcher: &Searcher,
+    options: &FieldOptions,
+    tokenizer: &TextAnalyzer,
+    input: &str,
+    acceptor: &F,
+) {
+    let num_docs = searcher.num_docs();
+
+    let mut freqs = termfreq(input, options.field, tokenizer)
+        .into_iter()
+        .map(|(term, tf)| (Keyword::Term(term), tf))
+        .collect::<Vec<_>>();
+
+    if options.bigrams {
+        freqs.extend(bigramfreq(input, options.field, tokenizer));
+    }
+
+    for (keyword, tf) in freqs {
+        let doc_freq = keyword_doc_freq(searcher, &keyword);
+
+        if doc_freq > 0 {
+            visit_keyword(
+                keywords,
+                keyword,
+                tf,
+                doc_freq,
+                num_docs,
+                options.boost,
+                acceptor,
+            );
+        }
+    }
+}
+
+fn visit_doc_keywords<F: KeywordAcceptor>(
+    keywords: &mut KeywordTopK,
+    searcher: &Searcher,
+    options: &FieldOptions,
+    addrs: &[DocAddress],
+    acceptor: &F,
+) {
+    let num_docs = searcher.num_docs();
+    let mut freqs = HashMap::new();
+
+    for addr in addrs {
+        let _ = termfreq_for_doc(searcher, options.field, *addr, |term, term_freq| {
+            *freqs.entry(Keyword::Term(term)).or_insert(0) += term_freq;
+        });
+
+        if options.bigrams {
+            if let Ok(bigrams) = bigramfreq_for_doc(searcher, options.field, *addr) {
+                for (keyword, tf) in bigrams {
+                    *freqs.entry(keyword).or_insert(0) += tf;
                 }
-            });
+            }
         }
+    }
 
-        keywords.into()
+    for (keyword, tf) in freqs {
+        let doc_freq = keyword_doc_freq(searcher, &keyword);
+        visit_keyword(
+            keywords,
+            keyword,
+            tf,
+            doc_freq,
+            num_docs,
+            options.boost,
+            acceptor,
+        );
     }
 }
 
+/// A small list of common english words, handy to use with
+/// `TopTerms::with_stopwords`. Same as Lucene's default.
+#[cfg(feature = "stopwords")]
+pub const ENGLISH_STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
 /// Keywords is a collection of Term objects found via TopTerms
 #[derive(Clone)]
-pub struct Keywords(Vec<(Term, f32)>);
+pub struct Keywords(Vec<KeywordStat>);
+
+/// A keyword picked by TopTerms
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Keyword {
+    /// A single term
+    Term(Term),
+    /// Two terms that appear next to each other, in this order.
+    /// Only picked when using `TopTerms::with_bigrams`
+    Bigram([Term; 2]),
+}
+
+impl Keyword {
+    /// The terms that make this keyword
+    pub fn terms(&self) -> &[Term] {
+        match self {
+            Keyword::Term(term) => std::slice::from_ref(term),
+            Keyword::Bigram(terms) => terms,
+        }
+    }
+
+    /// Converts into a query that matches this keyword: a `TermQuery`
+    /// for single terms and a `PhraseQuery` for bigrams
+    pub fn into_query(self) -> Box<dyn Query> {
+        match self {
+            Keyword::Term(term) => Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)),
+            Keyword::Bigram(terms) => Box::new(PhraseQuery::new(terms.to_vec())),
+        }
+    }
+}
+
+/// Details about a keyword picked by TopTerms. Useful to understand
+/// why a term was (or wasn't) picked when tuning the extraction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordStat {
+    /// The keyword
+    pub keyword: Keyword,
+    /// How often the keyword appeared in the input
+    pub tf: u32,
+    /// How many documents in the index contain the keyword
+    pub doc_freq: u64,
+    /// How many documents are in the index in total
+    pub num_docs: u64,
+    /// The final score of the keyword, including field boosts
+    pub score: f32,
+}
 
 impl Keywords {
+    fn from_topk(src: KeywordTopK) -> Self {
+        Self(
+            src.into_sorted_vec()
+                .into_iter()
+                .map(|(keyword, ks)| KeywordStat {
+                    keyword,
+                    tf: ks.tf,
+                    doc_freq: ks.doc_freq,
+                    num_docs: ks.num_docs,
+                    score: ks.score,
+                })
+                .collect(),
+        )
+    }
+
     /// Convert into a Query. It can be used as a way to approximate a
     /// nearest neighbors search, so it's expected that results are
     /// similar to the source used to create this Keywords instance.
     pub fn into_query(self) -> BooleanQuery {
-        BooleanQuery::new_multiterms_query(self.0.into_iter().map(|(term, _score)| term).collect())
+        BooleanQuery::from(
+            self.0
+                .into_iter()
+                .map(|stat| (Occur::Should, stat.keyword.into_query()))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Same as `into_query`, but only matches documents that contain
+    /// at least `min` of the terms, which leads to more strictly
+    /// similar results
+    pub fn into_query_with_min_match(self, min: usize) -> MinShouldMatchQuery {
+        MinShouldMatchQuery::new(
+            self.0
+                .into_iter()
+                .map(|stat| stat.keyword.into_query())
+                .collect(),
+            min,
+        )
     }
 
     /// Same as `into_query`, but with terms boosted by their
@@ -227,23 +727,23 @@ impl Keywords {
     /// The `boost_factor` parameter is useful when building more
     /// complex queries; `1.0` is a good default.
     pub fn into_boosted_query(self, boost_factor: f32) -> BooleanQuery {
-        let max_score = self.0.first().map_or(0.0, |(_term, score)| *score);
+        let max_score = self.0.first().map_or(0.0, |stat| stat.score);
 
         let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
 
-        for (term, score) in self.0 {
-            let boost = boost_factor * (score / max_score);
-            let tq = Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
-            clauses.push((Occur::Should, Box::new(BoostQuery::new(tq, boost))));
+        for stat in self.0 {
+            let boost = boost_factor * (stat.score / max_score);
+            let query = stat.keyword.into_query();
+            clauses.push((Occur::Should, Box::new(BoostQuery::new(query, boost))));
         }
 
         BooleanQuery::from(clauses)
     }
 
     /// Iterates over the terms of this keywords set, more relevant
-    /// terms appear first
+    /// terms appear first. Bigrams yield both of their terms
     pub fn terms(&self) -> impl Iterator<Item = &Term> {
-        self.0.iter().map(|(term, _score)| term)
+        self.0.iter().flat_map(|stat| stat.keyword.terms())
     }
 
     /// How many terms this set contains
@@ -259,14 +759,23 @@ impl Keywords {
     /// Exposes the ordered terms and their scores. Useful if you are
     /// using the keywords for other purposes, like reporting, feeding
     /// into a more complex query, etc.
+    ///
+    /// Bigram keywords are left out: use `into_detailed_vec` to
+    /// access them.
     pub fn into_sorted_vec(self) -> Vec<(Term, f32)> {
         self.0
+            .into_iter()
+            .filter_map(|stat| match stat.keyword {
+                Keyword::Term(term) => Some((term, stat.score)),
+                Keyword::Bigram(_) => None,
+            })
+            .collect()
     }
-}
 
-impl From<DescendingTopK<f32, Term>> for Keywords {
-    fn from(src: DescendingTopK<f32, Term>) -> Self {
-        Self(src.into_sorted_vec())
+    /// Same as `into_sorted_vec`, but with the details of how each
+    /// keyword got its score
+    pub fn into_detailed_vec(self) -> Vec<KeywordStat> {
+        self.0
     }
 }
 
@@ -282,6 +791,80 @@ fn termfreq(input: &str, field: Field, tokenizer: &TextAnalyzer) -> HashMap<Term
     termfreq
 }
 
+fn bigramfreq(input: &str, field: Field, tokenizer: &TextAnalyzer) -> HashMap<Keyword, u32> {
+    let mut bigramfreq = HashMap::new();
+    let mut previous: Option<(usize, Term)> = None;
+
+    let mut stream = tokenizer.token_stream(&input);
+    while let Some(token) = stream.next() {
+        let term = Term::from_field_text(field, &token.text);
+
+        if let Some((position, prev_term)) = previous.take() {
+            if position + 1 == token.position {
+                let bigram = Keyword::Bigram([prev_term, term.clone()]);
+                *bigramfreq.entry(bigram).or_insert(0) += 1;
+            }
+        }
+
+        previous = Some((token.position, term));
+    }
+
+    bigramfreq
+}
+
+fn bigramfreq_for_doc(
+    searcher: &Searcher,
+    field: Field,
+    doc: DocAddress,
+) -> Result<HashMap<Keyword, u32>> {
+    let DocAddress {
+        segment_ord,
+        doc_id,
+    } = doc;
+
+    let reader = searcher.segment_reader(segment_ord);
+    let inverted_index = reader.inverted_index(field)?;
+    let mut termstream = inverted_index.terms().stream()?;
+
+    // Rebuild the (positional) sequence of terms of the document
+    let mut positioned = Vec::new();
+    let mut positions = Vec::new();
+
+    while let Some((bytes, terminfo)) = termstream.next() {
+        if let Ok(text) = str::from_utf8(bytes) {
+            let mut postings = inverted_index
+                .read_postings_from_terminfo(terminfo, IndexRecordOption::WithFreqsAndPositions)?;
+
+            // XXX See termfreq_for_doc
+            if postings.doc() > doc_id {
+                continue;
+            }
+
+            if postings.seek(doc_id) == doc_id {
+                postings.positions(&mut positions);
+                for position in &positions {
+                    positioned.push((*position, Term::from_field_text(field, text)));
+                }
+            }
+        }
+    }
+
+    positioned.sort_unstable_by_key(|(position, _term)| *position);
+
+    let mut bigramfreq = HashMap::new();
+    for pair in positioned.windows(2) {
+        let (pos_a, term_a) = &pair[0];
+        let (pos_b, term_b) = &pair[1];
+
+        if pos_a + 1 == *pos_b {
+            let bigram = Keyword::Bigram([term_a.clone(), term_b.clone()]);
+            *bigramfreq.entry(bigram).or_insert(0) += 1;
+        }
+    }
+
+    Ok(bigramfreq)
+}
+
 fn termfreq_for_doc<F>(
     searcher: &Searcher,
     field: Field,
@@ -321,6 +904,24 @@ where
     Ok(())
 }
 
+fn tokenizer_name(schema: &Schema, field: Field) -> Option<String> {
+    if let FieldType::Str(opts) = schema.get_field_entry(field).field_type() {
+        opts.get_indexing_options()
+            .map(|opts| String::from(opts.tokenizer()))
+    } else {
+        None
+    }
+}
+
+fn field_has_positions(schema: &Schema, field: Field) -> bool {
+    if let FieldType::Str(opts) = schema.get_field_entry(field).field_type() {
+        opts.get_indexing_options()
+            .map_or(false, |opts| opts.index_option().has_positions())
+    } else {
+        false
+    }
+}
+
 fn field_is_valid(schema: &Schema, field: Field) -> bool {
     if let FieldType::Str(opts) = schema.get_field_entry(field).field_type() {
         opts.get_indexing_options()
@@ -336,7 +937,7 @@ mod tests {
 
     use tantivy::{
         doc,
-        schema::{SchemaBuilder, INDEXED, STRING, TEXT},
+        schema::{SchemaBuilder, TextFieldIndexing, TextOptions, INDEXED, STRING, TEXT},
         tokenizer::SimpleTokenizer,
     };
 
@@ -393,6 +994,281 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn stopwords_are_never_keywords() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "the cat and the hat"));
+        writer.add_document(doc!(body => "the dog"));
+        writer.commit()?;
+
+        let words = |kw: Keywords| {
+            let mut words = kw
+                .terms()
+                .map(|term| String::from(term.text()))
+                .collect::<Vec<_>>();
+            words.sort();
+            words
+        };
+
+        let input = "the cat and the dog";
+        let topterms = TopTerms::new(&index, vec![body])?;
+        assert_eq!(
+            vec!["and", "cat", "dog", "the"],
+            words(topterms.extract(10, input))
+        );
+
+        let stopwords = ["the", "and"].iter().map(|w| String::from(*w)).collect();
+        let topterms = topterms.with_stopwords(stopwords);
+
+        assert_eq!(vec!["cat", "dog"], words(topterms.extract(10, input)));
+
+        // Composes with the given acceptor
+        let no_dogs = |term: &Term, _tf, _doc_freq, _num_docs| term.text() != "dog";
+        assert_eq!(
+            vec!["cat"],
+            words(topterms.extract_filtered(10, input, &no_dogs))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn keywords_from_multiple_docs() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "pasta tomato basil"));
+        writer.add_document(doc!(body => "pasta garlic oil"));
+        writer.add_document(doc!(body => "salad lettuce"));
+        writer.add_document(doc!(body => "soup carrot"));
+        writer.commit()?;
+
+        let addr = |doc_id| DocAddress {
+            segment_ord: 0,
+            doc_id,
+        };
+
+        let topterms = TopTerms::new(&index, vec![body])?;
+        let keywords = topterms.extract_from_docs(1, &[addr(0), addr(1)]);
+
+        assert_eq!(
+            vec!["pasta"],
+            keywords.terms().map(|t| t.text()).collect::<Vec<_>>()
+        );
+
+        // Same as extracting from a single doc when given just one
+        assert_eq!(
+            topterms.extract_from_doc(3, addr(2)).into_sorted_vec(),
+            topterms.extract_from_docs(3, &[addr(2)]).into_sorted_vec()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn field_boosts_affect_ranking() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+
+        let title = builder.add_text_field("title", TEXT);
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(title => "lasagna", body => "oven"));
+        writer.add_document(doc!(title => "salad", body => "bowl"));
+        writer.commit()?;
+
+        let top_field = |topterms: &TopTerms| {
+            topterms
+                .extract(1, "lasagna oven")
+                .terms()
+                .map(|term| term.field())
+                .next()
+        };
+
+        let topterms = TopTerms::new(&index, vec![title, body])?;
+        // Same frequencies everywhere: ties break by the lowest term
+        assert_eq!(Some(title), top_field(&topterms));
+
+        let topterms = topterms.with_field_boost(body, 2.0);
+        assert_eq!(Some(body), top_field(&topterms));
+
+        Ok(())
+    }
+
+    #[test]
+    fn keywords_expose_details() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "rare common"));
+        writer.add_document(doc!(body => "common"));
+        writer.add_document(doc!(body => "common"));
+        writer.commit()?;
+
+        let topterms = TopTerms::new(&index, vec![body])?;
+        let keywords = topterms.extract(2, "common common rare");
+
+        let details = keywords.clone().into_detailed_vec();
+        assert_eq!(2, details.len());
+
+        let rare = &details[0];
+        assert_eq!(
+            Keyword::Term(Term::from_field_text(body, "rare")),
+            rare.keyword
+        );
+        assert_eq!(1, rare.tf);
+        assert_eq!(1, rare.doc_freq);
+        assert_eq!(3, rare.num_docs);
+
+        let common = &details[1];
+        assert_eq!(
+            Keyword::Term(Term::from_field_text(body, "common")),
+            common.keyword
+        );
+        assert_eq!(2, common.tf);
+        assert_eq!(3, common.doc_freq);
+
+        // Same ordering and scores as the plain version
+        assert_eq!(
+            keywords.into_sorted_vec(),
+            details
+                .into_iter()
+                .map(|stat| (stat.keyword.terms()[0].clone(), stat.score))
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn bigram_keywords() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "peanut butter jelly"));
+        writer.add_document(doc!(body => "butter peanut"));
+        writer.add_document(doc!(body => "peanut butter cookies"));
+        writer.add_document(doc!(body => "chocolate cookies"));
+        writer.commit()?;
+
+        let term = |text| Term::from_field_text(body, text);
+        let peanut_butter = Keyword::Bigram([term("peanut"), term("butter")]);
+
+        let find = |keywords: Keywords| {
+            keywords
+                .into_detailed_vec()
+                .into_iter()
+                .find(|stat| stat.keyword == peanut_butter)
+        };
+
+        let topterms = TopTerms::new(&index, vec![body])?;
+        // Disabled by default
+        assert!(find(topterms.extract(10, "peanut butter")).is_none());
+
+        let topterms = topterms.with_bigrams();
+
+        let stat = find(topterms.extract(10, "peanut butter")).expect("bigram is a keyword");
+        assert_eq!(1, stat.tf);
+        // "butter peanut" doesn't count
+        assert_eq!(2, stat.doc_freq);
+
+        let addr = DocAddress {
+            segment_ord: 0,
+            doc_id: 2,
+        };
+        let stat = find(topterms.extract_from_doc(10, addr)).expect("bigram found in doc");
+        assert_eq!(2, stat.doc_freq);
+
+        // Bigrams become phrase queries
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+        let query = Keywords(vec![KeywordStat {
+            keyword: peanut_butter.clone(),
+            tf: 1,
+            doc_freq: 2,
+            num_docs: 4,
+            score: 1.0,
+        }])
+        .into_query();
+        assert_eq!(2, searcher.search(&query, &Count)?);
+
+        // And are left out of the plain vec
+        assert!(topterms
+            .extract(10, "peanut butter")
+            .into_sorted_vec()
+            .iter()
+            .all(|(term, _score)| term.text() != "peanut butter"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn per_field_extraction() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+
+        let body = builder.add_text_field("body", TEXT);
+        let raw = builder.add_text_field(
+            "raw",
+            TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer("raw")
+                    .set_index_option(IndexRecordOption::WithFreqs),
+            ),
+        );
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "Hello World", raw => "Hello World"));
+        writer.add_document(doc!(body => "Goodbye", raw => "Goodbye"));
+        writer.commit()?;
+
+        assert!(!TopTerms::new(&index, vec![body])?.has_mixed_tokenizers());
+
+        let topterms = TopTerms::new(&index, vec![body, raw])?;
+        assert!(topterms.has_mixed_tokenizers());
+
+        let texts = |keywords: &Keywords| {
+            let mut texts = keywords
+                .terms()
+                .map(|term| String::from(term.text()))
+                .collect::<Vec<_>>();
+            texts.sort();
+            texts
+        };
+
+        let per_field = topterms.extract_per_field(10, "Hello World");
+        assert_eq!(vec!["hello", "world"], texts(&per_field[&body]));
+        assert_eq!(vec!["Hello World"], texts(&per_field[&raw]));
+
+        let per_field = topterms.extract_from_doc_per_field(
+            10,
+            DocAddress {
+                segment_ord: 0,
+                doc_id: 1,
+            },
+        );
+        assert_eq!(vec!["goodbye"], texts(&per_field[&body]));
+        assert_eq!(vec!["Goodbye"], texts(&per_field[&raw]));
+
+        Ok(())
+    }
+
     #[test]
     fn text_fields_are_valid() {
         let mut builder = SchemaBuilder::new();
@@ -490,6 +1366,35 @@ mod tests {
 
         assert_word_found("dangerous", holmes_keywords);
 
+        #[cfg(feature = "parallel")]
+        {
+            for doc_id in 0..3 {
+                let addr = DocAddress {
+                    segment_ord: 0,
+                    doc_id,
+                };
+
+                assert_eq!(
+                    topterms
+                        .extract_filtered_from_doc(5, addr, &keyword_filter)
+                        .into_sorted_vec(),
+                    topterms
+                        .par_extract_filtered_from_doc(5, addr, &keyword_filter)
+                        .into_sorted_vec()
+                );
+            }
+
+            let input = "a dog taught me about snarling and a book";
+            assert_eq!(
+                topterms
+                    .extract_filtered(5, input, &keyword_filter)
+                    .into_sorted_vec(),
+                topterms
+                    .par_extract_filtered(5, input, &keyword_filter)
+                    .into_sorted_vec()
+            );
+        }
+
         let groucho_keywords = topterms.extract_filtered_from_doc(
             5,
             DocAddress {
@@ -501,6 +1406,25 @@ mod tests {
 
         let reader = index.reader()?;
         let searcher = reader.searcher();
+
+        // Nothing else has every single keyword
+        let strict_groucho = searcher.search(
+            &groucho_keywords
+                .clone()
+                .into_query_with_min_match(groucho_keywords.len()),
+            &tantivy::collector::TopDocs::with_limit(3),
+        )?;
+        assert_eq!(
+            vec![DocAddress {
+                segment_ord: 0,
+                doc_id: 2
+            }],
+            strict_groucho
+                .into_iter()
+                .map(|(_score, addr)| addr)
+                .collect::<Vec<_>>()
+        );
+
         let similar_to_groucho = searcher.search(
             &groucho_keywords.into_query(),
             &tantivy::collector::TopDocs::with_limit(3),